@@ -1,19 +1,97 @@
 use crate::pyrite_log;
 use crate::resources;
-use rodio::DeviceTrait;
+use rodio::{DeviceTrait, Sample, Source};
 use std::collections::HashMap;
 use std::io::BufReader;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// An audio format, the file extensions it's detected by and whether its decoder is compiled in.
+struct AudioFormat {
+    name: &'static str,
+    extensions: &'static [&'static str],
+    supported: bool,
+}
+
+const AUDIO_FORMATS: [AudioFormat; 4] = [
+    AudioFormat {
+        name: "WAV",
+        extensions: &["wav", "wave"],
+        supported: cfg!(feature = "audio-wav"),
+    },
+    AudioFormat {
+        name: "OGG",
+        extensions: &["ogg", "oga"],
+        supported: cfg!(feature = "audio-ogg"),
+    },
+    AudioFormat {
+        name: "FLAC",
+        extensions: &["flac"],
+        supported: cfg!(feature = "audio-flac"),
+    },
+    AudioFormat {
+        name: "MP3",
+        extensions: &["mp3"],
+        supported: cfg!(feature = "audio-mp3"),
+    },
+];
+
+/// Names of the audio formats this build can decode, such as "WAV" or "OGG".
+pub fn supported_formats() -> Vec<String> {
+    AUDIO_FORMATS
+        .iter()
+        .filter(|format| format.supported)
+        .map(|format| format.name.to_owned())
+        .collect()
+}
+
+fn format_from_extension(track_name: &str) -> Option<&'static AudioFormat> {
+    let extension = std::path::Path::new(track_name)
+        .extension()?
+        .to_str()?
+        .to_lowercase();
+
+    AUDIO_FORMATS
+        .iter()
+        .find(|format| format.extensions.contains(&extension.as_str()))
+}
 
 pub struct AudioServer {
     output_device: Option<rodio::Device>,
-    tracks: HashMap<String, rodio::Sink>,
+    tracks: HashMap<String, Track>,
+}
+
+struct Track {
+    sink: rodio::Sink,
+    // f32 bits, shared with the source on the audio thread so it can be changed while playing.
+    pan: Arc<AtomicU32>,
+    // rodio doesn't report playback position, so it's tracked from when the track was played.
+    // play_start is None while paused or stopped, play_offset is the time played before that.
+    play_start: Option<Instant>,
+    play_offset: Duration,
+    duration: Option<Duration>,
+}
+
+impl Track {
+    fn position(&self) -> Duration {
+        let position = match self.play_start {
+            Some(play_start) => self.play_offset + play_start.elapsed(),
+            None => self.play_offset,
+        };
+
+        match self.duration {
+            Some(duration) => position.min(duration),
+            None => position,
+        }
+    }
 }
 
 impl AudioServer {
     pub fn new() -> Self {
         let output_device = rodio::default_output_device();
 
-        match output_device {
+        match &output_device {
             Some(od) => pyrite_log!(
                 "Audio server started with \"{}\"",
                 od.name().unwrap_or("Unknown".to_string())
@@ -22,34 +100,139 @@ impl AudioServer {
         };
 
         Self {
-            output_device: rodio::default_output_device(),
+            output_device,
             tracks: HashMap::new(),
         }
     }
 
+    /// True if there's an output device to play tracks on.
+    pub fn is_available(&self) -> bool {
+        self.output_device.is_some()
+    }
+
+    /// Reconnect to the current default output device, such as when headphones are plugged in
+    /// after the game started. All tracks are stopped and forgotten.
+    ///
+    /// Returns true if an output device is available.
+    pub fn reinitialize(&mut self) -> bool {
+        self.stop_all(true);
+
+        self.output_device = rodio::default_output_device();
+
+        match &self.output_device {
+            Some(od) => {
+                pyrite_log!(
+                    "Audio server reinitialised with \"{}\"",
+                    od.name().unwrap_or("Unknown".to_string())
+                );
+                true
+            }
+            None => {
+                pyrite_log!("Failed to reinitialise audio, no default audio device");
+                false
+            }
+        }
+    }
+
     pub fn stop(&mut self, track_name: &str) {
-        match self.tracks.get(track_name) {
-            Some(track) => track.stop(),
+        match self.tracks.get_mut(track_name) {
+            Some(track) => {
+                track.sink.stop();
+                track.play_start = None;
+                track.play_offset = Duration::from_secs(0);
+            }
             None => pyrite_log!("Failed to stop track \"{}\": track not found", track_name),
         }
     }
 
-    pub fn stop_all(&mut self) {
-        self.tracks.values().for_each(|track| track.stop());
+    /// Stop the track and forget it, freeing its decoded audio. Unlike a stopped track, its pan
+    /// isn't kept if it's played again.
+    pub fn unload(&mut self, track_name: &str) {
+        match self.tracks.remove(track_name) {
+            Some(track) => track.sink.stop(),
+            None => pyrite_log!("Failed to unload track \"{}\": track not found", track_name),
+        }
     }
 
-    pub fn play(&mut self, track_name: &str, resources: &Box<dyn resources::Provider>) {
-        let output_device = match &self.output_device {
-            Some(od) => od,
-            None => return,
-        };
+    /// Stop every track, when unload is true they're forgotten as well.
+    pub fn stop_all(&mut self, unload: bool) {
+        self.tracks.values_mut().for_each(|track| {
+            track.sink.stop();
+            track.play_start = None;
+            track.play_offset = Duration::from_secs(0);
+        });
+
+        if unload {
+            self.tracks.clear();
+        }
+    }
+
+    pub fn play(&mut self, track_name: &str, resources: &Arc<dyn resources::Provider>) {
+        if self.output_device.is_none() {
+            return;
+        }
 
         // resume the track if it exists and was paused
+        if let Some(track) = self.tracks.get_mut(track_name) {
+            if track.sink.is_paused() {
+                track.sink.play();
+                track.play_start = Some(Instant::now());
+                return;
+            } else if !track.sink.empty() {
+                return;
+            }
+        }
+
+        let track_data = match resources.read_to_bytes(track_name) {
+            Some(td) => td,
+            None => {
+                pyrite_log!("Audio resource not found \"{}\"", track_name);
+                return;
+            }
+        };
+
+        self.start_track(track_name, track_data);
+    }
+
+    /// Play encoded audio data under the given track name, replacing any track with that name.
+    pub fn play_bytes(&mut self, track_name: &str, track_data: Vec<u8>) {
         if let Some(track) = self.tracks.get(track_name) {
-            if track.is_paused() {
-                track.play();
+            track.sink.stop();
+        }
+
+        self.start_track(track_name, track_data);
+    }
+
+    /// Play a track from the beginning, then repeat the section between loop_start and loop_end
+    /// seconds until it's stopped. A loop_end past the end of the track loops to the end.
+    pub fn play_looping_section(
+        &mut self,
+        track_name: &str,
+        loop_start: f64,
+        loop_end: f64,
+        resources: &Arc<dyn resources::Provider>,
+    ) {
+        if self.output_device.is_none() {
+            return;
+        }
+
+        if loop_start < 0. || loop_end <= loop_start {
+            pyrite_log!(
+                "Failed to loop track \"{}\": the loop section {}s to {}s is invalid",
+                track_name,
+                loop_start,
+                loop_end
+            );
+            return;
+        }
+
+        // resume the track if it exists and was paused
+        if let Some(track) = self.tracks.get_mut(track_name) {
+            if track.sink.is_paused() {
+                track.sink.play();
+                track.play_start = Some(Instant::now());
                 return;
-            } else if !track.empty() {
+            } else if !track.sink.empty() {
                 return;
             }
         }
@@ -62,31 +245,385 @@ impl AudioServer {
             }
         };
 
-        let track_source =
-            match rodio::Decoder::new(BufReader::new(std::io::Cursor::new(track_data))) {
-                Ok(ts) => ts,
-                Err(e) => {
-                    pyrite_log!("Failed to decode audio \"{}\": {}", track_name, e);
-                    return;
-                }
-            };
+        let track_source = match decode(track_name, track_data) {
+            Some(ts) => ts,
+            None => return,
+        };
 
-        let track = rodio::Sink::new(output_device);
-        track.append(track_source);
-        self.tracks.insert(track_name.to_owned(), track);
+        let looped_source = LoopedSection::new(track_name, track_source, loop_start, loop_end);
+
+        // a looping track never finishes, so it has no duration to clamp its position to.
+        self.start_source(track_name, looped_source, None);
+    }
+
+    fn start_track(&mut self, track_name: &str, track_data: Vec<u8>) {
+        let track_source = match decode(track_name, track_data) {
+            Some(ts) => ts,
+            None => return,
+        };
+
+        // not every decoder can report the length of the audio
+        let duration = track_source.total_duration();
+
+        self.start_source(track_name, track_source, duration);
+    }
+
+    fn start_source<S>(&mut self, track_name: &str, track_source: S, duration: Option<Duration>)
+    where
+        S: Source<Item = i16> + Send + 'static,
+    {
+        let output_device = match &self.output_device {
+            Some(od) => od,
+            None => return,
+        };
+
+        // replayed tracks keep the pan of the previous instance
+        let pan = match self.tracks.get(track_name) {
+            Some(track) => track.pan.clone(),
+            None => Arc::new(AtomicU32::new(0f32.to_bits())),
+        };
+
+        let sink = rodio::Sink::new(output_device);
+        sink.append(Panned::new(track_source, pan.clone()));
+        self.tracks.insert(
+            track_name.to_owned(),
+            Track {
+                sink,
+                pan,
+                play_start: Some(Instant::now()),
+                play_offset: Duration::from_secs(0),
+                duration,
+            },
+        );
     }
 
     pub fn pause(&mut self, track_name: &str) {
-        match self.tracks.get(track_name) {
-            Some(track) => track.pause(),
+        match self.tracks.get_mut(track_name) {
+            Some(track) => {
+                if let Some(play_start) = track.play_start.take() {
+                    track.play_offset += play_start.elapsed();
+                }
+                track.sink.pause();
+            }
             None => pyrite_log!("Failed to pause track \"{}\": track not found", track_name),
         }
     }
 
     pub fn volume(&mut self, track_name: &str, value: f32) {
         match self.tracks.get(track_name) {
-            Some(track) => track.set_volume(value),
+            Some(track) => track.sink.set_volume(value),
             None => pyrite_log!("Failed to volume track \"{}\": track not found", track_name),
         }
     }
+
+    /// Seconds the track has been playing for, not counting time spent paused.
+    pub fn position(&self, track_name: &str) -> Option<f64> {
+        self.tracks
+            .get(track_name)
+            .map(|track| track.position().as_secs_f64())
+    }
+
+    /// Length of the track in seconds, if the decoder can report it.
+    pub fn duration(&self, track_name: &str) -> Option<f64> {
+        self.tracks
+            .get(track_name)
+            .and_then(|track| track.duration)
+            .map(|duration| duration.as_secs_f64())
+    }
+
+    /// Every track the server holds with whether it's "PLAYING", "PAUSED" or "STOPPED", sorted by
+    /// name. Finished tracks count as stopped.
+    pub fn tracks(&self) -> Vec<(String, String)> {
+        let mut tracks: Vec<(String, String)> = self
+            .tracks
+            .iter()
+            .map(|(track_name, track)| {
+                let status = if track.sink.empty() {
+                    "STOPPED"
+                } else if track.sink.is_paused() {
+                    "PAUSED"
+                } else {
+                    "PLAYING"
+                };
+
+                (track_name.clone(), status.to_owned())
+            })
+            .collect();
+
+        tracks.sort();
+        tracks
+    }
+
+    /// Pan from -1.0 (full left) to 1.0 (full right).
+    pub fn pan(&mut self, track_name: &str, value: f32) {
+        match self.tracks.get(track_name) {
+            Some(track) => track
+                .pan
+                .store(value.max(-1.).min(1.).to_bits(), Ordering::Relaxed),
+            None => pyrite_log!("Failed to pan track \"{}\": track not found", track_name),
+        }
+    }
+}
+
+/// The decoder still guesses the format from the data, the extension is only used to explain why
+/// a track couldn't be decoded. Tracks without a known extension, such as those played from
+/// memory, are decoded as whatever format the data looks like.
+fn decode(
+    track_name: &str,
+    track_data: Vec<u8>,
+) -> Option<rodio::Decoder<BufReader<std::io::Cursor<Vec<u8>>>>> {
+    let format = format_from_extension(track_name);
+
+    if let Some(format) = format {
+        if !format.supported {
+            pyrite_log!(
+                "Failed to decode audio \"{}\": {} isn't supported in this build, supported formats are {}",
+                track_name,
+                format.name,
+                supported_formats().join(", ")
+            );
+            return None;
+        }
+    }
+
+    match rodio::Decoder::new(BufReader::new(std::io::Cursor::new(track_data))) {
+        Ok(ts) => Some(ts),
+        Err(e) => {
+            match format {
+                Some(format) => pyrite_log!(
+                    "Failed to decode audio \"{}\" as {}: {}, the file may be corrupt or misnamed",
+                    track_name,
+                    format.name,
+                    e
+                ),
+                None => pyrite_log!(
+                    "Failed to decode audio \"{}\": {}, supported formats are {}",
+                    track_name,
+                    e,
+                    supported_formats().join(", ")
+                ),
+            }
+            None
+        }
+    }
+}
+
+/// Plays a track from the beginning, then repeats the section between the loop start and end.
+///
+/// The track is decoded as it plays, on the audio thread, and only the samples of the looping
+/// section are kept to jump back to once the loop end is reached.
+struct LoopedSection<I>
+where
+    I: Source<Item = i16>,
+{
+    track_name: String,
+    // None once the loop end, or the end of the track, has been reached.
+    input: Option<I>,
+    channels: u16,
+    sample_rate: u32,
+    // samples decoded so far, loop start and end are sample indices into the whole track.
+    position: usize,
+    loop_start: usize,
+    loop_end: usize,
+    loop_samples: Vec<i16>,
+    loop_position: usize,
+}
+
+impl<I> LoopedSection<I>
+where
+    I: Source<Item = i16>,
+{
+    fn new(track_name: &str, input: I, loop_start: f64, loop_end: f64) -> Self {
+        let channels = input.channels();
+        let sample_rate = input.sample_rate();
+
+        // loop points are kept on frame boundaries so the channels don't get swapped.
+        let frame_index =
+            |seconds: f64| (seconds * sample_rate as f64) as usize * channels as usize;
+
+        Self {
+            track_name: track_name.to_owned(),
+            input: Some(input),
+            channels,
+            sample_rate,
+            position: 0,
+            loop_start: frame_index(loop_start),
+            loop_end: frame_index(loop_end),
+            loop_samples: Vec::new(),
+            loop_position: 0,
+        }
+    }
+
+    /// Stop decoding and start repeating the samples kept from the looping section.
+    fn finish_decoding(&mut self) {
+        self.input = None;
+
+        // a track that ends part way through a frame loops on the last whole frame.
+        let whole_frames_len =
+            self.loop_samples.len() - self.loop_samples.len() % self.channels as usize;
+        self.loop_samples.truncate(whole_frames_len);
+        self.loop_samples.shrink_to_fit();
+
+        if self.loop_samples.is_empty() {
+            pyrite_log!(
+                "Failed to loop track \"{}\": the loop starts after the track ends",
+                self.track_name
+            );
+        }
+    }
+}
+
+impl<I> Iterator for LoopedSection<I>
+where
+    I: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(input) = &mut self.input {
+            let sample = if self.position < self.loop_end {
+                input.next()
+            } else {
+                None
+            };
+
+            match sample {
+                Some(sample) => {
+                    if self.position >= self.loop_start {
+                        self.loop_samples.push(sample);
+                    }
+                    self.position += 1;
+
+                    return Some(sample);
+                }
+                None => self.finish_decoding(),
+            }
+        }
+
+        // the track ends if there's nothing to loop
+        if self.loop_samples.is_empty() {
+            return None;
+        }
+
+        if self.loop_position >= self.loop_samples.len() {
+            self.loop_position = 0;
+        }
+
+        let sample = self.loop_samples[self.loop_position];
+        self.loop_position += 1;
+
+        Some(sample)
+    }
+}
+
+impl<I> Source for LoopedSection<I>
+where
+    I: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Attenuates the left or right channel of a source depending on the pan.
+///
+/// Mono sources are played on both channels so they can be panned, sources with more than two
+/// channels only have their first two channels panned.
+struct Panned<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    pan: Arc<AtomicU32>,
+    current_channel: u16,
+    mono_sample: Option<I::Item>,
+}
+
+impl<I> Panned<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn new(input: I, pan: Arc<AtomicU32>) -> Self {
+        Self {
+            input,
+            pan,
+            current_channel: 0,
+            mono_sample: None,
+        }
+    }
+
+    fn channel_gain(&self, channel: u16) -> f32 {
+        let pan = f32::from_bits(self.pan.load(Ordering::Relaxed));
+
+        match channel {
+            0 => (1. - pan).min(1.),
+            1 => (1. + pan).min(1.),
+            _ => 1.,
+        }
+    }
+}
+
+impl<I> Iterator for Panned<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let sample = if self.input.channels() == 1 {
+            // repeat each mono sample for the left and right channels
+            if self.current_channel == 0 {
+                self.mono_sample = self.input.next();
+            }
+            self.mono_sample?
+        } else {
+            self.input.next()?
+        };
+
+        let gain = self.channel_gain(self.current_channel);
+        self.current_channel = (self.current_channel + 1) % self.channels();
+
+        Some(sample.amplify(gain))
+    }
+}
+
+impl<I> Source for Panned<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        let input_channels = self.input.channels() as usize;
+
+        self.input
+            .current_frame_len()
+            .map(|len| len * self.channels() as usize / input_channels)
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels().max(2)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
 }
@@ -1,12 +1,26 @@
 use super::*;
 use engine::*;
-use pyo3::types::PyDict;
+use pyo3::exceptions;
+use pyo3::types::{PyBytes, PyDict};
 use pyo3::wrap_pyfunction;
+use pyo3::AsPyRef;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
-pub static mut ENGINE_INSTANCE: Option<Engine> = None;
-static mut GAME_DATA: Option<&PyDict> = None;
-static mut CURRENT_DELTA_TIME: f64 = 0.0;
+// The engine and python interpreter are only ever driven from the main thread, so global state
+// is thread local. Each access borrows through a RefCell, so an engine function invoked while the
+// engine is already borrowed panics with a message instead of aliasing the engine.
+thread_local! {
+    static ENGINE_INSTANCE: RefCell<Option<Engine>> = RefCell::new(None);
+    // Owned rather than a borrowed &PyDict, so the dictionary stays alive regardless of which GIL
+    // pool it was created in.
+    static GAME_DATA: RefCell<Option<Py<PyDict>>> = RefCell::new(None);
+    static CURRENT_DELTA_TIME: Cell<f64> = Cell::new(0.0);
+    static STRICT_MODE: Cell<bool> = Cell::new(false);
+    // the items of each window's configuration, reconfigure only replaces the items it's given.
+    static CONFIGURATION: RefCell<HashMap<u32, HashMap<String, PyObject>>> =
+        RefCell::new(HashMap::new());
+}
 
 macro_rules! bind {
     ($module:ident, $func:ident) => {
@@ -16,17 +30,14 @@ macro_rules! bind {
     };
 }
 
+/// Call a method on the engine instance, `engine!(method(args))`.
+///
+/// The engine is only borrowed for the duration of the call, so the result must not borrow from
+/// the engine.
 #[macro_export]
 macro_rules! engine {
-    () => {
-        unsafe {
-            match &mut $crate::binding::ENGINE_INSTANCE {
-                Some(e) => e,
-                None => {
-                    panic!("An engine function was invoked without an available engine instance")
-                }
-            }
-        }
+    ($method:ident($($arg:expr),* $(,)?)) => {
+        $crate::binding::with_engine(|engine| engine.$method($($arg),*))
     };
 }
 
@@ -38,35 +49,184 @@ macro_rules! extract_or {
     };
 }
 
-pub fn set_delta_time(delta_time: f64) {
-    unsafe {
-        CURRENT_DELTA_TIME = delta_time;
+/// Bindings that can fail return the lenient value unless strict mode was enabled with set_strict,
+/// then the error is raised as a python exception instead.
+///
+/// Every binding that returns a fallback value on failure goes through here, and is listed under
+/// set_strict in the readme.
+fn strict_or<T>(lenient: T, error: impl FnOnce() -> PyErr) -> PyResult<T> {
+    if STRICT_MODE.with(|strict| strict.get()) {
+        Err(error())
+    } else {
+        Ok(lenient)
+    }
+}
+
+/// The error for taking the result of a background task that failed or hasn't finished.
+fn unfinished_error(task: &str, handle: u32, finished: bool) -> PyErr {
+    if finished {
+        exceptions::IOError::py_err(format!("{} {} failed", task, handle))
+    } else {
+        exceptions::ValueError::py_err(format!(
+            "{} {} hasn't finished, or was already taken or cancelled",
+            task, handle
+        ))
+    }
+}
+
+fn unknown_handle_error(task: &str, handle: u32) -> PyErr {
+    exceptions::ValueError::py_err(format!(
+        "there's no {} {}, it was already taken or cancelled",
+        task, handle
+    ))
+}
+
+const ENGINE_IN_USE_MESSAGE: &str =
+    "An engine function was invoked while the engine was already in use";
+const NO_ENGINE_MESSAGE: &str =
+    "An engine function was invoked without an available engine instance";
+
+/// Run a function with the engine instance, prefer the engine! macro.
+pub fn with_engine<R>(f: impl FnOnce(&mut Engine) -> R) -> R {
+    ENGINE_INSTANCE.with(|instance| borrow_instance(instance, f))
+}
+
+/// Borrow the instance for the duration of the function, panicking if it's already borrowed or
+/// there isn't one.
+fn borrow_instance<T, R>(instance: &RefCell<Option<T>>, f: impl FnOnce(&mut T) -> R) -> R {
+    let mut instance = instance.try_borrow_mut().expect(ENGINE_IN_USE_MESSAGE);
+
+    match instance.as_mut() {
+        Some(value) => f(value),
+        None => panic!("{}", NO_ENGINE_MESSAGE),
     }
 }
 
+pub fn set_delta_time(delta_time: f64) {
+    CURRENT_DELTA_TIME.with(|current| current.set(delta_time));
+}
+
 pub fn inject_engine(py: Python, engine: Engine) {
     // set engine instance to be called by python module functions.
-    unsafe {
-        ENGINE_INSTANCE = Some(engine);
-        GAME_DATA = Some(PyDict::new(Python::assume_gil_acquired()));
-    }
+    ENGINE_INSTANCE.with(|instance| *instance.borrow_mut() = Some(engine));
+    GAME_DATA.with(|game_data| *game_data.borrow_mut() = Some(PyDict::new(py).into()));
 
     // create python engine module and bind functions
     let engine_module = PyModule::new(py, "pyrite").expect("failed to initialise engine module");
     bind!(engine_module, game_data);
+    bind!(engine_module, get_data);
+    bind!(engine_module, set_data);
     bind!(engine_module, exit);
+    bind!(engine_module, cancel_close);
+    bind!(engine_module, step_once);
+    bind!(engine_module, set_strict);
+    bind!(engine_module, set_event_enabled);
+    bind!(engine_module, reconfigure);
+    bind!(engine_module, app_version);
+    bind!(engine_module, engine_version);
     bind!(engine_module, delta_time);
+    bind!(engine_module, time);
+    bind!(engine_module, frame_timings);
+    bind!(engine_module, renderer_info);
+    bind!(engine_module, tiles_changed_last_frame);
+    bind!(engine_module, upload_region_last_frame);
     bind!(engine_module, mouse_position);
+    bind!(engine_module, mouse_position_pixels);
+    bind!(engine_module, mouse_position_normalized);
+    bind!(engine_module, tile_under_mouse);
     bind!(engine_module, button_down);
+    bind!(engine_module, button_pressed);
+    bind!(engine_module, pressed_buttons);
+    bind!(engine_module, button_released);
+    bind!(engine_module, load_key_map);
+    bind!(engine_module, bind_action);
+    bind!(engine_module, action_down);
+    bind!(engine_module, present);
+    bind!(engine_module, flush);
+    bind!(engine_module, request_redraw);
+    bind!(engine_module, set_continuous_rendering);
+    bind!(engine_module, set_clear_enabled);
+    bind!(engine_module, get_clear_enabled);
+    bind!(engine_module, set_debug_overlay);
     bind!(engine_module, set_viewport);
+    bind!(engine_module, set_zoom);
+    bind!(engine_module, get_zoom);
+    bind!(engine_module, set_camera);
+    bind!(engine_module, get_camera);
+    bind!(engine_module, set_fullscreen);
+    bind!(engine_module, list_monitors);
+    bind!(engine_module, set_monitor);
+    bind!(engine_module, create_window);
+    bind!(engine_module, set_active_window);
+    bind!(engine_module, get_active_window);
+    bind!(engine_module, close_window);
+    bind!(engine_module, get_window_position);
+    bind!(engine_module, set_window_position);
+    bind!(engine_module, center_window);
+    bind!(engine_module, minimize_window);
+    bind!(engine_module, maximize_window);
+    bind!(engine_module, restore_window);
+    bind!(engine_module, set_resizable);
+    bind!(engine_module, set_cursor_icon);
+    bind!(engine_module, set_palette);
+    bind!(engine_module, clear_palette);
+    bind!(engine_module, set_tint);
+    bind!(engine_module, tileset_info);
+    bind!(engine_module, tileset_pixels);
+    bind!(engine_module, require_tiles);
     bind!(engine_module, set_tile);
+    bind!(engine_module, set_front_tile);
+    bind!(engine_module, set_back_tile);
+    bind!(engine_module, set_tile_raw);
+    bind!(engine_module, tint_tile);
+    bind!(engine_module, get_tile_color);
+    bind!(engine_module, draw_particles);
+    bind!(engine_module, upload_pixels);
+    bind!(engine_module, clear_pixels);
+    bind!(engine_module, draw_debug_text);
     bind!(engine_module, clear);
+    bind!(engine_module, clear_viewport);
+    bind!(engine_module, clear_layer);
+    bind!(engine_module, fill_rect);
+    bind!(engine_module, clear_rect);
+    bind!(engine_module, draw_text);
+    bind!(engine_module, text_width);
+    bind!(engine_module, define_animation);
+    bind!(engine_module, set_animated_tile);
     bind!(engine_module, resource_read);
+    bind!(engine_module, resource_read_bytes);
     bind!(engine_module, resource_exists);
+    bind!(engine_module, require_resources);
+    bind!(engine_module, load_async);
+    bind!(engine_module, load_ready);
+    bind!(engine_module, load_take);
+    bind!(engine_module, load_cancel);
+    bind!(engine_module, socket_open);
+    bind!(engine_module, socket_state);
+    bind!(engine_module, socket_send);
+    bind!(engine_module, socket_recv);
+    bind!(engine_module, socket_close);
+    #[cfg(feature = "network")]
+    {
+        bind!(engine_module, http_get_async);
+        bind!(engine_module, http_ready);
+        bind!(engine_module, http_take);
+        bind!(engine_module, http_cancel);
+    }
     bind!(engine_module, play_audio);
+    bind!(engine_module, play_audio_bytes);
+    bind!(engine_module, play_looping_section);
     bind!(engine_module, stop_audio);
+    bind!(engine_module, unload_audio);
     bind!(engine_module, pause_audio);
     bind!(engine_module, volume_audio);
+    bind!(engine_module, pan_audio);
+    bind!(engine_module, audio_position);
+    bind!(engine_module, audio_duration);
+    bind!(engine_module, audio_tracks);
+    bind!(engine_module, audio_formats_supported);
+    bind!(engine_module, reinitialize_audio);
+    bind!(engine_module, audio_available);
 
     // Inject the engine module into the python importer
     py.import("sys")
@@ -84,33 +244,70 @@ pub fn inject_engine(py: Python, engine: Engine) {
 }
 
 pub fn destroy_engine() {
-    engine!().clean();
+    engine!(clean());
 
-    unsafe {
-        ENGINE_INSTANCE = None;
-        GAME_DATA = None;
-    }
+    ENGINE_INSTANCE.with(|instance| *instance.borrow_mut() = None);
+    GAME_DATA.with(|game_data| *game_data.borrow_mut() = None);
 }
 
-pub fn raise_event(py: Python, entry_module: &PyModule, event: &Event) {
+/// Raise an event that's happening now.
+///
+/// Returns the handler's return value if it was a boolean.
+pub fn raise_event(py: Python, entry_module: &PyModule, event: &Event) -> Option<bool> {
+    let timestamp = engine!(time_since_start());
+    raise_event_at(py, entry_module, event, timestamp)
+}
+
+/// Raise an event that arrived at the given time, in seconds since the engine started.
+///
+/// Returns the handler's return value if it was a boolean.
+pub fn raise_event_at(
+    py: Python,
+    entry_module: &PyModule,
+    event: &Event,
+    timestamp: f64,
+) -> Option<bool> {
     let event_type = event.type_str();
-    let event_data = event_data_into_pyobject(&event);
+    let event_data = event_data_into_pyobject(&event, timestamp);
 
     let event_result = entry_module.call1("__event__", (event_type, event_data));
 
     match event_result {
-        Ok(_) => (),
+        // handlers that don't return anything give None, which isn't a boolean.
+        Ok(result) => result.extract::<bool>().ok(),
         Err(e) => {
-            // can probably capture game errors here
             pyrite_log!(
-                "An error occurred in the game module while processing a {} event:",
-                event_type
+                "An error occurred in the game module while processing a {} event:\n{}",
+                event_type,
+                format_error(py, e)
             );
-            e.print(py);
+            None
         }
     }
 }
 
+/// Format a python error with its traceback, the same way python prints it, so it can be logged.
+pub fn format_error(py: Python, error: PyErr) -> String {
+    let sys = py.import("sys").expect("failed to import sys");
+    let buffer = py
+        .import("io")
+        .and_then(|io| io.call0("StringIO"))
+        .expect("failed to create python error buffer");
+    let stderr = sys.getattr("stderr").expect("python has no stderr");
+
+    // python only prints tracebacks to stderr, so it's swapped for the buffer while printing.
+    sys.setattr("stderr", buffer)
+        .expect("failed to redirect python stderr");
+    error.print(py);
+    sys.setattr("stderr", stderr)
+        .expect("failed to restore python stderr");
+
+    buffer
+        .call_method0("getvalue")
+        .and_then(|message| message.extract())
+        .expect("failed to read python error buffer")
+}
+
 pub fn get_configuration(entry_module: &PyModule) -> Option<Config> {
     let py_config = entry_module.call0("__config__").ok()?;
 
@@ -118,6 +315,19 @@ pub fn get_configuration(entry_module: &PyModule) -> Option<Config> {
         .extract()
         .expect("Type error when reading the configuration structure");
 
+    let engine_config = config_from_dict(&config);
+    CONFIGURATION.with(|configuration| {
+        configuration
+            .borrow_mut()
+            .insert(platform::MAIN_WINDOW, config)
+    });
+
+    Some(engine_config)
+}
+
+/// Build a configuration from a dictionary in the same structure returned by __config__,
+/// missing items take their default values.
+fn config_from_dict(config: &HashMap<String, PyObject>) -> Config {
     let py = unsafe { Python::assume_gil_acquired() };
 
     let application_name = extract_or!(
@@ -139,31 +349,138 @@ pub fn get_configuration(entry_module: &PyModule) -> Option<Config> {
     let viewport_scale = extract_or!(py, config, "viewport_scale", i32, 2);
     let viewport_width = extract_or!(py, config, "viewport_width", i32, 10);
     let viewport_height = extract_or!(py, config, "viewport_height", i32, 10);
+    // the scene is limited to what the graphics card can hold when it's created.
+    let scene_width = extract_or!(py, config, "scene_width", i32, viewport_width);
+    let scene_height = extract_or!(py, config, "scene_height", i32, viewport_height);
 
     let tileset_width = extract_or!(py, config, "tileset_width", u32, 3);
     let tileset_height = extract_or!(py, config, "tileset_height", u32, 3);
     let tileset_path = extract_or!(py, config, "tileset_path", String, "default.png".to_owned());
     let tile_names = extract_or!(py, config, "tile_names", Vec<String>, Vec::new());
+    let tile_animations = extract_or!(
+        py,
+        config,
+        "tile_animations",
+        HashMap<String, (Vec<String>, f64)>,
+        HashMap::new()
+    );
+    let legacy_tile_detection = extract_or!(py, config, "legacy_tile_detection", bool, false);
+    let tile_filter = extract_or!(py, config, "tile_filter", String, "nearest".to_owned());
+    let strict_tile_names = extract_or!(py, config, "strict_tile_names", bool, false);
+    let glyph_names = extract_or!(
+        py,
+        config,
+        "glyph_names",
+        HashMap<String, String>,
+        HashMap::new()
+    );
+    let msaa_samples = extract_or!(py, config, "msaa_samples", u16, 0);
+    let gl_versions = extract_or!(
+        py,
+        config,
+        "gl_versions",
+        Vec<String>,
+        vec!["GL 3.3".to_owned()]
+    );
+    let fullscreen = extract_or!(py, config, "fullscreen", bool, false);
+    let monitor = extract_or!(py, config, "monitor", Option<usize>, None);
+    let letterbox = extract_or!(py, config, "letterbox", bool, false);
+    let auto_scale = extract_or!(py, config, "auto_scale", bool, false);
+    let resizable = extract_or!(py, config, "resizable", bool, false);
+    let maintain_aspect_ratio = extract_or!(py, config, "maintain_aspect_ratio", bool, true);
+    let max_window_scale = extract_or!(py, config, "max_window_scale", Option<i32>, None);
+    let background_color = extract_or!(py, config, "background_color", (u8, u8, u8), (0, 0, 0));
+    let transparent = extract_or!(py, config, "transparent", bool, false);
+    let precise_frame_pacing = extract_or!(py, config, "precise_frame_pacing", bool, false);
+    let max_delta_time = extract_or!(py, config, "max_delta_time", f64, 0.1);
+    let delta_time_smoothing = extract_or!(py, config, "delta_time_smoothing", u32, 1);
+    let scroll_pixels_per_line = extract_or!(py, config, "scroll_pixels_per_line", f32, 10.);
+    let scroll_threshold = extract_or!(py, config, "scroll_threshold", f32, 1.);
+    let screenshot_key = extract_or!(py, config, "screenshot_key", Option<String>, None);
+    let splash_path = extract_or!(py, config, "splash_path", Option<String>, None);
 
-    Some(Config {
+    Config {
         application_name,
         application_version,
         viewport_scale,
         viewport_width,
         viewport_height,
+        scene_width,
+        scene_height,
         tileset_width,
         tileset_height,
         tileset_path,
         tile_names,
-    })
+        tile_animations,
+        legacy_tile_detection,
+        tile_filter,
+        strict_tile_names,
+        glyph_names,
+        msaa_samples,
+        gl_versions,
+        fullscreen,
+        monitor,
+        letterbox,
+        background_color,
+        transparent,
+        auto_scale,
+        resizable,
+        maintain_aspect_ratio,
+        max_window_scale,
+        precise_frame_pacing,
+        max_delta_time,
+        delta_time_smoothing,
+        scroll_pixels_per_line,
+        scroll_threshold,
+        screenshot_key,
+        splash_path,
+    }
 }
 
 /// game_data()
 /// --
 /// Return a reference to the global game data dictionary
 #[pyfunction]
-fn game_data() -> &'static PyDict {
-    unsafe { GAME_DATA.expect("Game data was accessed before initialised") }
+fn game_data() -> PyObject {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    game_data_dict(py).to_object(py)
+}
+
+/// get_data(key) -> value
+/// --
+/// Return a single item from the game data dictionary, or None if it isn't set
+#[pyfunction]
+fn get_data(key: PyObject) -> PyObject {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    match game_data_dict(py).as_ref(py).get_item(key) {
+        Some(value) => value.to_object(py),
+        None => py.None(),
+    }
+}
+
+/// set_data(key, value)
+/// --
+/// Set a single item in the game data dictionary
+#[pyfunction]
+fn set_data(key: PyObject, value: PyObject) -> PyResult<()> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    // keys that can't be hashed raise TypeError, as they would setting the item in python.
+    game_data_dict(py).as_ref(py).set_item(key, value)?;
+
+    Ok(())
+}
+
+fn game_data_dict(py: Python) -> Py<PyDict> {
+    GAME_DATA.with(|game_data| {
+        game_data
+            .borrow()
+            .as_ref()
+            .expect("Game data was accessed before initialised")
+            .clone_ref(py)
+    })
 }
 
 /// exit()
@@ -171,7 +488,46 @@ fn game_data() -> &'static PyDict {
 /// Initiate engine shut down
 #[pyfunction]
 fn exit() {
-    engine!().exit();
+    engine!(exit());
+}
+
+/// cancel_close()
+/// --
+/// Keep the window open after it was asked to close, only works while handling CLOSE_REQUESTED
+#[pyfunction]
+fn cancel_close() {
+    engine!(cancel_close());
+}
+
+/// step_once() -> Boolean
+/// --
+/// Run a single frame of the engine, returns false once the engine is exiting
+#[pyfunction]
+fn step_once(py: Python) -> PyResult<bool> {
+    let entry_module = py.import("entry")?;
+
+    // a frame run from inside an event handler mustn't change the handler's delta time
+    let delta_time = CURRENT_DELTA_TIME.with(|current| current.get());
+    let running = crate::tick(py, entry_module);
+    set_delta_time(delta_time);
+
+    Ok(running)
+}
+
+/// set_strict(strict)
+/// --
+/// Raise exceptions from failed calls instead of returning fallback values
+#[pyfunction]
+fn set_strict(strict: bool) {
+    STRICT_MODE.with(|strict_mode| strict_mode.set(strict));
+}
+
+/// set_event_enabled(event_type, enabled)
+/// --
+/// Stop or resume raising an event type, for games that poll input instead of handling events
+#[pyfunction]
+fn set_event_enabled(event_type: String, enabled: bool) {
+    engine!(set_event_enabled(event_type, enabled))
 }
 
 /// delta_time() -> dt
@@ -179,7 +535,131 @@ fn exit() {
 /// Return the time since the last frame
 #[pyfunction]
 fn delta_time() -> f64 {
-    unsafe { CURRENT_DELTA_TIME }
+    CURRENT_DELTA_TIME.with(|current| current.get())
+}
+
+/// time() -> Float
+/// --
+/// Return the seconds since the engine started, from a clock that never goes backwards
+#[pyfunction]
+fn time() -> f64 {
+    engine!(time_since_start())
+}
+
+/// reconfigure(config)
+/// --
+/// Change items of the configuration while running, items that aren't given keep their current values
+#[pyfunction]
+fn reconfigure(config: HashMap<String, PyObject>) {
+    let window = engine!(get_active_window());
+
+    CONFIGURATION.with(|configuration| {
+        let mut configuration = configuration.borrow_mut();
+        let configuration = configuration.entry(window).or_insert_with(HashMap::new);
+
+        // keep the items that are replaced, so they can be put back if the change isn't applied.
+        let replaced: Vec<(String, Option<PyObject>)> = config
+            .into_iter()
+            .map(|(key, value)| {
+                let previous = configuration.insert(key.clone(), value);
+                (key, previous)
+            })
+            .collect();
+
+        if !engine!(reconfigure(config_from_dict(&configuration))) {
+            for (key, previous) in replaced {
+                match previous {
+                    Some(value) => configuration.insert(key, value),
+                    None => configuration.remove(&key),
+                };
+            }
+        }
+    })
+}
+
+/// app_version() -> String
+/// --
+/// Return the application_version from the game configuration
+#[pyfunction]
+fn app_version() -> String {
+    engine!(app_version())
+}
+
+/// engine_version() -> String
+/// --
+/// Return the version of the pyrite engine
+#[pyfunction]
+fn engine_version() -> String {
+    engine!(engine_version())
+}
+
+/// frame_timings() -> dict
+/// --
+/// Return how long event handling, the step event and rendering took last frame in milliseconds
+#[pyfunction]
+fn frame_timings() -> PyObject {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let timings = engine!(frame_timings());
+
+    let py_timings = PyDict::new(py);
+    py_timings
+        .set_item("events_ms", timings.events.as_secs_f64() * 1000.)
+        .expect("failed to set frame timings item");
+    py_timings
+        .set_item("step_ms", timings.step.as_secs_f64() * 1000.)
+        .expect("failed to set frame timings item");
+    py_timings
+        .set_item("render_ms", timings.render.as_secs_f64() * 1000.)
+        .expect("failed to set frame timings item");
+
+    py_timings.to_object(py)
+}
+
+/// renderer_info() -> dict
+/// --
+/// Return the OpenGL version, GPU name and GLSL version reported by the graphics driver
+#[pyfunction]
+fn renderer_info() -> PyResult<PyObject> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let info = match engine!(renderer_info()) {
+        Some(info) => info,
+        None => {
+            return strict_or(py.None(), || {
+                exceptions::RuntimeError::py_err("the window hasn't been created yet")
+            })
+        }
+    };
+
+    let py_info = PyDict::new(py);
+    py_info
+        .set_item("gl_version", info.gl_version)
+        .expect("failed to set renderer info item");
+    py_info
+        .set_item("gpu", info.gpu)
+        .expect("failed to set renderer info item");
+    py_info
+        .set_item("glsl_version", info.glsl_version)
+        .expect("failed to set renderer info item");
+
+    Ok(py_info.to_object(py))
+}
+
+/// tiles_changed_last_frame() -> int
+/// --
+/// Return how many tiles were set to something different during the last frame
+#[pyfunction]
+fn tiles_changed_last_frame() -> u32 {
+    engine!(tiles_changed_last_frame())
+}
+
+/// upload_region_last_frame() -> (width, height)
+/// --
+/// Return the size in tiles of the scene region uploaded to the graphics card in the last frame
+#[pyfunction]
+fn upload_region_last_frame() -> (u32, u32) {
+    engine!(upload_region_last_frame())
 }
 
 /// mouse_position(camera) -> (x, y)
@@ -188,142 +668,1185 @@ fn delta_time() -> f64 {
 ///
 /// Needs to be provided with a camera to determine the coordinate space to be used
 #[pyfunction]
-fn mouse_position() -> (i32, i32) {
-    engine!().mouse_position()
+fn mouse_position() -> PyResult<(i32, i32)> {
+    match engine!(mouse_position()) {
+        Some(position) => Ok(position),
+        None => strict_or((0, 0), || {
+            exceptions::RuntimeError::py_err("there's no window to get the mouse position in")
+        }),
+    }
 }
 
-/// set_viewport(viewport_width, viewport_height)
+/// mouse_position_pixels() -> (x, y)
 /// --
-/// Set the viewport in tiles
+/// Return the x and y position of the mouse in window pixels.
 #[pyfunction]
-fn set_viewport(viewport_width: i32, viewport_height: i32, viewport_scale: i32) {
-    engine!().set_viewport(viewport_width, viewport_height, viewport_scale)
+fn mouse_position_pixels() -> (i32, i32) {
+    engine!(mouse_position_pixels())
 }
 
-/// set_tile(name, x, y)
-/// set_tile(name, x, y, r, g, b)
-/// set_tile(name, x, y, r, g, b, flip_x, flip_y)
+/// mouse_position_normalized() -> (x, y)
 /// --
-/// Add a tile to the scene
+/// Return the x and y position of the mouse as a fraction of the window size.
 #[pyfunction]
-fn set_tile(
-    position: (i32, i32),
-    front_tile: String,
-    front_color: (u8, u8, u8),
-    front_flip: (bool, bool),
-    back_tile: Option<String>,
-    back_color: Option<(u8, u8, u8)>,
-    back_flip: Option<(bool, bool)>,
-) {
-    let back_tile = back_tile.unwrap_or_else(|| "none".to_owned());
-    let back_color = back_color.unwrap_or((0, 0, 0));
-    let back_flip = back_flip.unwrap_or((false, false));
+fn mouse_position_normalized() -> PyResult<(f32, f32)> {
+    match engine!(mouse_position_normalized()) {
+        Some(position) => Ok(position),
+        None => strict_or((0., 0.), || {
+            exceptions::RuntimeError::py_err("there's no window to get the mouse position in")
+        }),
+    }
+}
 
-    engine!().set_tile(
-        position,
-        front_tile,
-        front_color,
-        front_flip,
-        back_tile,
-        back_color,
-        back_flip,
-    );
+/// tile_under_mouse() -> (x, y)
+/// --
+/// Return the scene position of the tile under the mouse, or None when the mouse isn't over the scene
+#[pyfunction]
+fn tile_under_mouse() -> Option<(i32, i32)> {
+    engine!(tile_under_mouse())
 }
 
-/// clear()
-/// clear all the tiles to none, and modifiers to unflipped and coloured black.
+/// present() -> Boolean
+/// --
+/// Render and present a frame now if the scene changed, returns true if a frame was drawn
 #[pyfunction]
-fn clear() {
-    engine!().clear_tiles();
+fn present() -> bool {
+    engine!(render())
 }
 
-/// button_down(button) -> Boolean
+/// flush()
 /// --
-/// returns true if button is down
+/// Present any changes to the scene and wait for the graphics driver to finish drawing them
 #[pyfunction]
-fn button_down(button: String) -> bool {
-    engine!().button_down(button)
+fn flush() {
+    engine!(flush())
 }
 
-/// resource_read(path)
+/// request_redraw()
 /// --
-/// Read in the contents of a resource file
+/// Render a new frame at the next present, even if the scene hasn't changed
 #[pyfunction]
-fn resource_read(path: String) -> String {
-    engine!().resource_read(path)
+fn request_redraw() {
+    engine!(request_redraw())
 }
 
-/// resource_exists(path)
+/// set_continuous_rendering(enabled)
 /// --
-/// Check if a resource exists
+/// Render every frame instead of only when the scene changes
 #[pyfunction]
-fn resource_exists(path: String) -> bool {
-    engine!().resource_exists(path)
+fn set_continuous_rendering(enabled: bool) {
+    engine!(set_continuous_rendering(enabled))
 }
 
-/// play_audio(path)
+/// set_clear_enabled(enabled)
 /// --
-/// Start playing an audio track
+/// Clear the window before drawing each frame, or keep the previous frame for trail effects
 #[pyfunction]
-fn play_audio(path: String) {
-    engine!().play_audio(path)
+fn set_clear_enabled(enabled: bool) {
+    engine!(set_clear_enabled(enabled))
 }
 
-/// stop_audio(path)
+/// get_clear_enabled() -> Boolean
 /// --
-/// Stop playing an audio track
+/// Return whether the window is cleared before drawing each frame
 #[pyfunction]
-fn stop_audio(path: String) {
-    engine!().stop_audio(path)
+fn get_clear_enabled() -> bool {
+    engine!(get_clear_enabled())
 }
 
-/// pause_audio(path)
+/// set_debug_overlay(enabled)
 /// --
-/// Pause playing an audio track
+/// Show the frame rate and frame time over the top of the game
 #[pyfunction]
-fn pause_audio(path: String) {
-    engine!().pause_audio(path)
+fn set_debug_overlay(enabled: bool) {
+    engine!(set_debug_overlay(enabled))
 }
 
-/// volume_audio(path)
+/// set_zoom(factor)
 /// --
-/// Adjust volume of track by multiplying samples by given factor
+/// Magnify the scene about the centre of the viewport, factors can be fractional and start at 1.0
 #[pyfunction]
-fn volume_audio(path: String, value: f32) {
-    engine!().volume_audio(path, value)
+fn set_zoom(factor: f32) {
+    engine!(set_zoom(factor))
+}
+
+/// get_zoom() -> factor
+/// --
+/// Return the zoom factor of the scene
+#[pyfunction]
+fn get_zoom() -> f32 {
+    engine!(get_zoom())
+}
+
+/// set_viewport(viewport_width, viewport_height)
+/// --
+/// Set the viewport in tiles
+#[pyfunction]
+fn set_viewport(viewport_width: i32, viewport_height: i32, viewport_scale: i32) {
+    engine!(set_viewport(
+        viewport_width,
+        viewport_height,
+        viewport_scale
+    ))
+}
+
+/// set_camera(position)
+/// --
+/// Scroll the scene so the tile at position is drawn in the top left corner of the viewport
+#[pyfunction]
+fn set_camera(position: (i32, i32)) {
+    engine!(set_camera(position))
+}
+
+/// get_camera() -> (x, y)
+/// --
+/// Return the tile drawn in the top left corner of the viewport
+#[pyfunction]
+fn get_camera() -> (i32, i32) {
+    engine!(get_camera())
 }
 
-fn event_data_into_pyobject(event: &Event) -> PyObject {
+/// list_monitors() -> list
+/// --
+/// Get the name, size, position and scale factor of every connected display
+#[pyfunction]
+fn list_monitors() -> PyObject {
     let py = unsafe { Python::assume_gil_acquired() };
 
-    let py_event = PyDict::new(py);
+    let monitors: Vec<PyObject> = engine!(list_monitors())
+        .into_iter()
+        .map(|monitor| {
+            let py_monitor = PyDict::new(py);
+            py_monitor
+                .set_item("name", monitor.name)
+                .expect("failed to set monitor item");
+            py_monitor
+                .set_item("size", monitor.size)
+                .expect("failed to set monitor item");
+            py_monitor
+                .set_item("position", monitor.position)
+                .expect("failed to set monitor item");
+            py_monitor
+                .set_item("scale_factor", monitor.scale_factor)
+                .expect("failed to set monitor item");
+            py_monitor
+                .set_item("primary", monitor.primary)
+                .expect("failed to set monitor item");
 
-    match event {
-        Event::Load => (),
-        Event::Button { button, transition } => {
-            py_event
-                .set_item("button", button)
-                .expect("failed to set event item");
-            py_event
-                .set_item("transition", transition)
-                .expect("failed to set event item");
-        }
-        Event::Scroll { x, y } => {
-            py_event.set_item("x", x).expect("failed to set event item");
-            py_event.set_item("y", y).expect("failed to set event item");
-        }
-        Event::Text { text } => {
-            py_event
-                .set_item("text", text)
-                .expect("failed to set event item");
-        }
-        Event::Step { delta_time } => {
-            py_event
-                .set_item("delta_time", delta_time)
-                .expect("failed to set event item");
+            py_monitor.to_object(py)
+        })
+        .collect();
+
+    monitors.to_object(py)
+}
+
+/// set_monitor(index)
+/// --
+/// Move the window, or fullscreen, to a display from list_monitors
+#[pyfunction]
+fn set_monitor(index: usize) {
+    engine!(set_monitor(index))
+}
+
+/// create_window(config) -> Integer
+/// --
+/// Open another window, items of the configuration that aren't given are copied from the active window
+#[pyfunction]
+fn create_window(config: HashMap<String, PyObject>) -> PyResult<Option<u32>> {
+    let py = unsafe { Python::assume_gil_acquired() };
+    let active_window = engine!(get_active_window());
+
+    let mut window_config: HashMap<String, PyObject> = CONFIGURATION.with(|configuration| {
+        configuration
+            .borrow()
+            .get(&active_window)
+            .map(|active_config| {
+                active_config
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone_ref(py)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+    window_config.extend(config);
+
+    match engine!(create_window(config_from_dict(&window_config))) {
+        Some(window) => {
+            CONFIGURATION
+                .with(|configuration| configuration.borrow_mut().insert(window, window_config));
+            Ok(Some(window))
         }
-        Event::Exit => (),
-    };
+        None => strict_or(None, || {
+            exceptions::RuntimeError::py_err("the window couldn't be created")
+        }),
+    }
+}
 
-    return py_event.to_object(py);
+/// set_active_window(window) -> Boolean
+/// --
+/// Direct the engine functions to a window from create_window, the game's first window is 0
+#[pyfunction]
+fn set_active_window(window: u32) -> PyResult<bool> {
+    if engine!(set_active_window(window)) {
+        Ok(true)
+    } else {
+        strict_or(false, || {
+            exceptions::ValueError::py_err(format!("there's no window {}", window))
+        })
+    }
+}
+
+/// get_active_window() -> Integer
+/// --
+/// Return the window the engine functions are directed to
+#[pyfunction]
+fn get_active_window() -> u32 {
+    engine!(get_active_window())
+}
+
+/// close_window(window) -> Boolean
+/// --
+/// Close a window from create_window, the game's first window can't be closed
+#[pyfunction]
+fn close_window(window: u32) -> PyResult<bool> {
+    if engine!(close_window(window)) {
+        CONFIGURATION.with(|configuration| configuration.borrow_mut().remove(&window));
+        Ok(true)
+    } else {
+        strict_or(false, || {
+            exceptions::ValueError::py_err(format!("there's no window {} to close", window))
+        })
+    }
+}
+
+/// get_window_position() -> (x, y)
+/// --
+/// Get the desktop position of the window's top left corner, None if the platform doesn't allow it
+#[pyfunction]
+fn get_window_position() -> PyResult<Option<(i32, i32)>> {
+    match engine!(get_window_position()) {
+        Some(position) => Ok(Some(position)),
+        None => strict_or(None, || {
+            exceptions::RuntimeError::py_err("the window position isn't available")
+        }),
+    }
+}
+
+/// set_window_position(position)
+/// --
+/// Move the window's top left corner to a desktop position
+#[pyfunction]
+fn set_window_position(position: (i32, i32)) {
+    engine!(set_window_position(position))
+}
+
+/// center_window()
+/// --
+/// Move the window to the centre of its display
+#[pyfunction]
+fn center_window() {
+    engine!(center_window())
+}
+
+/// minimize_window()
+/// --
+/// Minimize the window
+#[pyfunction]
+fn minimize_window() {
+    engine!(minimize_window())
+}
+
+/// maximize_window()
+/// --
+/// Maximize a resizable window to fill its display
+#[pyfunction]
+fn maximize_window() {
+    engine!(maximize_window())
+}
+
+/// restore_window()
+/// --
+/// Return a minimized or maximized window to its regular size
+#[pyfunction]
+fn restore_window() {
+    engine!(restore_window())
+}
+
+/// set_fullscreen(fullscreen)
+/// --
+/// Switch between a borderless fullscreen window and a regular window
+#[pyfunction]
+fn set_fullscreen(fullscreen: bool) {
+    engine!(set_fullscreen(fullscreen))
+}
+
+/// set_resizable(resizable)
+/// --
+/// Allow or prevent the player from resizing the window
+#[pyfunction]
+fn set_resizable(resizable: bool) {
+    engine!(set_resizable(resizable))
+}
+
+/// set_cursor_icon(name)
+/// --
+/// Change the mouse cursor shown over the window, such as "arrow", "hand", "crosshair" or "text"
+#[pyfunction]
+fn set_cursor_icon(name: String) {
+    engine!(set_cursor_icon(name))
+}
+
+/// set_palette(start_index, colors)
+/// --
+/// Replace tileset pixels whose luminance (0-255) matches a palette index with the palette color
+#[pyfunction]
+fn set_palette(start_index: usize, colors: Vec<(u8, u8, u8)>) {
+    engine!(set_palette(start_index, colors))
+}
+
+/// clear_palette()
+/// --
+/// Remove all palette colors, tiles are drawn with their tileset colors
+#[pyfunction]
+fn clear_palette() {
+    engine!(clear_palette())
+}
+
+/// set_tint(r, g, b, a)
+/// --
+/// Multiply a color over the whole screen, a controls the strength of the tint
+#[pyfunction]
+fn set_tint(r: u8, g: u8, b: u8, a: u8) {
+    engine!(set_tint((r, g, b, a)))
+}
+
+/// tileset_info() -> dict
+/// --
+/// Return the tile dimensions, tileset grid size and known tile names of the loaded tileset
+#[pyfunction]
+fn tileset_info() -> PyResult<PyObject> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let info = match engine!(tileset_info()) {
+        Some(info) => info,
+        None => {
+            return strict_or(py.None(), || {
+                exceptions::RuntimeError::py_err("there's no tileset loaded yet")
+            })
+        }
+    };
+
+    let py_info = PyDict::new(py);
+    py_info
+        .set_item("tile_width", info.tile_width)
+        .expect("failed to set tileset info item");
+    py_info
+        .set_item("tile_height", info.tile_height)
+        .expect("failed to set tileset info item");
+    py_info
+        .set_item("set_width", info.set_width)
+        .expect("failed to set tileset info item");
+    py_info
+        .set_item("set_height", info.set_height)
+        .expect("failed to set tileset info item");
+    py_info
+        .set_item("tile_names", info.tile_names)
+        .expect("failed to set tileset info item");
+
+    Ok(py_info.to_object(py))
+}
+
+/// tileset_pixels() -> (width, height, bytes)
+/// --
+/// Return the size of the tileset image and its pixels as RGBA bytes
+#[pyfunction]
+fn tileset_pixels() -> PyResult<PyObject> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    match engine!(tileset_pixels()) {
+        Some((width, height, pixels)) => {
+            Ok((width, height, PyBytes::new(py, &pixels).to_object(py)).to_object(py))
+        }
+        None => strict_or(py.None(), || {
+            exceptions::RuntimeError::py_err("there's no tileset loaded yet")
+        }),
+    }
+}
+
+/// require_tiles(names) -> List
+/// --
+/// returns the tile names that aren't in the tileset
+#[pyfunction]
+fn require_tiles(names: Vec<String>) -> Vec<String> {
+    engine!(require_tiles(names))
+}
+
+/// set_tile(name, x, y)
+/// set_tile(name, x, y, r, g, b)
+/// set_tile(name, x, y, r, g, b, flip_x, flip_y)
+/// --
+/// Add a tile to the scene
+#[pyfunction]
+fn set_tile(
+    position: (i32, i32),
+    front_tile: String,
+    front_color: (u8, u8, u8),
+    front_flip: (bool, bool),
+    back_tile: Option<String>,
+    back_color: Option<(u8, u8, u8)>,
+    back_flip: Option<(bool, bool)>,
+    front_depth: Option<u8>,
+    back_depth: Option<u8>,
+) {
+    let back_tile = back_tile.unwrap_or_else(|| "none".to_owned());
+    let back_color = back_color.unwrap_or((0, 0, 0));
+    let back_flip = back_flip.unwrap_or((false, false));
+    let depths = (
+        front_depth.unwrap_or(graphics::DEFAULT_TILE_DEPTHS.0),
+        back_depth.unwrap_or(graphics::DEFAULT_TILE_DEPTHS.1),
+    );
+
+    engine!(set_tile(
+        position,
+        front_tile,
+        front_color,
+        front_flip,
+        back_tile,
+        back_color,
+        back_flip,
+        depths,
+    ));
+}
+
+/// set_front_tile(position, tile, color)
+/// set_front_tile(position, tile, color, flip)
+/// --
+/// Set the front layer of a tile, leaving its back layer as it is
+#[pyfunction]
+fn set_front_tile(
+    position: (i32, i32),
+    tile: String,
+    color: (u8, u8, u8),
+    flip: Option<(bool, bool)>,
+) {
+    let flip = flip.unwrap_or((false, false));
+    engine!(set_layer_tile(
+        position,
+        graphics::Layer::Front,
+        tile,
+        color,
+        flip
+    ));
+}
+
+/// set_back_tile(position, tile, color)
+/// set_back_tile(position, tile, color, flip)
+/// --
+/// Set the back layer of a tile, leaving its front layer as it is
+#[pyfunction]
+fn set_back_tile(
+    position: (i32, i32),
+    tile: String,
+    color: (u8, u8, u8),
+    flip: Option<(bool, bool)>,
+) {
+    let flip = flip.unwrap_or((false, false));
+    engine!(set_layer_tile(
+        position,
+        graphics::Layer::Back,
+        tile,
+        color,
+        flip
+    ));
+}
+
+/// set_tile_raw(position, front_index, front_modifiers, back_index, back_modifiers) -> Boolean
+/// --
+/// Set the tileset cells and modifier bytes of a tile directly, for custom shaders
+#[pyfunction]
+fn set_tile_raw(
+    position: (i32, i32),
+    front_index: i32,
+    front_modifiers: (u8, u8, u8, u8),
+    back_index: i32,
+    back_modifiers: (u8, u8, u8, u8),
+) -> PyResult<bool> {
+    if engine!(set_tile_raw(
+        position,
+        front_index,
+        front_modifiers,
+        back_index,
+        back_modifiers
+    )) {
+        return Ok(true);
+    }
+
+    strict_or(false, || {
+        exceptions::ValueError::py_err(format!(
+            "no tile set at {:?}, the position must be inside the scene and the indices inside \
+             the tileset",
+            position
+        ))
+    })
+}
+
+/// tint_tile(position, front_color)
+/// tint_tile(position, front_color, back_color)
+/// --
+/// Change the colors of a tile without changing its tiles or flip
+#[pyfunction]
+fn tint_tile(position: (i32, i32), front_color: (u8, u8, u8), back_color: Option<(u8, u8, u8)>) {
+    engine!(tint_tile(position, front_color, back_color));
+}
+
+/// draw_debug_text(position, text, color)
+/// --
+/// Draw text in the built-in font over the scene for the next frame only
+#[pyfunction]
+fn draw_debug_text(position: (i32, i32), text: String, color: (u8, u8, u8)) {
+    engine!(draw_debug_text(position, text, color));
+}
+
+/// upload_pixels(width, height, data) -> bool
+/// --
+/// Show RGBA pixel data stretched over the viewport above the tiles, returns False if the size is wrong
+#[pyfunction]
+fn upload_pixels(width: i32, height: i32, data: &PyBytes) -> PyResult<bool> {
+    if engine!(upload_pixels((width, height), data.as_bytes())) {
+        return Ok(true);
+    }
+
+    strict_or(false, || {
+        exceptions::ValueError::py_err(format!(
+            "pixels weren't uploaded, {}x{} pixels need {} bytes of RGBA data and {} were given",
+            width,
+            height,
+            i64::from(width.max(0)) * i64::from(height.max(0)) * 4,
+            data.as_bytes().len()
+        ))
+    })
+}
+
+/// clear_pixels()
+/// --
+/// Stop showing the pixel data from upload_pixels
+#[pyfunction]
+fn clear_pixels() {
+    engine!(clear_pixels());
+}
+
+/// draw_particles(particles)
+/// --
+/// Draw (x, y, name, color) tiles over the scene for the next frame only
+#[pyfunction]
+fn draw_particles(particles: Vec<(f32, f32, String, (u8, u8, u8))>) {
+    engine!(draw_particles(particles));
+}
+
+/// get_tile_color(position, layer) -> (r, g, b)
+/// --
+/// Get the color of the "front" or "back" layer of a tile, None outside the scene
+#[pyfunction]
+fn get_tile_color(position: (i32, i32), layer: String) -> PyResult<Option<(u8, u8, u8)>> {
+    match engine!(get_tile_color(position, layer.clone())) {
+        Some(color) => Ok(Some(color)),
+        None => strict_or(None, || {
+            exceptions::ValueError::py_err(format!(
+                "no {} tile color at {:?}, the layer must be \"front\" or \"back\" and the \
+                 position inside the scene",
+                layer, position
+            ))
+        }),
+    }
+}
+
+/// clear()
+/// --
+/// Clear all the tiles to none, and modifiers to unflipped and coloured black.
+#[pyfunction]
+fn clear() {
+    engine!(clear_tiles());
+}
+
+/// clear_viewport()
+/// --
+/// Clear only the tiles shown in the viewport, the same way clear does
+#[pyfunction]
+fn clear_viewport() {
+    engine!(clear_viewport());
+}
+
+/// clear_layer(layer)
+/// --
+/// Clear only the "front" or "back" layer of the tiles shown in the viewport
+#[pyfunction]
+fn clear_layer(layer: String) {
+    engine!(clear_layer(layer));
+}
+
+/// fill_rect(position, size, tile, color)
+/// --
+/// Set every tile within the rectangle to the given tile and colour
+#[pyfunction]
+fn fill_rect(position: (i32, i32), size: (i32, i32), tile: String, color: (u8, u8, u8)) {
+    engine!(fill_rect(position, size, tile, color));
+}
+
+/// clear_rect(position, size)
+/// --
+/// Clear every tile within the rectangle the same way clear() does
+#[pyfunction]
+fn clear_rect(position: (i32, i32), size: (i32, i32)) {
+    engine!(clear_rect(position, size));
+}
+
+/// draw_text(position, text, color)
+/// --
+/// Draw a string of glyph tiles starting at position, wrapping at the edge of the viewport
+#[pyfunction]
+fn draw_text(position: (i32, i32), text: String, color: (u8, u8, u8)) {
+    engine!(draw_text(position, text, color));
+}
+
+/// text_width(text) -> int
+/// --
+/// Return how many tiles wide the longest line of text is when drawn with draw_text
+#[pyfunction]
+fn text_width(text: String) -> i32 {
+    engine!(text_width(text))
+}
+
+/// define_animation(name, frames, fps)
+/// --
+/// Define a named animation that cycles through the given tile names at fps frames per second
+#[pyfunction]
+fn define_animation(name: String, frames: Vec<String>, fps: f64) {
+    engine!(define_animation(name, frames, fps));
+}
+
+/// set_animated_tile(position, animation, color)
+/// --
+/// Play a defined animation on a tile until the tile is set or cleared
+#[pyfunction]
+fn set_animated_tile(position: (i32, i32), animation: String, color: (u8, u8, u8)) {
+    engine!(set_animated_tile(position, animation, color));
+}
+
+/// button_down(button) -> Boolean
+/// --
+/// returns true if button is down
+#[pyfunction]
+fn button_down(button: String) -> bool {
+    engine!(button_down(button))
+}
+
+/// button_pressed(button) -> Boolean
+/// --
+/// returns true if button went down this frame
+#[pyfunction]
+fn button_pressed(button: String) -> bool {
+    engine!(button_pressed(button))
+}
+
+/// button_released(button) -> Boolean
+/// --
+/// returns true if button went up this frame
+#[pyfunction]
+fn button_released(button: String) -> bool {
+    engine!(button_released(button))
+}
+
+/// pressed_buttons() -> List
+/// --
+/// returns the names of all buttons currently held down
+#[pyfunction]
+fn pressed_buttons() -> Vec<String> {
+    engine!(pressed_buttons())
+}
+
+/// load_key_map(path) -> Boolean
+/// --
+/// bind actions to buttons from a JSON key map resource, returns false if it couldn't be loaded
+#[pyfunction]
+fn load_key_map(path: String) -> PyResult<bool> {
+    if engine!(load_key_map(path.clone())) {
+        return Ok(true);
+    }
+
+    strict_or(false, || {
+        exceptions::IOError::py_err(format!("key map \"{}\" couldn't be loaded", path))
+    })
+}
+
+/// bind_action(action, button)
+/// --
+/// bind an action to a button, replacing its previous buttons
+#[pyfunction]
+fn bind_action(action: String, button: String) {
+    engine!(bind_action(action, button))
+}
+
+/// action_down(action) -> Boolean
+/// --
+/// returns true if any button bound to the action is down
+#[pyfunction]
+fn action_down(action: String) -> bool {
+    engine!(action_down(action))
+}
+
+/// resource_read(path) -> str
+/// --
+/// Read in the contents of a resource file, returns None if the resource can't be read
+#[pyfunction]
+fn resource_read(path: String) -> PyResult<Option<String>> {
+    match engine!(resource_read(path.clone())) {
+        Some(text) => Ok(Some(text)),
+        None => strict_or(None, || {
+            exceptions::IOError::py_err(format!("resource \"{}\" couldn't be read as text", path))
+        }),
+    }
+}
+
+/// resource_read_bytes(path) -> bytes
+/// --
+/// Read a resource as bytes, returns empty bytes if the resource can't be read
+#[pyfunction]
+fn resource_read_bytes(path: String) -> PyResult<PyObject> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let data = match engine!(resource_read_bytes(path.clone())) {
+        Some(data) => data,
+        None => strict_or(Vec::new(), || {
+            exceptions::IOError::py_err(format!("resource \"{}\" couldn't be read", path))
+        })?,
+    };
+
+    Ok(PyBytes::new(py, &data).to_object(py))
+}
+
+/// resource_exists(path)
+/// --
+/// Check if a resource exists
+#[pyfunction]
+fn resource_exists(path: String) -> bool {
+    engine!(resource_exists(path))
+}
+
+/// require_resources(paths) -> List
+/// --
+/// returns the paths of the resources that don't exist
+#[pyfunction]
+fn require_resources(paths: Vec<String>) -> Vec<String> {
+    engine!(require_resources(paths))
+}
+
+/// load_async(path) -> int
+/// --
+/// Start reading a resource in the background, returns a handle for load_ready and load_take
+#[pyfunction]
+fn load_async(path: String) -> u32 {
+    engine!(load_async(path))
+}
+
+/// load_ready(handle) -> bool
+/// --
+/// Check if a background load has finished
+#[pyfunction]
+fn load_ready(handle: u32) -> bool {
+    engine!(load_ready(handle))
+}
+
+/// load_take(handle) -> bytes
+/// --
+/// Take the bytes of a finished background load, returns None if it hasn't finished or failed
+#[pyfunction]
+fn load_take(handle: u32) -> PyResult<Option<PyObject>> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let finished = engine!(load_ready(handle));
+    match engine!(load_take(handle)) {
+        Some(data) => Ok(Some(PyBytes::new(py, &data).to_object(py))),
+        None => strict_or(None, || {
+            unfinished_error("background load", handle, finished)
+        }),
+    }
+}
+
+/// load_cancel(handle) -> bool
+/// --
+/// Forget a background load and discard its data, returns false if the handle is unknown
+#[pyfunction]
+fn load_cancel(handle: u32) -> PyResult<bool> {
+    if engine!(load_cancel(handle)) {
+        return Ok(true);
+    }
+
+    strict_or(false, || unknown_handle_error("background load", handle))
+}
+
+/// socket_open(address, protocol, events) -> int
+/// --
+/// Start connecting a non-blocking TCP or UDP socket in the background, returns a handle or None if the protocol is unknown
+#[pyfunction]
+fn socket_open(address: String, protocol: String, events: bool) -> PyResult<Option<u32>> {
+    match engine!(socket_open(address, protocol.clone(), events)) {
+        Some(handle) => Ok(Some(handle)),
+        None => strict_or(None, || {
+            exceptions::ValueError::py_err(format!(
+                "unknown socket protocol \"{}\", it must be \"TCP\" or \"UDP\"",
+                protocol
+            ))
+        }),
+    }
+}
+
+/// socket_state(handle) -> str
+/// --
+/// Get whether a socket is CONNECTING, OPEN or CLOSED
+#[pyfunction]
+fn socket_state(handle: u32) -> String {
+    engine!(socket_state(handle))
+}
+
+/// socket_send(handle, data) -> bool
+/// --
+/// Send bytes over a socket once it can take them, returns False if the socket is closed
+#[pyfunction]
+fn socket_send(handle: u32, data: &PyBytes) -> PyResult<bool> {
+    if engine!(socket_send(handle, data.as_bytes())) {
+        return Ok(true);
+    }
+
+    strict_or(false, || {
+        exceptions::IOError::py_err(format!("socket {} is closed", handle))
+    })
+}
+
+/// socket_recv(handle) -> bytes
+/// --
+/// Read whatever has arrived on a socket without waiting, returns None if nothing has arrived
+#[pyfunction]
+fn socket_recv(handle: u32) -> Option<PyObject> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    engine!(socket_recv(handle)).map(|data| PyBytes::new(py, &data).to_object(py))
+}
+
+/// socket_close(handle)
+/// --
+/// Close a socket
+#[pyfunction]
+fn socket_close(handle: u32) {
+    engine!(socket_close(handle))
+}
+
+/// http_get_async(url) -> int
+/// --
+/// Start a HTTP GET request in the background, returns a handle for http_ready and http_take
+#[cfg(feature = "network")]
+#[pyfunction]
+fn http_get_async(url: String) -> u32 {
+    engine!(http_get_async(url))
+}
+
+/// http_ready(handle) -> bool
+/// --
+/// Check if a background HTTP request has finished
+#[cfg(feature = "network")]
+#[pyfunction]
+fn http_ready(handle: u32) -> bool {
+    engine!(http_ready(handle))
+}
+
+/// http_take(handle) -> bytes
+/// --
+/// Take the response body of a finished HTTP request, returns None if it hasn't finished or failed
+#[cfg(feature = "network")]
+#[pyfunction]
+fn http_take(handle: u32) -> PyResult<Option<PyObject>> {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let finished = engine!(http_ready(handle));
+    match engine!(http_take(handle)) {
+        Some(data) => Ok(Some(PyBytes::new(py, &data).to_object(py))),
+        None => strict_or(None, || unfinished_error("HTTP request", handle, finished)),
+    }
+}
+
+/// http_cancel(handle) -> bool
+/// --
+/// Forget a background HTTP request and discard its response, returns false if the handle is unknown
+#[cfg(feature = "network")]
+#[pyfunction]
+fn http_cancel(handle: u32) -> PyResult<bool> {
+    if engine!(http_cancel(handle)) {
+        return Ok(true);
+    }
+
+    strict_or(false, || unknown_handle_error("HTTP request", handle))
+}
+
+/// play_audio(path)
+/// --
+/// Start playing an audio track
+#[pyfunction]
+fn play_audio(path: String) {
+    engine!(play_audio(path))
+}
+
+/// play_looping_section(path, start_secs, end_secs)
+/// --
+/// Play an audio track from the beginning, then repeat the section from start_secs to end_secs
+#[pyfunction]
+fn play_looping_section(path: String, start_secs: f64, end_secs: f64) {
+    engine!(play_audio_looping_section(path, start_secs, end_secs))
+}
+
+/// play_audio_bytes(name, data)
+/// --
+/// Start playing encoded audio data from a bytes object under the given track name
+#[pyfunction]
+fn play_audio_bytes(name: String, data: &PyBytes) {
+    engine!(play_audio_bytes(name, data.as_bytes().to_vec()))
+}
+
+/// stop_audio(path)
+/// --
+/// Stop playing an audio track
+#[pyfunction]
+fn stop_audio(path: String) {
+    engine!(stop_audio(path))
+}
+
+/// unload_audio(path)
+/// --
+/// Stop an audio track and free its memory
+#[pyfunction]
+fn unload_audio(path: String) {
+    engine!(unload_audio(path))
+}
+
+/// pause_audio(path)
+/// --
+/// Pause playing an audio track
+#[pyfunction]
+fn pause_audio(path: String) {
+    engine!(pause_audio(path))
+}
+
+/// volume_audio(path)
+/// --
+/// Adjust volume of track by multiplying samples by given factor
+#[pyfunction]
+fn volume_audio(path: String, value: f32) {
+    engine!(volume_audio(path, value))
+}
+
+/// pan_audio(path, value)
+/// --
+/// Pan a track between the left (-1.0) and right (1.0) speakers
+#[pyfunction]
+fn pan_audio(path: String, value: f32) {
+    engine!(pan_audio(path, value))
+}
+
+/// audio_position(path) -> Float
+/// --
+/// Return how many seconds a track has been playing for, or None if the track isn't loaded
+#[pyfunction]
+fn audio_position(path: String) -> PyResult<Option<f64>> {
+    match engine!(audio_position(path.clone())) {
+        Some(position) => Ok(Some(position)),
+        None => strict_or(None, || {
+            exceptions::ValueError::py_err(format!("audio track \"{}\" isn't loaded", path))
+        }),
+    }
+}
+
+/// audio_duration(path) -> Float
+/// --
+/// Return the length of a track in seconds, or None if it's unknown
+#[pyfunction]
+fn audio_duration(path: String) -> Option<f64> {
+    engine!(audio_duration(path))
+}
+
+/// audio_tracks() -> List
+/// --
+/// Return (path, status) for every track the audio server holds, for debugging
+#[pyfunction]
+fn audio_tracks() -> Vec<(String, String)> {
+    engine!(audio_tracks())
+}
+
+/// audio_formats_supported() -> List
+/// --
+/// Return the names of the audio formats this build can decode, such as "WAV" or "OGG"
+#[pyfunction]
+fn audio_formats_supported() -> Vec<String> {
+    engine!(audio_formats_supported())
+}
+
+/// reinitialize_audio() -> Boolean
+/// --
+/// Reconnect to the default audio device, stopping all tracks. Returns true if a device was found
+#[pyfunction]
+fn reinitialize_audio() -> PyResult<bool> {
+    if engine!(reinitialize_audio()) {
+        return Ok(true);
+    }
+
+    strict_or(false, || {
+        exceptions::RuntimeError::py_err("no audio device was found")
+    })
+}
+
+/// audio_available() -> Boolean
+/// --
+/// Return true if there's an audio device to play tracks on
+#[pyfunction]
+fn audio_available() -> bool {
+    engine!(audio_available())
+}
+
+fn event_data_into_pyobject(event: &Event, timestamp: f64) -> PyObject {
+    let py = unsafe { Python::assume_gil_acquired() };
+
+    let py_event = PyDict::new(py);
+    py_event
+        .set_item("timestamp", timestamp)
+        .expect("failed to set event item");
+
+    match event {
+        Event::Load => (),
+        Event::Button {
+            button,
+            transition,
+            modifiers,
+        } => {
+            py_event
+                .set_item("button", button)
+                .expect("failed to set event item");
+            py_event
+                .set_item("transition", transition)
+                .expect("failed to set event item");
+            py_event
+                .set_item("shift", modifiers.shift)
+                .expect("failed to set event item");
+            py_event
+                .set_item("ctrl", modifiers.ctrl)
+                .expect("failed to set event item");
+            py_event
+                .set_item("alt", modifiers.alt)
+                .expect("failed to set event item");
+            py_event
+                .set_item("super", modifiers.logo)
+                .expect("failed to set event item");
+        }
+        Event::Scroll { x, y } => {
+            py_event.set_item("x", x).expect("failed to set event item");
+            py_event.set_item("y", y).expect("failed to set event item");
+        }
+        Event::Text { text } => {
+            py_event
+                .set_item("text", text)
+                .expect("failed to set event item");
+        }
+        Event::Step { delta_time } => {
+            py_event
+                .set_item("delta_time", delta_time)
+                .expect("failed to set event item");
+        }
+        Event::Network { handle, data } => {
+            py_event
+                .set_item("handle", handle)
+                .expect("failed to set event item");
+            py_event
+                .set_item("data", PyBytes::new(py, data))
+                .expect("failed to set event item");
+        }
+        Event::Focus { focused, minimized } => {
+            py_event
+                .set_item("focused", focused)
+                .expect("failed to set event item");
+            py_event
+                .set_item("minimized", minimized)
+                .expect("failed to set event item");
+        }
+        Event::CloseRequested => (),
+        Event::WindowClosed { window } => {
+            py_event
+                .set_item("window", window)
+                .expect("failed to set event item");
+        }
+        Event::Exit => (),
+    };
+
+    return py_event.to_object(py);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyList;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    #[should_panic(expected = "An engine function was invoked while the engine was already in use")]
+    fn nested_borrow_panics() {
+        let instance = RefCell::new(Some(0));
+
+        borrow_instance(&instance, |_| borrow_instance(&instance, |_| ()));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "An engine function was invoked without an available engine instance"
+    )]
+    fn missing_instance_panics() {
+        let instance: RefCell<Option<i32>> = RefCell::new(None);
+
+        borrow_instance(&instance, |_| ());
+    }
+
+    #[test]
+    fn borrow_after_completed_borrow_succeeds() {
+        let instance = RefCell::new(Some(0));
+
+        borrow_instance(&instance, |value| *value += 1);
+        let value = borrow_instance(&instance, |value| {
+            *value += 1;
+            *value
+        });
+
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn borrow_after_nested_panic_succeeds() {
+        let instance = RefCell::new(Some(0));
+
+        // unwinding out of the nested call releases the outer borrow.
+        let nested = catch_unwind(AssertUnwindSafe(|| {
+            borrow_instance(&instance, |_| borrow_instance(&instance, |_| ()))
+        }));
+        assert!(nested.is_err());
+
+        assert_eq!(borrow_instance(&instance, |value| *value), 0);
+    }
+
+    #[test]
+    fn set_data_with_an_unhashable_key_raises() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        GAME_DATA.with(|game_data| *game_data.borrow_mut() = Some(PyDict::new(py).into()));
+
+        let error = set_data(PyList::empty(py).to_object(py), py.None()).unwrap_err();
+        assert!(error.is_instance::<exceptions::TypeError>(py));
+
+        // the game data is still usable afterwards
+        set_data("level".to_object(py), 3.to_object(py)).unwrap();
+        let level: u32 = get_data("level".to_object(py)).extract(py).unwrap();
+        assert_eq!(level, 3);
+    }
 }
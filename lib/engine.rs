@@ -1,8 +1,18 @@
 use crate::audio;
 use crate::graphics;
-use crate::platform::Platform;
+use crate::loader;
+#[cfg(feature = "network")]
+use crate::network;
+use crate::platform::{Platform, MAIN_WINDOW};
 use crate::pyrite_log;
+use crate::replay::Replay;
 use crate::resources;
+use crate::socket;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug)]
 pub struct Config {
@@ -11,22 +21,79 @@ pub struct Config {
     pub viewport_scale: i32,
     pub viewport_width: i32,
     pub viewport_height: i32,
+    pub scene_width: i32,
+    pub scene_height: i32,
     pub tileset_width: u32,
     pub tileset_height: u32,
     pub tileset_path: String,
     pub tile_names: Vec<String>,
+    pub tile_animations: HashMap<String, (Vec<String>, f64)>,
+    pub legacy_tile_detection: bool,
+    pub tile_filter: String,
+    pub strict_tile_names: bool,
+    pub glyph_names: HashMap<String, String>,
+    pub msaa_samples: u16,
+    pub gl_versions: Vec<String>,
+    pub fullscreen: bool,
+    pub monitor: Option<usize>,
+    pub letterbox: bool,
+    pub background_color: (u8, u8, u8),
+    pub transparent: bool,
+    pub auto_scale: bool,
+    pub resizable: bool,
+    pub maintain_aspect_ratio: bool,
+    pub max_window_scale: Option<i32>,
+    pub precise_frame_pacing: bool,
+    pub max_delta_time: f64,
+    pub delta_time_smoothing: u32,
+    pub scroll_pixels_per_line: f32,
+    pub scroll_threshold: f32,
+    pub screenshot_key: Option<String>,
+    pub splash_path: Option<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Event {
     Load,
-    Button { button: String, transition: String },
-    Scroll { x: i32, y: i32 },
-    Text { text: String },
-    Step { delta_time: f64 },
+    Button {
+        button: String,
+        transition: String,
+        modifiers: Modifiers,
+    },
+    Scroll {
+        x: i32,
+        y: i32,
+    },
+    Text {
+        text: String,
+    },
+    Step {
+        delta_time: f64,
+    },
+    Network {
+        handle: u32,
+        data: Vec<u8>,
+    },
+    Focus {
+        focused: bool,
+        minimized: bool,
+    },
+    CloseRequested,
+    WindowClosed {
+        window: u32,
+    },
     Exit,
 }
 
+/// Modifier keys held at the moment an event arrived.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
 impl Event {
     pub fn type_str(&self) -> &str {
         match self {
@@ -35,28 +102,117 @@ impl Event {
             Self::Scroll { .. } => "SCROLL",
             Self::Text { .. } => "TEXT",
             Self::Step { .. } => "STEP",
+            Self::Network { .. } => "NETWORK",
+            Self::Focus { .. } => "FOCUS",
+            Self::CloseRequested => "CLOSE_REQUESTED",
+            Self::WindowClosed { .. } => "WINDOW_CLOSED",
             Self::Exit => "EXIT",
         }
     }
 }
 
+struct Animation {
+    frames: Vec<String>,
+    fps: f64,
+}
+
+impl Animation {
+    fn frame_at(&self, time: f64) -> &str {
+        if self.frames.is_empty() {
+            return "none";
+        }
+
+        let frame_index = if self.fps > 0. {
+            (time * self.fps) as usize % self.frames.len()
+        } else {
+            0
+        };
+
+        &self.frames[frame_index]
+    }
+}
+
+/// How long each phase of the last frame took.
+#[derive(Clone, Copy, Default)]
+pub struct FrameTimings {
+    pub events: Duration,
+    pub step: Duration,
+    pub render: Duration,
+}
+
+struct AnimatedTile {
+    animation: String,
+    color: (u8, u8, u8),
+}
+
+/// A window that isn't active, waiting to be swapped back in by set_active_window.
+struct InactiveWindow {
+    config: Config,
+    graphics_context: graphics::Context,
+    animated_tiles: HashMap<(i32, i32), AnimatedTile>,
+}
+
 pub struct Engine {
+    // the configuration, graphics and animated tiles of the active window, the API functions
+    // work on whichever window is active.
     config: Option<Config>,
-    resources: Box<dyn resources::Provider>,
+    resources: Arc<dyn resources::Provider>,
     platform: Platform,
     graphics_context: Option<graphics::Context>,
+    active_window: u32,
+    inactive_windows: HashMap<u32, InactiveWindow>,
+    next_window: u32,
     audio: audio::AudioServer,
+    resource_loads: loader::BackgroundLoads,
+    #[cfg(feature = "network")]
+    http_requests: loader::BackgroundLoads,
+    sockets: socket::Sockets,
+    animations: HashMap<String, Animation>,
+    actions: HashMap<String, Vec<String>>,
+    animated_tiles: HashMap<(i32, i32), AnimatedTile>,
+    animation_time: f64,
+    frame_timings: FrameTimings,
+    recent_delta_times: VecDeque<f64>,
+    scene_statistics: graphics::SceneStatistics,
+    start_time: Instant,
+    last_frame_time: Option<Instant>,
+    frame_delta_time: Duration,
+    disabled_events: HashSet<String>,
+    replay: Option<Replay>,
+    replay_frame: u64,
+    replayed_delta_time: Option<f64>,
     running: bool,
 }
 
 impl Engine {
-    pub fn new(resources: Box<dyn resources::Provider>) -> Self {
+    pub fn new(resources: Arc<dyn resources::Provider>) -> Self {
         Self {
             config: None,
             resources,
             platform: Platform::new(),
             graphics_context: None,
+            active_window: MAIN_WINDOW,
+            inactive_windows: HashMap::new(),
+            next_window: MAIN_WINDOW + 1,
             audio: audio::AudioServer::new(),
+            resource_loads: loader::BackgroundLoads::new(),
+            #[cfg(feature = "network")]
+            http_requests: loader::BackgroundLoads::new(),
+            sockets: socket::Sockets::new(),
+            animations: HashMap::new(),
+            actions: HashMap::new(),
+            animated_tiles: HashMap::new(),
+            animation_time: 0.,
+            frame_timings: FrameTimings::default(),
+            recent_delta_times: VecDeque::new(),
+            scene_statistics: graphics::SceneStatistics::default(),
+            start_time: Instant::now(),
+            last_frame_time: None,
+            frame_delta_time: Duration::from_secs(0),
+            disabled_events: HashSet::new(),
+            replay: None,
+            replay_frame: 0,
+            replayed_delta_time: None,
             running: true,
         }
     }
@@ -65,24 +221,402 @@ impl Engine {
         self.running && !self.platform.close_requested
     }
 
-    pub fn load_configuration(&mut self, config: Config) {
+    pub fn get_minimized(&self) -> bool {
+        self.platform.window_minimized
+    }
+
+    /// Real time since this was last called, zero for the first frame.
+    pub fn measure_frame_delta_time(&mut self) -> Duration {
+        let now = Instant::now();
+        self.frame_delta_time = self
+            .last_frame_time
+            .map_or(Duration::from_secs(0), |last_frame_time| {
+                now - last_frame_time
+            });
+        self.last_frame_time = Some(now);
+
+        self.frame_delta_time
+    }
+
+    /// The real time measured at the start of the current frame.
+    pub fn get_frame_delta_time(&self) -> Duration {
+        self.frame_delta_time
+    }
+
+    pub fn get_precise_frame_pacing(&self) -> bool {
+        self.config
+            .as_ref()
+            .map_or(false, |config| config.precise_frame_pacing)
+    }
+
+    /// Clamp the measured frame time to max_delta_time and average it over the last
+    /// delta_time_smoothing frames, so a single long frame doesn't move objects through walls.
+    pub fn smooth_delta_time(&mut self, delta_time: f64) -> f64 {
+        let (max_delta_time, smoothing) = self.config.as_ref().map_or((0., 1), |config| {
+            (config.max_delta_time, config.delta_time_smoothing.max(1))
+        });
+
+        let delta_time = if max_delta_time > 0. {
+            delta_time.min(max_delta_time)
+        } else {
+            delta_time
+        };
+
+        self.recent_delta_times.push_back(delta_time);
+        while self.recent_delta_times.len() > smoothing as usize {
+            self.recent_delta_times.pop_front();
+        }
+
+        self.recent_delta_times.iter().sum::<f64>() / self.recent_delta_times.len() as f64
+    }
+
+    /// Write the input of every frame to a file, to be played back with play_replay.
+    pub fn record_replay(&mut self, path: &str) {
+        match Replay::record(path) {
+            Ok(replay) => {
+                pyrite_log!("Recording replay to \"{}\"", path);
+                self.replay = Some(replay);
+            }
+            Err(e) => pyrite_log!("Failed to record replay to \"{}\": {}", path, e),
+        }
+    }
+
+    /// Take input from a recorded file instead of the player, until the recording runs out.
+    pub fn play_replay(&mut self, path: &str) {
+        match Replay::play(path) {
+            Ok(replay) => {
+                pyrite_log!(
+                    "Playing replay \"{}\", input is ignored until it ends",
+                    path
+                );
+                self.replay = Some(replay);
+                self.platform.set_replaying(true);
+            }
+            Err(e) => pyrite_log!("Failed to play replay \"{}\": {}", path, e),
+        }
+    }
+
+    /// Recordings keep the time step given to the STEP event, and replays give it back, so the
+    /// game sees the same timing as when it was recorded.
+    pub fn replay_delta_time(&mut self, delta_time: f64) -> f64 {
+        let replay = match &mut self.replay {
+            Some(replay) => replay,
+            None => return delta_time,
+        };
+
+        if replay.is_playing() {
+            return self.replayed_delta_time.take().unwrap_or(delta_time);
+        }
+
+        if let Err(e) = replay.record_delta_time(self.replay_frame, delta_time) {
+            pyrite_log!("Failed to write replay, recording stopped: {}", e);
+            self.replay = None;
+        }
+
+        delta_time
+    }
+
+    /// Swap the frame's events for the recorded ones while playing a replay, or write them down
+    /// while recording.
+    fn replay_events(&mut self, events: Vec<(Event, f64)>) -> Vec<(Event, f64)> {
+        self.replay_frame += 1;
+
+        let replay = match &mut self.replay {
+            Some(replay) => replay,
+            None => return events,
+        };
+
+        if replay.is_playing() {
+            return match replay.take_frame(self.replay_frame) {
+                Some(input) => {
+                    if let Some((position, in_window)) = input.mouse {
+                        self.platform.replay_mouse(position, in_window);
+                    }
+                    for (event, _) in &input.events {
+                        self.platform.replay_event(event);
+                    }
+
+                    self.replayed_delta_time = input.delta_time;
+                    input.events
+                }
+                None => {
+                    pyrite_log!("Replay finished, returning to player input");
+                    self.replay = None;
+                    self.platform.set_replaying(false);
+                    events
+                }
+            };
+        }
+
+        let mouse = (
+            self.platform.mouse_position_pixels(),
+            self.platform.mouse_in_window(),
+        );
+        if let Err(e) = replay.record_input(self.replay_frame, mouse, &events) {
+            pyrite_log!("Failed to write replay, recording stopped: {}", e);
+            self.replay = None;
+        }
+
+        events
+    }
+
+    /// Returns false if the engine couldn't be started with the configuration.
+    pub fn load_configuration(&mut self, config: Config) -> bool {
         if self.config.is_none() {
             pyrite_log!("Loading configuration");
             log_config(&config);
+            self.platform
+                .set_scroll_options(config.scroll_pixels_per_line, config.scroll_threshold);
             self.config = Some(config);
 
-            let graphics_context = graphics::Context::new(
+            match graphics::Context::new(
                 self.config.as_ref().unwrap(),
                 &self.platform,
                 &self.resources,
-            );
+                true,
+            ) {
+                Ok(graphics_context) => {
+                    self.platform
+                        .add_window(graphics_context.window_id(), MAIN_WINDOW);
+                    self.graphics_context = Some(graphics_context);
+                }
+                Err(e) => {
+                    pyrite_log!("Failed to start graphics: {}", e);
+                    pyrite_log!(
+                        "Pyrite requires a graphics card and driver supporting {}, updating \
+                         your graphics drivers may fix this problem",
+                        graphics::describe_gl_versions(&self.config.as_ref().unwrap().gl_versions)
+                    );
+                    self.running = false;
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    // API Function
+    /// Returns false if the configuration couldn't be applied, the previous one is kept.
+    pub fn reconfigure(&mut self, config: Config) -> bool {
+        let old_config = match &self.config {
+            Some(old_config) => old_config,
+            None => return self.load_configuration(config),
+        };
+
+        pyrite_log!("Reconfiguring");
+        log_config(&config);
+
+        if config.msaa_samples != old_config.msaa_samples {
+            pyrite_log!("msaa_samples can't be changed while running, restart to apply it");
+        }
+
+        if config.gl_versions != old_config.gl_versions {
+            pyrite_log!("gl_versions can't be changed while running, restart to apply it");
+        }
+
+        if config.transparent != old_config.transparent {
+            pyrite_log!("transparent can't be changed while running, restart to apply it");
+        }
+
+        if (config.scene_width, config.scene_height)
+            != (old_config.scene_width, old_config.scene_height)
+        {
+            pyrite_log!("The scene size can't be changed while running, restart to apply it");
+        }
+
+        let reload_tileset = config.tileset_path != old_config.tileset_path
+            || config.tileset_width != old_config.tileset_width
+            || config.tileset_height != old_config.tileset_height
+            || config.tile_names != old_config.tile_names
+            || config.tile_animations != old_config.tile_animations
+            || config.legacy_tile_detection != old_config.legacy_tile_detection
+            || config.tile_filter != old_config.tile_filter;
+
+        if let Some(context) = self.graphics_context.as_mut() {
+            if let Err(e) = context.reconfigure(&config, &self.resources, reload_tileset) {
+                pyrite_log!(
+                    "Failed to reconfigure, keeping previous configuration: {}",
+                    e
+                );
+                return false;
+            }
+        }
+
+        self.platform
+            .set_scroll_options(config.scroll_pixels_per_line, config.scroll_threshold);
+        self.config = Some(config);
+
+        // scene tiles hold positions in the old tileset, and tile animations their index in it.
+        if reload_tileset {
+            self.clear_tiles();
+        }
+
+        true
+    }
+
+    // API Function
+    /// Open another window from a configuration, returning its number or None if it couldn't be
+    /// created. The window has its own tileset, scene and viewport.
+    pub fn create_window(&mut self, mut config: Config) -> Option<u32> {
+        if self.graphics_context.is_none() {
+            pyrite_log!("Windows can only be created once the game's window has been created");
+            return None;
+        }
+
+        // the splash only covers the game starting.
+        config.splash_path = None;
+
+        pyrite_log!("Creating window {}", self.next_window);
+        let graphics_context =
+            graphics::Context::new(&config, &self.platform, &self.resources, false);
+
+        // the new window's graphics context was made current, gl calls belong to the active one.
+        self.make_active_window_current();
+
+        let graphics_context = match graphics_context {
+            Ok(graphics_context) => graphics_context,
+            Err(e) => {
+                pyrite_log!("Failed to create window: {}", e);
+                return None;
+            }
+        };
+
+        let window = self.next_window;
+        self.next_window += 1;
+
+        self.platform
+            .add_window(graphics_context.window_id(), window);
+        self.inactive_windows.insert(
+            window,
+            InactiveWindow {
+                config,
+                graphics_context,
+                animated_tiles: HashMap::new(),
+            },
+        );
+
+        Some(window)
+    }
+
+    // API Function
+    /// Returns false if there's no window with the number.
+    pub fn set_active_window(&mut self, window: u32) -> bool {
+        if !self.swap_active_window(window) {
+            return false;
+        }
+
+        self.make_active_window_current();
+        self.platform.set_active_window(window);
+
+        true
+    }
+
+    // API Function
+    pub fn get_active_window(&self) -> u32 {
+        self.active_window
+    }
+
+    // API Function
+    /// Returns false if there's no window with the number, or it's the main window.
+    pub fn close_window(&mut self, window: u32) -> bool {
+        if window == MAIN_WINDOW {
+            pyrite_log!("The main window can't be closed, exit() ends the game instead");
+            return false;
+        }
+
+        if window == self.active_window {
+            self.set_active_window(MAIN_WINDOW);
+        }
+
+        let mut closed_window = match self.inactive_windows.remove(&window) {
+            Some(closed_window) => closed_window,
+            None => return false,
+        };
+
+        self.platform.remove_window(window);
+
+        // gl objects are deleted as they're dropped, which has to happen in their own context.
+        closed_window.graphics_context.make_current();
+        mem::drop(closed_window);
+        self.make_active_window_current();
+
+        true
+    }
+
+    /// Swap another window's configuration, graphics and animated tiles in for the active
+    /// window's, without switching graphics contexts. Returns false if there's no such window.
+    fn swap_active_window(&mut self, window: u32) -> bool {
+        if window == self.active_window {
+            return true;
+        }
+
+        let next_window = match self.inactive_windows.remove(&window) {
+            Some(next_window) => next_window,
+            None => return false,
+        };
+
+        // other windows can only be created after the main window, so these are always set.
+        let previous_window = InactiveWindow {
+            config: self
+                .config
+                .replace(next_window.config)
+                .expect("an engine with windows has no configuration"),
+            graphics_context: self
+                .graphics_context
+                .replace(next_window.graphics_context)
+                .expect("an engine with windows has no graphics context"),
+            animated_tiles: mem::replace(&mut self.animated_tiles, next_window.animated_tiles),
+        };
+
+        self.inactive_windows
+            .insert(self.active_window, previous_window);
+        self.active_window = window;
+
+        true
+    }
+
+    fn make_active_window_current(&mut self) {
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.make_current();
+        }
+    }
 
-            self.graphics_context = Some(graphics_context);
+    fn window_graphics_context(&mut self, window: u32) -> Option<&mut graphics::Context> {
+        if window == self.active_window {
+            self.graphics_context.as_mut()
+        } else {
+            self.inactive_windows
+                .get_mut(&window)
+                .map(|window| &mut window.graphics_context)
         }
     }
 
     pub fn render(&mut self) -> bool {
-        let frame_presented = self.graphics_context.as_mut().unwrap().present_frame();
+        let frame_presented = match self.graphics_context.as_mut() {
+            Some(context) => {
+                let frame_presented = context.present_frame();
+                // rendering ends the frame, so this covers every tile change made during it.
+                self.scene_statistics = context.take_scene_statistics();
+
+                if let Some(frame) = context.take_captured_frame() {
+                    save_screenshot(&frame);
+                }
+
+                frame_presented
+            }
+            None => false,
+        };
+
+        if !self.inactive_windows.is_empty() {
+            for window in self.inactive_windows.values_mut() {
+                window.graphics_context.make_current();
+                window.graphics_context.present_frame();
+            }
+
+            self.make_active_window_current();
+        }
+
         // The renderer optimises and will sometimes choose not to render or swap buffers.
         // return the value for the game or binding to decide on the best course of action in this
         // case.
@@ -90,146 +624,1328 @@ impl Engine {
     }
 
     // API Function
-    pub fn exit(&mut self) {
-        pyrite_log!("Exit requested");
-        self.running = false;
+    pub fn flush(&mut self) {
+        // a frame is only drawn if something changed, otherwise the last frame is already the
+        // whole scene.
+        self.render();
+
+        if let Some(context) = &self.graphics_context {
+            context.finish();
+        }
     }
 
     // API Function
-    pub fn mouse_position(&mut self) -> (i32, i32) {
-        if let Some(context) = &self.graphics_context {
-            self.platform.mouse_position(
-                context.windowed_context.window().inner_size(),
-                context.get_viewport().clone(),
-            )
-        } else {
-            (0, 0)
+    pub fn request_redraw(&mut self) {
+        if let Some(context) = &mut self.graphics_context {
+            context.request_redraw();
         }
     }
 
     // API Function
-    pub fn set_viewport(&mut self, width: i32, height: i32, scale: i32) {
+    pub fn set_debug_overlay(&mut self, debug_overlay: bool) {
         if let Some(context) = &mut self.graphics_context {
-            context.set_viewport(width, height, scale);
+            context.set_debug_overlay(debug_overlay);
         }
     }
 
-    // API function
-    pub fn clear_tiles(&mut self) {
-        let context = match &mut self.graphics_context {
-            Some(c) => c,
-            _ => return,
-        };
+    // API Function
+    pub fn set_clear_enabled(&mut self, clear_enabled: bool) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_clear_enabled(clear_enabled);
+        }
+    }
 
-        let (viewport_width, viewport_height) = context.get_viewport().get_dimensions();
+    // API Function
+    pub fn get_clear_enabled(&self) -> bool {
+        self.graphics_context
+            .as_ref()
+            .map_or(true, |context| context.get_clear_enabled())
+    }
 
-        for x in 0..viewport_width {
-            for y in 0..viewport_height {
-                context.set_tile(
-                    (x, y),
-                    "none",
-                    (0, 0, 0),
-                    (false, false),
-                    "none",
-                    (0, 0, 0),
-                    (false, false),
-                );
-            }
+    // API Function
+    pub fn set_continuous_rendering(&mut self, continuous_rendering: bool) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_continuous_rendering(continuous_rendering);
         }
     }
 
+    pub fn set_frame_timings(&mut self, frame_timings: FrameTimings) {
+        self.frame_timings = frame_timings;
+    }
+
     // API Function
-    pub fn set_tile(
-        &mut self,
-        position: (i32, i32),
-        front_tile: String,
-        front_color: (u8, u8, u8),
-        front_flip: (bool, bool),
-        back_tile: String,
-        back_color: (u8, u8, u8),
-        back_flip: (bool, bool),
-    ) {
-        if let Some(context) = self.graphics_context.as_mut() {
-            context.set_tile(
-                position,
-                &front_tile,
-                front_color,
-                front_flip,
-                &back_tile,
-                back_color,
-                back_flip,
-            );
-        }
+    pub fn frame_timings(&self) -> FrameTimings {
+        self.frame_timings
     }
 
     // API Function
-    pub fn button_down(&mut self, button: String) -> bool {
-        self.platform.button_down(button)
+    pub fn tiles_changed_last_frame(&self) -> u32 {
+        self.scene_statistics.tiles_changed
     }
 
     // API Function
-    pub fn poll_events(&mut self) -> Vec<Event> {
-        self.platform.service();
-        // eventually will inject other events here such as network api stuff
-        self.platform.poll_events()
+    pub fn upload_region_last_frame(&self) -> (u32, u32) {
+        self.scene_statistics.upload_region
     }
 
     // API Function
-    pub fn resource_read(&mut self, path: String) -> String {
-        self.resources
-            .read_to_string(&path)
-            .unwrap_or(String::new())
+    pub fn app_version(&self) -> String {
+        match &self.config {
+            Some(config) => config.application_version.clone(),
+            None => String::new(),
+        }
     }
 
     // API Function
-    pub fn resource_exists(&self, path: String) -> bool {
-        self.resources.exists(&path)
+    pub fn engine_version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_owned()
     }
 
     // API Function
-    pub fn play_audio(&mut self, path: String) {
-        self.audio.play(&path, &self.resources);
+    pub fn exit(&mut self) {
+        pyrite_log!("Exit requested");
+        self.running = false;
     }
 
     // API Function
-    pub fn stop_audio(&mut self, path: String) {
-        if path == "*" {
-            self.audio.stop_all();
+    pub fn set_event_enabled(&mut self, event_type: String, enabled: bool) {
+        let event_type = event_type.to_uppercase();
+
+        // only events the game can poll for instead can be disabled, the rest either drive the
+        // game or carry data that would be lost.
+        match event_type.as_str() {
+            "BUTTON" | "SCROLL" | "TEXT" | "FOCUS" | "CLOSE_REQUESTED" => (),
+            _ => {
+                pyrite_log!("The {} event can't be disabled", event_type);
+                return;
+            }
+        }
+
+        if enabled {
+            self.disabled_events.remove(&event_type);
         } else {
-            self.audio.stop(&path);
+            self.disabled_events.insert(event_type);
         }
     }
 
     // API Function
-    pub fn pause_audio(&mut self, path: String) {
-        self.audio.pause(&path);
+    pub fn cancel_close(&mut self) {
+        if self.platform.close_requested {
+            pyrite_log!("Window close cancelled");
+            self.platform.close_requested = false;
+        }
     }
 
     // API Function
-    pub fn volume_audio(&mut self, path: String, value: f32) {
-        self.audio.volume(&path, value);
+    pub fn mouse_position(&mut self) -> Option<(i32, i32)> {
+        let context = self.graphics_context.as_ref()?;
+
+        Some(
+            self.platform
+                .mouse_position(context.get_render_region(), context.get_viewport().clone()),
+        )
     }
 
-    pub fn clean(&mut self) {
-        self.graphics_context.take();
-        self.platform.service();
+    // API Function
+    pub fn tile_under_mouse(&mut self) -> Option<(i32, i32)> {
+        let context = self.graphics_context.as_ref()?;
+
+        if !self.platform.mouse_in_window() {
+            return None;
+        }
+
+        let (x, y) = self
+            .platform
+            .mouse_position(context.get_render_region(), context.get_viewport().clone());
+        let (viewport_width, viewport_height) = context.get_viewport().get_dimensions();
+
+        // the cursor may be over the letterbox margins around the scene
+        if x < 0 || y < 0 || x >= viewport_width || y >= viewport_height {
+            return None;
+        }
+
+        let camera = context.get_camera();
+        Some((x + camera.0, y + camera.1))
     }
-}
 
-fn log_config(config: &Config) {
-    macro_rules! log_config_item {
-        ($config:ident, $item:ident) => {
-            pyrite_log!("{}: {:?}", stringify!($item), $config.$item);
-        };
+    // API Function
+    pub fn mouse_position_pixels(&mut self) -> (i32, i32) {
+        self.platform.mouse_position_pixels()
     }
 
-    log_config_item!(config, application_name);
-    log_config_item!(config, application_version);
-    log_config_item!(config, viewport_scale);
-    log_config_item!(config, viewport_width);
-    log_config_item!(config, viewport_height);
-    log_config_item!(config, tileset_width);
-    log_config_item!(config, tileset_height);
-    log_config_item!(config, tileset_path);
-    log_config_item!(config, tile_names);
+    // API Function
+    pub fn mouse_position_normalized(&mut self) -> Option<(f32, f32)> {
+        let context = self.graphics_context.as_ref()?;
+
+        Some(
+            self.platform
+                .mouse_position_normalized(context.window_size()),
+        )
+    }
+
+    // API Function
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_zoom(zoom);
+        }
+    }
+
+    // API Function
+    pub fn get_zoom(&self) -> f32 {
+        self.graphics_context
+            .as_ref()
+            .map_or(1., |context| context.get_viewport().get_zoom())
+    }
+
+    // API Function
+    pub fn set_viewport(&mut self, width: i32, height: i32, scale: i32) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_viewport(width, height, scale);
+        }
+    }
+
+    // API function
+    pub fn clear_tiles(&mut self) {
+        let scene_size = match &self.graphics_context {
+            Some(c) => c.get_scene_size(),
+            _ => return,
+        };
+
+        self.clear_rect((0, 0), scene_size);
+    }
+
+    // API Function
+    pub fn clear_viewport(&mut self) {
+        let (camera, viewport_size) = match &self.graphics_context {
+            Some(c) => (c.get_camera(), c.get_viewport().get_dimensions()),
+            _ => return,
+        };
+
+        self.clear_rect(camera, viewport_size);
+    }
+
+    // API Function
+    pub fn clear_layer(&mut self, layer: String) {
+        let layer = match parse_layer(&layer) {
+            Some(layer) => layer,
+            None => return,
+        };
+
+        let context = match &mut self.graphics_context {
+            Some(c) => c,
+            _ => return,
+        };
+
+        // animations only draw on the front layer, they'd redraw a cleared tile on the next frame.
+        if let graphics::Layer::Front = layer {
+            let camera = context.get_camera();
+            let (viewport_width, viewport_height) = context.get_viewport().get_dimensions();
+
+            self.animated_tiles.retain(|(x, y), _| {
+                *x < camera.0
+                    || *x >= camera.0 + viewport_width
+                    || *y < camera.1
+                    || *y >= camera.1 + viewport_height
+            });
+        }
+
+        context.clear_layer(layer);
+    }
+
+    // API Function
+    pub fn fill_rect(
+        &mut self,
+        position: (i32, i32),
+        size: (i32, i32),
+        tile: String,
+        color: (u8, u8, u8),
+    ) {
+        self.set_rect(position, size, &tile, color, "none", (0, 0, 0));
+    }
+
+    // API Function
+    pub fn clear_rect(&mut self, position: (i32, i32), size: (i32, i32)) {
+        self.set_rect(position, size, "none", (0, 0, 0), "none", (0, 0, 0));
+    }
+
+    fn set_rect(
+        &mut self,
+        position: (i32, i32),
+        size: (i32, i32),
+        front_tile: &str,
+        front_color: (u8, u8, u8),
+        back_tile: &str,
+        back_color: (u8, u8, u8),
+    ) {
+        let context = match &mut self.graphics_context {
+            Some(c) => c,
+            _ => return,
+        };
+
+        let (scene_width, scene_height) = context.get_scene_size();
+
+        // clip the rectangle to the scene, so large regions don't waste time on tiles that
+        // would be rejected anyway.
+        let left = position.0.max(0);
+        let top = position.1.max(0);
+        let right = (position.0 + size.0).min(scene_width);
+        let bottom = (position.1 + size.1).min(scene_height);
+
+        for x in left..right {
+            for y in top..bottom {
+                self.animated_tiles.remove(&(x, y));
+                context.set_tile(
+                    (x, y),
+                    front_tile,
+                    front_color,
+                    (false, false),
+                    back_tile,
+                    back_color,
+                    (false, false),
+                    graphics::DEFAULT_TILE_DEPTHS,
+                );
+            }
+        }
+    }
+
+    // API Function
+    pub fn set_palette(&mut self, start_index: usize, colors: Vec<(u8, u8, u8)>) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_palette(start_index, &colors);
+        }
+    }
+
+    // API Function
+    pub fn clear_palette(&mut self) {
+        if let Some(context) = &mut self.graphics_context {
+            context.clear_palette();
+        }
+    }
+
+    // API Function
+    pub fn renderer_info(&self) -> Option<graphics::RendererInfo> {
+        self.graphics_context
+            .as_ref()
+            .map(|context| context.get_renderer_info().clone())
+    }
+
+    // API Function
+    pub fn tileset_pixels(&self) -> Option<(u32, u32, Vec<u8>)> {
+        self.graphics_context.as_ref().map(|context| {
+            let (width, height, pixels) = context.get_tileset_pixels();
+            (width, height, pixels.to_vec())
+        })
+    }
+
+    // API Function
+    pub fn tileset_info(&self) -> Option<graphics::TilesetInfo> {
+        self.graphics_context
+            .as_ref()
+            .map(|context| context.get_tileset_info())
+    }
+
+    // API Function
+    pub fn require_tiles(&self, tile_names: Vec<String>) -> Vec<String> {
+        // every tile is missing until the tileset has been loaded
+        let context = match &self.graphics_context {
+            Some(context) => context,
+            None => return tile_names,
+        };
+
+        tile_names
+            .into_iter()
+            .filter(|tile_name| !context.has_tile(tile_name))
+            .collect()
+    }
+
+    // API Function
+    pub fn set_camera(&mut self, position: (i32, i32)) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_camera(position);
+        }
+    }
+
+    // API Function
+    pub fn get_camera(&self) -> (i32, i32) {
+        match &self.graphics_context {
+            Some(context) => context.get_camera(),
+            None => (0, 0),
+        }
+    }
+
+    // API Function
+    pub fn set_tint(&mut self, tint: (u8, u8, u8, u8)) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_tint(tint);
+        }
+    }
+
+    // API Function
+    pub fn set_cursor_icon(&mut self, name: String) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_cursor_icon(&name);
+        }
+    }
+
+    // API Function
+    pub fn set_resizable(&mut self, resizable: bool) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_resizable(resizable);
+        }
+    }
+
+    // API Function
+    pub fn list_monitors(&self) -> Vec<graphics::MonitorInfo> {
+        match &self.graphics_context {
+            Some(context) => context.list_monitors(),
+            None => Vec::new(),
+        }
+    }
+
+    // API Function
+    pub fn set_monitor(&mut self, index: usize) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_monitor(index);
+        }
+    }
+
+    // API Function
+    pub fn get_window_position(&self) -> Option<(i32, i32)> {
+        self.graphics_context
+            .as_ref()
+            .and_then(|context| context.get_window_position())
+    }
+
+    // API Function
+    pub fn set_window_position(&mut self, position: (i32, i32)) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_window_position(position);
+        }
+    }
+
+    // API Function
+    pub fn center_window(&mut self) {
+        if let Some(context) = &mut self.graphics_context {
+            context.center_window();
+        }
+    }
+
+    // API Function
+    pub fn minimize_window(&mut self) {
+        if let Some(context) = &mut self.graphics_context {
+            context.minimize_window();
+        }
+    }
+
+    // API Function
+    pub fn maximize_window(&mut self) {
+        if let Some(context) = &mut self.graphics_context {
+            context.maximize_window();
+        }
+    }
+
+    // API Function
+    pub fn restore_window(&mut self) {
+        if let Some(context) = &mut self.graphics_context {
+            context.restore_window();
+        }
+    }
+
+    // API Function
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if let Some(context) = &mut self.graphics_context {
+            context.set_fullscreen(fullscreen);
+        }
+    }
+
+    // API Function
+    pub fn set_tile(
+        &mut self,
+        position: (i32, i32),
+        front_tile: String,
+        front_color: (u8, u8, u8),
+        front_flip: (bool, bool),
+        back_tile: String,
+        back_color: (u8, u8, u8),
+        back_flip: (bool, bool),
+        depths: (u8, u8),
+    ) {
+        self.animated_tiles.remove(&position);
+
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.set_tile(
+                position,
+                &front_tile,
+                front_color,
+                front_flip,
+                &back_tile,
+                back_color,
+                back_flip,
+                depths,
+            );
+        }
+    }
+
+    // API Function
+    pub fn set_layer_tile(
+        &mut self,
+        position: (i32, i32),
+        layer: graphics::Layer,
+        tile: String,
+        color: (u8, u8, u8),
+        flip: (bool, bool),
+    ) {
+        // animations only draw on the front layer, so they can keep running over a new back tile.
+        if let graphics::Layer::Front = layer {
+            self.animated_tiles.remove(&position);
+        }
+
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.set_layer_tile(position, layer, &tile, color, flip);
+        }
+    }
+
+    // API Function
+    pub fn set_tile_raw(
+        &mut self,
+        position: (i32, i32),
+        front_index: i32,
+        front_modifiers: (u8, u8, u8, u8),
+        back_index: i32,
+        back_modifiers: (u8, u8, u8, u8),
+    ) -> bool {
+        self.animated_tiles.remove(&position);
+
+        match self.graphics_context.as_mut() {
+            Some(context) => context.set_tile_raw(
+                position,
+                front_index,
+                front_modifiers,
+                back_index,
+                back_modifiers,
+            ),
+            None => false,
+        }
+    }
+
+    // API Function
+    pub fn tint_tile(
+        &mut self,
+        position: (i32, i32),
+        front_color: (u8, u8, u8),
+        back_color: Option<(u8, u8, u8)>,
+    ) {
+        // keep the tint when an animation on the tile advances to its next frame.
+        if let Some(animated_tile) = self.animated_tiles.get_mut(&position) {
+            animated_tile.color = front_color;
+        }
+
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.tint_tile(position, front_color, back_color);
+        }
+    }
+
+    // API Function
+    pub fn get_tile_color(&self, position: (i32, i32), layer: String) -> Option<(u8, u8, u8)> {
+        let layer = parse_layer(&layer)?;
+
+        self.graphics_context
+            .as_ref()
+            .and_then(|context| context.get_tile_color(position, layer))
+    }
+
+    // API Function
+    pub fn draw_debug_text(&mut self, position: (i32, i32), text: String, color: (u8, u8, u8)) {
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.queue_debug_text(position, text, color);
+        }
+    }
+
+    // API Function
+    pub fn upload_pixels(&mut self, size: (i32, i32), data: &[u8]) -> bool {
+        match self.graphics_context.as_mut() {
+            Some(context) => context.upload_pixels(size, data),
+            None => false,
+        }
+    }
+
+    // API Function
+    pub fn clear_pixels(&mut self) {
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.clear_pixels();
+        }
+    }
+
+    // API Function
+    pub fn draw_particles(&mut self, particles: Vec<(f32, f32, String, (u8, u8, u8))>) {
+        if let Some(context) = self.graphics_context.as_mut() {
+            context.draw_particles(&particles);
+        }
+    }
+
+    // API Function
+    pub fn draw_text(&mut self, position: (i32, i32), text: String, color: (u8, u8, u8)) {
+        let glyph_names = match &self.config {
+            Some(config) => &config.glyph_names,
+            None => return,
+        };
+
+        let context = match &mut self.graphics_context {
+            Some(c) => c,
+            _ => return,
+        };
+
+        // the viewport shows the scene from the camera, so its right edge moves along with it.
+        let (viewport_width, _) = context.get_viewport().get_dimensions();
+        let wrap_column = context.get_camera().0 + viewport_width;
+
+        for ((x, y), glyph) in layout_text(&text, position, wrap_column) {
+            // characters without a matching tile are drawn as empty space, rather than leaving
+            // whatever was previously in that cell.
+            let tile = glyph_tile_name(glyph_names, glyph);
+            let tile = if context.has_tile(&tile) {
+                tile
+            } else {
+                "none".to_owned()
+            };
+
+            self.animated_tiles.remove(&(x, y));
+            context.set_tile(
+                (x, y),
+                &tile,
+                color,
+                (false, false),
+                "none",
+                (0, 0, 0),
+                (false, false),
+                graphics::DEFAULT_TILE_DEPTHS,
+            );
+        }
+    }
+
+    // API Function
+    pub fn text_width(&self, text: String) -> i32 {
+        text_width(&text)
+    }
+
+    // API Function
+    pub fn define_animation(&mut self, name: String, frames: Vec<String>, fps: f64) {
+        self.animations.insert(name, Animation { frames, fps });
+    }
+
+    // API Function
+    pub fn set_animated_tile(
+        &mut self,
+        position: (i32, i32),
+        animation: String,
+        color: (u8, u8, u8),
+    ) {
+        self.animated_tiles
+            .insert(position, AnimatedTile { animation, color });
+
+        // show the current frame straight away instead of waiting for the next update.
+        self.update_animations(0.);
+    }
+
+    /// Advance the animation clock and write the current frame of every animated tile.
+    ///
+    /// All animations share the same clock, so every instance of an animation stays in sync.
+    pub fn update_animations(&mut self, delta_time: f64) {
+        self.animation_time += delta_time;
+
+        // animating only changes the scenes, so the windows' graphics contexts aren't made current.
+        let active_window = self.active_window;
+        let windows: Vec<u32> = self.inactive_windows.keys().cloned().collect();
+        for window in windows {
+            self.swap_active_window(window);
+            self.animate_active_window();
+        }
+
+        self.swap_active_window(active_window);
+        self.animate_active_window();
+    }
+
+    fn animate_active_window(&mut self) {
+        let context = match &mut self.graphics_context {
+            Some(c) => c,
+            _ => return,
+        };
+
+        context.set_tile_animation_time(self.animation_time);
+
+        for (position, animated_tile) in &self.animated_tiles {
+            let animation = match self.animations.get(&animated_tile.animation) {
+                Some(a) => a,
+                None => continue,
+            };
+
+            // the scene ignores writes that don't change anything, so this only costs an upload
+            // when the frame actually advances.
+            context.set_layer_tile(
+                *position,
+                graphics::Layer::Front,
+                animation.frame_at(self.animation_time),
+                animated_tile.color,
+                (false, false),
+            );
+        }
+    }
+
+    // API Function
+    pub fn button_down(&mut self, button: String) -> bool {
+        self.platform.button_down(button)
+    }
+
+    // API Function
+    pub fn pressed_buttons(&mut self) -> Vec<String> {
+        self.platform.pressed_buttons()
+    }
+
+    // API Function
+    pub fn button_pressed(&mut self, button: String) -> bool {
+        self.platform.button_pressed(button)
+    }
+
+    // API Function
+    pub fn button_released(&mut self, button: String) -> bool {
+        self.platform.button_released(button)
+    }
+
+    // API Function
+    pub fn load_key_map(&mut self, path: String) -> bool {
+        let source = match self.resources.read_to_string(&path) {
+            Some(source) => source,
+            None => {
+                pyrite_log!("Failed to load key map {}", path);
+                return false;
+            }
+        };
+
+        match parse_key_map(&source) {
+            Ok(actions) => {
+                // actions in the key map replace any existing bindings, others are kept.
+                self.actions.extend(actions);
+                true
+            }
+            Err(e) => {
+                pyrite_log!("Failed to load key map {}: {}", path, e);
+                false
+            }
+        }
+    }
+
+    // API Function
+    pub fn bind_action(&mut self, action: String, button: String) {
+        self.actions.insert(action, vec![button.to_uppercase()]);
+    }
+
+    // API Function
+    pub fn action_down(&mut self, action: String) -> bool {
+        match self.actions.get(&action) {
+            Some(buttons) => buttons
+                .iter()
+                .any(|button| self.platform.button_down(button.clone())),
+            None => false,
+        }
+    }
+
+    // API Function
+    /// Seconds since the engine started, used to timestamp events.
+    pub fn time_since_start(&self) -> f64 {
+        self.start_time.elapsed().as_secs_f64()
+    }
+
+    /// Collect input that arrived while the STEP event was running, so a long frame doesn't leave
+    /// it waiting in the operating system's queue. The events are queued, not dispatched, and are
+    /// raised in arrival order at the start of the next frame.
+    pub fn sample_input(&mut self) {
+        self.platform.clear_button_transitions();
+        self.platform.service();
+    }
+
+    /// Events that arrived since the last poll, along with their arrival time in seconds since the
+    /// engine started.
+    pub fn poll_events(&mut self) -> Vec<(Event, f64)> {
+        // pressed and released only last for the frame the transition happened in.
+        self.platform.clear_button_transitions();
+        self.platform.service();
+
+        let scale_factor_changes: Vec<(u32, f64)> =
+            self.platform.scale_factor_changes.drain().collect();
+        for (window, scale_factor) in scale_factor_changes {
+            if let Some(context) = self.window_graphics_context(window) {
+                context.set_scale_factor(scale_factor);
+            }
+        }
+
+        let screenshot_key = self
+            .config
+            .as_ref()
+            .and_then(|config| config.screenshot_key.clone());
+        if let Some(screenshot_key) = screenshot_key {
+            if self.platform.button_pressed(screenshot_key) {
+                if let Some(context) = self.graphics_context.as_mut() {
+                    context.request_capture();
+                }
+            }
+        }
+
+        let resized_windows: Vec<u32> = self.platform.resized_windows.drain().collect();
+        for window in resized_windows {
+            if let Some(context) = self.window_graphics_context(window) {
+                context.window_resized();
+            }
+        }
+
+        let start_time = self.start_time;
+        let events: Vec<(Event, f64)> = self
+            .platform
+            .poll_events()
+            .into_iter()
+            .map(|(event, arrival_time)| {
+                let timestamp = arrival_time
+                    .checked_duration_since(start_time)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+
+                (event, timestamp)
+            })
+            .collect();
+
+        let mut events = self.replay_events(events);
+
+        // other windows close as soon as the player asks, only the main window can be kept open.
+        let closed_windows = mem::replace(&mut self.platform.closed_windows, Vec::new());
+        for window in closed_windows {
+            if self.close_window(window) {
+                events.push((Event::WindowClosed { window }, self.time_since_start()));
+            }
+        }
+
+        // button states and the like are already up to date, only the python call is skipped.
+        events.retain(|(event, _)| !self.disabled_events.contains(event.type_str()));
+
+        // socket data is only noticed when polled, so it's stamped with the time it was read.
+        let timestamp = self.time_since_start();
+        events.extend(
+            self.sockets
+                .poll()
+                .into_iter()
+                .map(|(handle, data)| (Event::Network { handle, data }, timestamp)),
+        );
+
+        events
+    }
+
+    // API Function
+    pub fn resource_read(&mut self, path: String) -> Option<String> {
+        self.resources.read_to_string(&path)
+    }
+
+    // API Function
+    pub fn resource_read_bytes(&mut self, path: String) -> Option<Vec<u8>> {
+        self.resources.read_to_bytes(&path)
+    }
+
+    // API Function
+    pub fn resource_exists(&self, path: String) -> bool {
+        self.resources.exists(&path)
+    }
+
+    // API Function
+    pub fn require_resources(&self, paths: Vec<String>) -> Vec<String> {
+        paths
+            .into_iter()
+            .filter(|path| !self.resources.exists(path))
+            .collect()
+    }
+
+    // API Function
+    pub fn load_async(&mut self, path: String) -> u32 {
+        let resources = Arc::clone(&self.resources);
+
+        self.resource_loads
+            .spawn(move || resources.read_to_bytes(&path))
+    }
+
+    // API Function
+    pub fn load_ready(&mut self, handle: u32) -> bool {
+        self.resource_loads.ready(handle)
+    }
+
+    // API Function
+    pub fn load_take(&mut self, handle: u32) -> Option<Vec<u8>> {
+        self.resource_loads.take(handle)
+    }
+
+    // API Function
+    pub fn load_cancel(&mut self, handle: u32) -> bool {
+        self.resource_loads.cancel(handle)
+    }
+
+    // API Function
+    pub fn socket_open(&mut self, address: String, protocol: String, events: bool) -> Option<u32> {
+        self.sockets.open(&address, &protocol, events)
+    }
+
+    // API Function
+    pub fn socket_state(&mut self, handle: u32) -> String {
+        self.sockets.state(handle).to_string()
+    }
+
+    // API Function
+    pub fn socket_send(&mut self, handle: u32, data: &[u8]) -> bool {
+        self.sockets.send(handle, data)
+    }
+
+    // API Function
+    pub fn socket_recv(&mut self, handle: u32) -> Option<Vec<u8>> {
+        self.sockets.receive(handle)
+    }
+
+    // API Function
+    pub fn socket_close(&mut self, handle: u32) {
+        self.sockets.close(handle)
+    }
+
+    // API Function
+    #[cfg(feature = "network")]
+    pub fn http_get_async(&mut self, url: String) -> u32 {
+        self.http_requests.spawn(move || network::http_get(&url))
+    }
+
+    // API Function
+    #[cfg(feature = "network")]
+    pub fn http_ready(&mut self, handle: u32) -> bool {
+        self.http_requests.ready(handle)
+    }
+
+    // API Function
+    #[cfg(feature = "network")]
+    pub fn http_take(&mut self, handle: u32) -> Option<Vec<u8>> {
+        self.http_requests.take(handle)
+    }
+
+    // API Function
+    #[cfg(feature = "network")]
+    pub fn http_cancel(&mut self, handle: u32) -> bool {
+        self.http_requests.cancel(handle)
+    }
+
+    // API Function
+    pub fn play_audio(&mut self, path: String) {
+        self.audio.play(&path, &self.resources);
+    }
+
+    // API Function
+    pub fn play_audio_looping_section(&mut self, path: String, loop_start: f64, loop_end: f64) {
+        self.audio
+            .play_looping_section(&path, loop_start, loop_end, &self.resources);
+    }
+
+    // API Function
+    pub fn reinitialize_audio(&mut self) -> bool {
+        self.audio.reinitialize()
+    }
+
+    // API Function
+    pub fn audio_available(&self) -> bool {
+        self.audio.is_available()
+    }
+
+    // API Function
+    pub fn play_audio_bytes(&mut self, name: String, data: Vec<u8>) {
+        self.audio.play_bytes(&name, data);
+    }
+
+    // API Function
+    pub fn stop_audio(&mut self, path: String) {
+        if path == "*" {
+            self.audio.stop_all(false);
+        } else {
+            self.audio.stop(&path);
+        }
+    }
+
+    // API Function
+    pub fn unload_audio(&mut self, path: String) {
+        if path == "*" {
+            self.audio.stop_all(true);
+        } else {
+            self.audio.unload(&path);
+        }
+    }
+
+    // API Function
+    pub fn pause_audio(&mut self, path: String) {
+        self.audio.pause(&path);
+    }
+
+    // API Function
+    pub fn volume_audio(&mut self, path: String, value: f32) {
+        self.audio.volume(&path, value);
+    }
+
+    // API Function
+    pub fn audio_position(&self, path: String) -> Option<f64> {
+        self.audio.position(&path)
+    }
+
+    // API Function
+    pub fn audio_duration(&self, path: String) -> Option<f64> {
+        self.audio.duration(&path)
+    }
+
+    // API Function
+    pub fn audio_tracks(&self) -> Vec<(String, String)> {
+        self.audio.tracks()
+    }
+
+    // API Function
+    pub fn audio_formats_supported(&self) -> Vec<String> {
+        audio::supported_formats()
+    }
+
+    // API Function
+    pub fn pan_audio(&mut self, path: String, value: f32) {
+        self.audio.pan(&path, value);
+    }
+
+    pub fn clean(&mut self) {
+        // gl objects are deleted as they're dropped, each window has to be current for its own.
+        for (window, mut inactive_window) in self.inactive_windows.drain() {
+            self.platform.remove_window(window);
+            inactive_window.graphics_context.make_current();
+        }
+
+        self.make_active_window_current();
+        self.graphics_context.take();
+        self.platform.service();
+    }
+}
+
+/// Map a character to the name of the tile used to draw it.
+///
+/// The configured glyph names take priority, otherwise the tile is expected to be named after the
+/// character itself. Spaces are drawn as empty tiles unless they have been mapped.
+fn glyph_tile_name(glyph_names: &HashMap<String, String>, glyph: char) -> String {
+    let glyph = glyph.to_string();
+
+    match glyph_names.get(&glyph) {
+        Some(tile_name) => tile_name.clone(),
+        None if glyph == " " => "none".to_owned(),
+        None => glyph,
+    }
+}
+
+/// The scene position of every glyph drawn by draw_text, text that reaches wrap_column wraps back
+/// to the starting column.
+fn layout_text(text: &str, position: (i32, i32), wrap_column: i32) -> Vec<((i32, i32), char)> {
+    let (mut x, mut y) = position;
+    let mut glyphs = Vec::new();
+
+    for glyph in text.chars() {
+        if glyph == '\n' {
+            x = position.0;
+            y += 1;
+            continue;
+        }
+
+        // combining marks and joiners belong to the previous character's cell, tilesets can
+        // only show precomposed characters (e.g. "é" rather than "e" followed by U+0301).
+        if is_zero_width(glyph) {
+            continue;
+        }
+
+        if x >= wrap_column {
+            x = position.0;
+            y += 1;
+        }
+
+        glyphs.push(((x, y), glyph));
+        x += 1;
+    }
+
+    glyphs
+}
+
+/// How many cells the longest line of text takes up when drawn with draw_text, ignoring wrapping.
+///
+/// Every glyph is one tile wide, including characters without a matching tile as they're drawn as
+/// empty space.
+fn text_width(text: &str) -> i32 {
+    text.split('\n')
+        .map(|line| line.chars().filter(|glyph| !is_zero_width(*glyph)).count() as i32)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Characters that modify the previous character instead of taking up a cell of their own.
+fn is_zero_width(glyph: char) -> bool {
+    match glyph {
+        // combining diacritical marks and their extensions
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}'
+        // zero width space, non-joiner and joiner
+        | '\u{200B}'..='\u{200D}'
+        // variation selectors, commonly following emoji
+        | '\u{FE00}'..='\u{FE0F}' => true,
+        _ => false,
+    }
+}
+
+/// Parse a layer name given to the bindings, "front" or "back".
+fn parse_layer(layer: &str) -> Option<graphics::Layer> {
+    match layer.to_lowercase().as_str() {
+        "front" => Some(graphics::Layer::Front),
+        "back" => Some(graphics::Layer::Back),
+        _ => {
+            pyrite_log!(
+                "Unknown layer \"{}\", expected \"front\" or \"back\"",
+                layer
+            );
+            None
+        }
+    }
+}
+
+/// Parse a JSON key map, an object binding each action to a button or a list of buttons, such as
+/// {"jump": ["SPACE", "W"], "pause": "ESCAPE"}.
+fn parse_key_map(source: &str) -> Result<HashMap<String, Vec<String>>, String> {
+    let key_map: serde_json::Value = serde_json::from_str(source).map_err(|e| e.to_string())?;
+    let key_map = key_map
+        .as_object()
+        .ok_or_else(|| "the key map should be an object of actions".to_owned())?;
+
+    let mut actions = HashMap::new();
+
+    for (action, buttons) in key_map {
+        let buttons = match buttons {
+            serde_json::Value::String(button) => vec![button.to_uppercase()],
+            serde_json::Value::Array(buttons) => buttons
+                .iter()
+                .map(|button| button.as_str().map(str::to_uppercase))
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(|| format!("the buttons of \"{}\" should be strings", action))?,
+            _ => {
+                return Err(format!(
+                    "\"{}\" should be a button or a list of buttons",
+                    action
+                ))
+            }
+        };
+
+        if buttons.is_empty() {
+            return Err(format!("\"{}\" isn't bound to any buttons", action));
+        }
+
+        actions.insert(action.clone(), buttons);
+    }
+
+    Ok(actions)
+}
+
+/// Write a frame to a timestamped png next to the executable.
+fn save_screenshot(frame: &image::RgbaImage) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let file_name = format!(
+        "screenshot-{}-{:03}.png",
+        timestamp.as_secs(),
+        timestamp.subsec_millis()
+    );
+
+    let path = match env::current_exe() {
+        Ok(executable) => executable.with_file_name(file_name),
+        Err(_) => file_name.into(),
+    };
+
+    match frame.save(&path) {
+        Ok(()) => pyrite_log!("Saved screenshot {}", path.display()),
+        Err(e) => pyrite_log!("Failed to save screenshot {}: {}", path.display(), e),
+    }
+}
+
+fn log_config(config: &Config) {
+    macro_rules! log_config_item {
+        ($config:ident, $item:ident) => {
+            pyrite_log!("{}: {:?}", stringify!($item), $config.$item);
+        };
+    }
+
+    log_config_item!(config, application_name);
+    log_config_item!(config, application_version);
+    log_config_item!(config, viewport_scale);
+    log_config_item!(config, viewport_width);
+    log_config_item!(config, viewport_height);
+    log_config_item!(config, scene_width);
+    log_config_item!(config, scene_height);
+    log_config_item!(config, tileset_width);
+    log_config_item!(config, tileset_height);
+    log_config_item!(config, tileset_path);
+    log_config_item!(config, tile_names);
+    log_config_item!(config, tile_animations);
+    log_config_item!(config, legacy_tile_detection);
+    log_config_item!(config, tile_filter);
+    log_config_item!(config, strict_tile_names);
+    log_config_item!(config, glyph_names);
+    log_config_item!(config, msaa_samples);
+    log_config_item!(config, gl_versions);
+    log_config_item!(config, fullscreen);
+    log_config_item!(config, monitor);
+    log_config_item!(config, letterbox);
+    log_config_item!(config, background_color);
+    log_config_item!(config, transparent);
+    log_config_item!(config, auto_scale);
+    log_config_item!(config, resizable);
+    log_config_item!(config, maintain_aspect_ratio);
+    log_config_item!(config, max_window_scale);
+    log_config_item!(config, precise_frame_pacing);
+    log_config_item!(config, max_delta_time);
+    log_config_item!(config, delta_time_smoothing);
+    log_config_item!(config, scroll_pixels_per_line);
+    log_config_item!(config, scroll_threshold);
+    log_config_item!(config, screenshot_key);
+    log_config_item!(config, splash_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyph_tile_name_keeps_multi_byte_characters() {
+        let glyph_names = HashMap::new();
+
+        assert_eq!(glyph_tile_name(&glyph_names, 'é'), "é");
+        assert_eq!(glyph_tile_name(&glyph_names, '😀'), "😀");
+        assert_eq!(glyph_tile_name(&glyph_names, ' '), "none");
+    }
+
+    #[test]
+    fn glyph_tile_name_prefers_glyph_names() {
+        let mut glyph_names = HashMap::new();
+        glyph_names.insert("😀".to_owned(), "smile".to_owned());
+        glyph_names.insert(" ".to_owned(), "space".to_owned());
+
+        assert_eq!(glyph_tile_name(&glyph_names, '😀'), "smile");
+        assert_eq!(glyph_tile_name(&glyph_names, ' '), "space");
+    }
+
+    #[test]
+    fn combining_marks_and_joiners_are_zero_width() {
+        assert!(is_zero_width('\u{0301}'));
+        assert!(is_zero_width('\u{200D}'));
+        assert!(is_zero_width('\u{FE0F}'));
+
+        assert!(!is_zero_width('e'));
+        assert!(!is_zero_width('é'));
+        assert!(!is_zero_width('😀'));
+    }
+
+    #[test]
+    fn precomposed_and_decomposed_accents_take_one_cell() {
+        assert_eq!(text_width("\u{00E9}"), 1);
+        assert_eq!(text_width("e\u{0301}"), 1);
+        assert_eq!(text_width("caf\u{00E9}"), text_width("cafe\u{0301}"));
+    }
+
+    #[test]
+    fn non_bmp_characters_take_one_cell() {
+        assert_eq!(text_width("😀"), 1);
+        assert_eq!(text_width("a😀b"), 3);
+        assert_eq!(text_width("❤\u{FE0F}"), 1);
+    }
+
+    #[test]
+    fn zwj_sequences_take_a_cell_per_joined_character() {
+        // tiles are looked up one character at a time, so the joiner is dropped and each person
+        // of the family is drawn in its own cell.
+        assert_eq!(text_width("👨\u{200D}👩\u{200D}👧"), 3);
+    }
+
+    #[test]
+    fn text_wraps_at_the_right_edge_of_the_viewport() {
+        let positions: Vec<(i32, i32)> = layout_text("abcd\ne", (1, 0), 3)
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+
+        assert_eq!(positions, vec![(1, 0), (2, 0), (1, 1), (2, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn text_wraps_with_the_camera() {
+        // a 4 tile wide viewport with the camera 10 tiles right shows columns 10 to 13.
+        let positions: Vec<(i32, i32)> = layout_text("abcde", (11, 2), 10 + 4)
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+
+        assert_eq!(positions, vec![(11, 2), (12, 2), (13, 2), (11, 3), (12, 3)]);
+    }
+
+    #[test]
+    fn text_width_is_the_longest_line() {
+        assert_eq!(text_width(""), 0);
+        assert_eq!(text_width("ab\nabcd\nabc"), 4);
+        assert_eq!(text_width("é😀\n"), 2);
+    }
+
+    #[test]
+    fn key_map_binds_buttons_and_lists() {
+        let actions =
+            parse_key_map(r#"{"jump": ["space", "W"], "pause": "Escape", "left": ["A"]}"#).unwrap();
+
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions["jump"], vec!["SPACE", "W"]);
+        assert_eq!(actions["pause"], vec!["ESCAPE"]);
+        assert_eq!(actions["left"], vec!["A"]);
+    }
+
+    #[test]
+    fn key_map_allows_whitespace_and_empty_maps() {
+        let actions = parse_key_map("\n  {\n\n  \"jump\" : \"SPACE\"\n}\n").unwrap();
+        assert_eq!(actions["jump"], vec!["SPACE"]);
+
+        assert!(parse_key_map("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn key_map_duplicate_actions_keep_the_last_binding() {
+        let actions = parse_key_map(r#"{"jump": "SPACE", "jump": "W"}"#).unwrap();
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions["jump"], vec!["W"]);
+    }
+
+    #[test]
+    fn key_map_rejects_malformed_maps() {
+        let malformed = [
+            "",
+            "jump = SPACE",
+            "# comments aren't json\n{}",
+            r#"["SPACE"]"#,
+            r#"{"jump": "SPACE""#,
+            r#"{"jump": 32}"#,
+            r#"{"jump": ["SPACE", 32]}"#,
+            r#"{"jump": []}"#,
+            r#"{"jump": null}"#,
+        ];
+
+        for source in &malformed {
+            assert!(parse_key_map(source).is_err(), "{:?} was parsed", source);
+        }
+    }
+
+    #[test]
+    fn key_map_errors_give_the_location() {
+        let error = parse_key_map("{\n  \"jump\": \"SPACE\",\n}").unwrap_err();
+
+        assert!(error.contains("line 3"), "{}", error);
+    }
 }
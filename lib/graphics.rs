@@ -1,23 +1,29 @@
 use crate::engine;
+use crate::overlay::Overlay;
+use crate::particles::Particles;
 use crate::platform;
 use crate::pyrite_log;
 use crate::resources;
 use gl;
 use gl::types::*;
 use glutin::{
-    dpi::PhysicalSize, window::WindowBuilder, Api, ContextBuilder, GlProfile, GlRequest,
-    PossiblyCurrent, WindowedContext,
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::MonitorHandle,
+    window::{CursorIcon, Fullscreen, Window, WindowBuilder, WindowId},
+    Api, ContextBuilder, CreationError, GlProfile, GlRequest, NotCurrent, PossiblyCurrent,
+    WindowedContext,
 };
 use image::GenericImageView;
-use image::Pixel;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi;
 use std::mem;
 use std::ptr;
-use std::str;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub struct Context {
-    pub windowed_context: WindowedContext<PossiblyCurrent>,
+    // only None while make_current is switching to it.
+    windowed_context: Option<WindowedContext<PossiblyCurrent>>,
     framebuffer_size: PhysicalSize<u32>,
     tileset: Tileset,
     viewport: Viewport,
@@ -25,55 +31,185 @@ pub struct Context {
     quad: Quad,
     shader: Shader,
     pending_render: bool,
+    letterbox: bool,
+    fullscreen: bool,
+    monitor: Option<usize>,
+    background_color: (u8, u8, u8),
+    transparent: bool,
+    render_region: RenderRegion,
+    palette: Palette,
+    screen_tint: (u8, u8, u8, u8),
+    camera: (i32, i32),
+    continuous_rendering: bool,
+    clear_enabled: bool,
+    // frames are drawn here instead of the window while clearing is disabled.
+    frame_target: Option<FrameTarget>,
+    resizable: bool,
+    maintain_aspect_ratio: bool,
+    max_window_scale: Option<i32>,
+    renderer_info: RendererInfo,
+    cursor_icon: CursorIcon,
+    strict_tile_names: bool,
+    reported_tile_names: HashSet<String>,
+    capture_requested: bool,
+    captured_frame: Option<image::RgbaImage>,
+    debug_text: Vec<((i32, i32), String, (u8, u8, u8))>,
+    overlay: Overlay,
+    particles: Particles,
+    pixels_shader: Shader,
+    pixels_texture: Option<Texture>,
+    debug_overlay: bool,
+    last_present_time: Instant,
+    frame_time: f64,
+}
+
+/// The area of the window the scene is drawn into, measured in pixels from the top left.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: i32,
+}
+
+/// A display the window can be shown on.
+pub struct MonitorInfo {
+    pub name: String,
+    pub size: (u32, u32),
+    pub position: (i32, i32),
+    pub scale_factor: f64,
+    pub primary: bool,
+}
+
+/// Tileset metadata for games that need to inspect the loaded tileset.
+pub struct TilesetInfo {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub set_width: u32,
+    pub set_height: u32,
+    pub tile_names: Vec<String>,
+}
+
+/// Details of the gl driver, collected when the context is created.
+#[derive(Clone, Debug)]
+pub struct RendererInfo {
+    pub gl_version: String,
+    pub gpu: String,
+    pub glsl_version: String,
+}
+
+/// How much of the scene changed since statistics were last taken.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SceneStatistics {
+    /// Number of tiles that were set to something different.
+    pub tiles_changed: u32,
+    /// Size in tiles of the region uploaded to the graphics card, covering every changed tile.
+    pub upload_region: (u32, u32),
 }
 
 impl Context {
+    /// Open a window with its own graphics context, which is left current.
+    ///
+    /// Only one window should wait for the display to refresh before presenting, each window that
+    /// waits would divide the frame rate.
     pub fn new(
         config: &engine::Config,
         platform: &platform::Platform,
-        resources: &Box<dyn resources::Provider>,
-    ) -> Self {
+        resources: &Arc<dyn resources::Provider>,
+        vsync: bool,
+    ) -> Result<Self, String> {
         let window_builder = WindowBuilder::new()
             .with_title(&config.application_name)
             .with_visible(false)
-            .with_resizable(false);
+            .with_resizable(config.resizable)
+            .with_transparent(config.transparent);
 
         pyrite_log!("Loading graphics context");
+        let msaa_samples = validate_msaa_samples(config.msaa_samples);
+        for gl_version in &config.gl_versions {
+            if parse_gl_version(gl_version)
+                .filter(GlVersion::is_supported)
+                .is_none()
+            {
+                pyrite_log!(
+                    "Skipping gl version \"{}\", expected GL 3.3 or newer, or GLES 3.0 or newer",
+                    gl_version
+                );
+            }
+        }
+        let gl_versions = parse_gl_versions(&config.gl_versions);
+
+        // try each requested version in order until the driver accepts one
+        let mut windowed_context = None;
+        let mut last_error = None;
+
+        for gl_version in gl_versions {
+            match build_windowed_context(
+                window_builder.clone(),
+                platform,
+                gl_version,
+                msaa_samples,
+                vsync,
+            ) {
+                Ok(context) => {
+                    pyrite_log!("Created {} graphics context", gl_version);
+                    windowed_context = Some(context);
+                    break;
+                }
+                Err(e) => {
+                    pyrite_log!("Failed to create {} graphics context: {}", gl_version, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        let windowed_context = windowed_context.ok_or_else(|| {
+            format!(
+                "graphics context initialisation failed: {}",
+                last_error.expect("at least one gl version is always requested")
+            )
+        })?;
         let windowed_context = unsafe {
-            ContextBuilder::new()
-                .with_gl(GlRequest::Specific(Api::OpenGl, (3, 3)))
-                .with_gl_profile(GlProfile::Core)
-                .with_vsync(true)
-                .build_windowed(
-                    window_builder,
-                    &platform
-                        .events
-                        .as_ref()
-                        .expect("failed to build window as platform didn't provide an events loop"),
-                )
-                .expect("graphics context initialisation failed")
+            windowed_context
                 .make_current()
-                .expect("failed to access graphics context")
+                .map_err(|(_, e)| format!("failed to access graphics context: {:?}", e))?
         };
 
         gl::load_with(|s| windowed_context.get_proc_address(s) as *const _);
 
-        gl_log_info();
+        let renderer_info = gl_log_info();
+        gl_check_error("context creation");
 
         pyrite_log!("Loading tileset...");
-        let tileset = Tileset::from_config(config, resources);
+        let tileset = Tileset::from_config(config, resources)?;
+
+        pyrite_log!("Loading scene...");
+        let scene = Scene::new((config.scene_width, config.scene_height))?;
 
         pyrite_log!("Building viewport...");
-        let viewport = Viewport::new(
+        let mut viewport = Viewport::new(
             config.viewport_width,
             config.viewport_height,
             config.viewport_scale,
+            scene.size,
         );
+        viewport.set_auto_scale(config.auto_scale, windowed_context.window().scale_factor());
         let framebuffer_size = viewport.get_framebuffer_size(tileset.get_tile_dimensions_i32());
-        windowed_context.window().set_inner_size(framebuffer_size);
+        let window = windowed_context.window();
+        let monitor = match config.monitor {
+            Some(index) => select_monitor(window, index),
+            None => window.current_monitor(),
+        };
+        if config.fullscreen {
+            window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        } else {
+            window.set_inner_size(framebuffer_size);
 
-        pyrite_log!("Loading scene...");
-        let scene = Scene::new();
+            if config.monitor.is_some() {
+                center_window_on(window, &monitor);
+            }
+        }
 
         let quad = Quad::new();
 
@@ -81,17 +217,46 @@ impl Context {
         let shader = Shader::new(
             include_str!("pass_through.vert"),
             include_str!("pixel_render.frag"),
-        );
+        )?;
+
+        let palette = Palette::new()?;
+
+        let overlay = Overlay::new()?;
+        let particles = Particles::new()?;
+        let pixels_shader = Shader::new(
+            include_str!("pass_through.vert"),
+            include_str!("pixels.frag"),
+        )?;
 
         let pending_render = true;
 
+        let render_region = RenderRegion {
+            x: 0,
+            y: 0,
+            width: framebuffer_size.width,
+            height: framebuffer_size.height,
+            scale: viewport.get_scale(),
+        };
+
         pyrite_log!("Graphics context created");
 
+        let minimum_window_size =
+            viewport.get_minimum_framebuffer_size(tileset.get_tile_dimensions_i32());
+        windowed_context
+            .window()
+            .set_min_inner_size(Some(minimum_window_size));
+        windowed_context
+            .window()
+            .set_max_inner_size(viewport.get_maximum_framebuffer_size(
+                tileset.get_tile_dimensions_i32(),
+                config.max_window_scale,
+            ));
+
         // only show the window after everything is set-up and the framebuffer size as been set.
         windowed_context.window().set_visible(true);
 
-        Context {
-            windowed_context,
+        let mut context = Context {
+            windowed_context: Some(windowed_context),
             framebuffer_size,
             tileset,
             viewport,
@@ -99,7 +264,135 @@ impl Context {
             quad,
             shader,
             pending_render,
+            letterbox: config.letterbox,
+            fullscreen: config.fullscreen,
+            monitor: config.monitor,
+            background_color: config.background_color,
+            transparent: config.transparent,
+            render_region,
+            palette,
+            screen_tint: (255, 255, 255, 255),
+            camera: (0, 0),
+            continuous_rendering: false,
+            clear_enabled: true,
+            frame_target: None,
+            resizable: config.resizable,
+            maintain_aspect_ratio: config.maintain_aspect_ratio,
+            max_window_scale: config.max_window_scale,
+            renderer_info,
+            cursor_icon: CursorIcon::Default,
+            strict_tile_names: config.strict_tile_names,
+            reported_tile_names: HashSet::new(),
+            capture_requested: false,
+            captured_frame: None,
+            debug_text: Vec::new(),
+            overlay,
+            particles,
+            pixels_shader,
+            pixels_texture: None,
+            debug_overlay: false,
+            last_present_time: Instant::now(),
+            frame_time: 0.,
+        };
+
+        if let Some(splash_path) = &config.splash_path {
+            context.present_splash(splash_path, resources);
+        }
+
+        Ok(context)
+    }
+
+    /// Send gl calls to this window's context. Every window has its own context, so this must be
+    /// called before anything that draws or creates and deletes gl objects for a window that
+    /// isn't current.
+    pub fn make_current(&mut self) {
+        if self.windowed_context().is_current() {
+            return;
+        }
+
+        // make_current takes the context by value, it's taken out and the result put back. Both
+        // success and failure hand the context back.
+        let windowed_context = self
+            .windowed_context
+            .take()
+            .expect("the window has no graphics context");
+        let windowed_context = match unsafe { windowed_context.make_current() } {
+            Ok(windowed_context) => windowed_context,
+            Err((windowed_context, e)) => {
+                pyrite_log!("Failed to switch graphics context: {:?}", e);
+                windowed_context
+            }
+        };
+
+        self.windowed_context = Some(windowed_context);
+    }
+
+    fn windowed_context(&self) -> &WindowedContext<PossiblyCurrent> {
+        self.windowed_context
+            .as_ref()
+            .expect("the window has no graphics context")
+    }
+
+    /// The platform's id for the window, which its events are tagged with.
+    pub fn window_id(&self) -> WindowId {
+        self.windowed_context().window().id()
+    }
+
+    /// The size of the inside of the window in physical pixels.
+    pub fn window_size(&self) -> PhysicalSize<u32> {
+        self.windowed_context().window().inner_size()
+    }
+
+    /// Show an image over the scene until the first frame is rendered, so the window isn't blank
+    /// while the game loads. The first frame is always rendered, which replaces the splash.
+    fn present_splash(&mut self, splash_path: &str, resources: &Arc<dyn resources::Provider>) {
+        let image_bytes = match resources.read_to_bytes(splash_path) {
+            Some(image_bytes) => image_bytes,
+            None => {
+                pyrite_log!("Failed to load splash image {}", splash_path);
+                return;
+            }
+        };
+        let splash_image = match image::load_from_memory(&image_bytes) {
+            Ok(splash_image) => splash_image,
+            Err(e) => {
+                pyrite_log!("Failed to load splash image {}: {}", splash_path, e);
+                return;
+            }
+        };
+        let splash_texture = match Texture::from_image(&splash_image, TextureFilter::Nearest) {
+            Ok(splash_texture) => splash_texture,
+            Err(e) => {
+                pyrite_log!("Failed to load splash image {}: {}", splash_path, e);
+                return;
+            }
+        };
+
+        self.clear_frame();
+        self.update_render_region();
+        self.apply_viewport_framebuffer();
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::ActiveTexture(gl::TEXTURE0);
         }
+        splash_texture.bind();
+
+        // the pixels shader stretches the image over the scene, without zoom or tint.
+        self.pixels_shader.bind();
+        self.pixels_shader.set_uniform_1i("pixels", 0);
+        self.pixels_shader.set_uniform_1f("zoom", 1.);
+        self.pixels_shader
+            .set_uniform_4f("screen_tint", (1., 1., 1., 0.));
+
+        self.quad.draw();
+
+        unsafe { gl::Disable(gl::BLEND) };
+        gl_check_error("drawing splash");
+
+        self.windowed_context().swap_buffers().unwrap();
     }
 
     pub fn set_tile(
@@ -111,9 +404,15 @@ impl Context {
         back_tile: &str,
         back_color: (u8, u8, u8),
         back_flip: (bool, bool),
+        depths: (u8, u8),
     ) {
-        // only allow tiles within the viewport to be changed
-        if self.viewport.contains(position.0, position.1) {
+        if self.strict_tile_names {
+            self.report_unknown_tile_name(front_tile);
+            self.report_unknown_tile_name(back_tile);
+        }
+
+        // tiles anywhere in the scene can be changed, the camera decides which part is shown.
+        if self.scene.contains(position) {
             let scene_changed = self.scene.set_tile(
                 &self.tileset,
                 position,
@@ -123,6 +422,7 @@ impl Context {
                 back_tile,
                 back_color,
                 back_flip,
+                depths,
             );
 
             // Flag that the scene was changed. Because we only render and swap buffers when
@@ -131,132 +431,1204 @@ impl Context {
         }
     }
 
+    /// Set the tileset cells and modifier bytes of both layers directly, for shaders that read the
+    /// modifiers as their own data. Returns false if the position is outside the scene or either
+    /// index is outside the tileset.
+    pub fn set_tile_raw(
+        &mut self,
+        position: (i32, i32),
+        front_index: i32,
+        front_modifiers: (u8, u8, u8, u8),
+        back_index: i32,
+        back_modifiers: (u8, u8, u8, u8),
+    ) -> bool {
+        if !self.scene.contains(position) {
+            return false;
+        }
+
+        let (front_location, back_location) = match (
+            self.tileset.get_index_location(front_index),
+            self.tileset.get_index_location(back_index),
+        ) {
+            (Some(front_location), Some(back_location)) => (front_location, back_location),
+            _ => {
+                pyrite_log!(
+                    "Tileset index {} or {} is outside the tileset",
+                    front_index,
+                    back_index
+                );
+                return false;
+            }
+        };
+
+        let scene_changed = self.scene.set_tile_raw(
+            position,
+            (front_location, front_modifiers),
+            (back_location, back_modifiers),
+        );
+        self.pending_render = self.pending_render || scene_changed;
+
+        true
+    }
+
+    /// Set one layer of a tile, the other layer and the depths are left as they are.
+    pub fn set_layer_tile(
+        &mut self,
+        position: (i32, i32),
+        layer: Layer,
+        tile: &str,
+        color: (u8, u8, u8),
+        flip: (bool, bool),
+    ) {
+        if self.strict_tile_names {
+            self.report_unknown_tile_name(tile);
+        }
+
+        if self.scene.contains(position) {
+            let scene_changed =
+                self.scene
+                    .set_layer_tile(&self.tileset, position, layer, tile, color, flip);
+            self.pending_render = self.pending_render || scene_changed;
+        }
+    }
+
+    /// Change the colors of a tile without changing which tiles it shows or how they're flipped.
+    /// When back_color is None the back layer keeps its color.
+    pub fn tint_tile(
+        &mut self,
+        position: (i32, i32),
+        front_color: (u8, u8, u8),
+        back_color: Option<(u8, u8, u8)>,
+    ) {
+        if self.scene.contains(position) {
+            let scene_changed = self.scene.tint_tile(position, front_color, back_color);
+            self.pending_render = self.pending_render || scene_changed;
+        }
+    }
+
+    /// Queue tiles to be drawn over the scene in the next frame only. Positions are in scene tiles
+    /// and don't have to be whole numbers.
+    pub fn draw_particles(&mut self, particles: &[(f32, f32, String, (u8, u8, u8))]) {
+        let tile_size = self.tileset.get_tile_dimensions_i32();
+        let tile_size = (tile_size.0 as f32, tile_size.1 as f32);
+
+        for (x, y, tile_name, (r, g, b)) in particles {
+            let tile = match tile_name.as_str() {
+                "none" => continue,
+                "fill" => None,
+                _ => match self.tileset.get_frame_location(tile_name) {
+                    Some((tile_x, tile_y)) => Some((tile_x * tile_size.0, tile_y * tile_size.1)),
+                    None => {
+                        if self.strict_tile_names {
+                            self.report_unknown_tile_name(tile_name);
+                        }
+                        continue;
+                    }
+                },
+            };
+
+            // snapped to whole scene pixels, so particles stay as sharp as the tiles beneath them
+            let position = ((x * tile_size.0).floor(), (y * tile_size.1).floor());
+            let color = (*r as f32 / 255., *g as f32 / 255., *b as f32 / 255.);
+
+            self.particles.push(position, tile_size, tile, color);
+        }
+
+        self.pending_render = true;
+    }
+
+    /// Show an RGBA image stretched over the viewport, above both layers of the scene. It stays
+    /// until it's replaced or cleared. Returns false if the data doesn't match the dimensions.
+    pub fn upload_pixels(&mut self, size: (i32, i32), data: &[u8]) -> bool {
+        let (width, height) = size;
+
+        if width <= 0 || height <= 0 || data.len() != width as usize * height as usize * 4 {
+            pyrite_log!(
+                "Pixel data is {} bytes, {}x{} RGBA pixels needs {}",
+                data.len(),
+                width,
+                height,
+                width.max(0) as usize * height.max(0) as usize * 4
+            );
+            return false;
+        }
+
+        let pixels: Vec<(u8, u8, u8, u8)> = data
+            .chunks_exact(4)
+            .map(|pixel| (pixel[0], pixel[1], pixel[2], pixel[3]))
+            .collect();
+
+        match &mut self.pixels_texture {
+            Some(texture) => texture.update_from_vec4_u8(width, height, &pixels),
+            None => match Texture::from_vec4_u8(width, height, &pixels) {
+                Ok(texture) => self.pixels_texture = Some(texture),
+                Err(e) => {
+                    pyrite_log!("Failed to upload pixels: {}", e);
+                    return false;
+                }
+            },
+        }
+
+        self.pending_render = true;
+        true
+    }
+
+    pub fn clear_pixels(&mut self) {
+        if self.pixels_texture.take().is_some() {
+            self.pending_render = true;
+        }
+    }
+
+    /// Queue text in the engine's built-in font to be drawn over the scene in the next frame only.
+    /// The position is in viewport tiles, so it isn't moved by the camera.
+    pub fn queue_debug_text(&mut self, position: (i32, i32), text: String, color: (u8, u8, u8)) {
+        self.debug_text.push((position, text, color));
+        self.pending_render = true;
+    }
+
+    /// Draw the queued debug text at the scale of the scene's pixels, so it lines up with tiles.
+    fn draw_debug_text(&mut self) {
+        let tile_size = self.tileset.get_tile_dimensions_i32();
+        let region = self.render_region;
+        let scale = region.scale as f32;
+
+        for (position, text, (r, g, b)) in self.debug_text.drain(..) {
+            let window_position = (
+                region.x as f32 + (position.0 * tile_size.0) as f32 * scale,
+                region.y as f32 + (position.1 * tile_size.1) as f32 * scale,
+            );
+            let color = (r as f32 / 255., g as f32 / 255., b as f32 / 255., 1.);
+
+            self.overlay.push_text(window_position, scale, &text, color);
+        }
+
+        self.overlay
+            .draw(self.windowed_context().window().inner_size());
+    }
+
+    /// Show the frame of every tileset animation at the clock time, in seconds.
+    pub fn set_tile_animation_time(&mut self, time: f64) {
+        let frames_changed = self.tileset.advance_animations(time);
+        self.pending_render = self.pending_render || frames_changed;
+    }
+
+    /// The color of one layer of a tile, None outside the scene.
+    pub fn get_tile_color(&self, position: (i32, i32), layer: Layer) -> Option<(u8, u8, u8)> {
+        if self.scene.contains(position) {
+            Some(self.scene.get_tile_color(position, layer))
+        } else {
+            None
+        }
+    }
+
+    /// Log a tile name missing from the tileset, each name is only logged once so a typo in a
+    /// frequently set tile doesn't flood the log.
+    fn report_unknown_tile_name(&mut self, tile_name: &str) {
+        if self.tileset.get_tile_location(tile_name).is_some()
+            || self.reported_tile_names.contains(tile_name)
+        {
+            return;
+        }
+
+        pyrite_log!(
+            "Warning: tile \"{}\" isn't in the tileset, check tile_names in __config__",
+            tile_name
+        );
+        self.reported_tile_names.insert(tile_name.to_owned());
+    }
+
+    /// Reset one layer of the tiles shown through the viewport, leaving the other layer as is.
+    pub fn clear_layer(&mut self, layer: Layer) {
+        let (viewport_width, viewport_height) = self.viewport.get_dimensions();
+        let region = (
+            self.camera.0,
+            self.camera.1,
+            self.camera.0 + viewport_width,
+            self.camera.1 + viewport_height,
+        );
+
+        let scene_changed = self.scene.clear_layer(&self.tileset, region, layer);
+        self.pending_render = self.pending_render || scene_changed;
+    }
+
+    /// Magnify the scene about the centre of the viewport without changing the window size. The
+    /// factor can be fractional, below 1.0 is treated as 1.0.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if self.viewport.get_zoom() != zoom.max(1.) {
+            self.viewport.set_zoom(zoom);
+            self.pending_render = true;
+        }
+    }
+
     pub fn set_viewport(&mut self, width: i32, height: i32, scale: i32) {
         self.viewport.set(width, height, scale);
+        // a larger viewport may push the camera past the edge of the scene.
+        self.set_camera(self.camera);
 
         self.framebuffer_size = self
             .viewport
             .get_framebuffer_size(self.tileset.get_tile_dimensions_i32());
 
-        self.windowed_context
+        let minimum_window_size = self
+            .viewport
+            .get_minimum_framebuffer_size(self.tileset.get_tile_dimensions_i32());
+        self.windowed_context()
+            .window()
+            .set_min_inner_size(Some(minimum_window_size));
+
+        let maximum_window_size = self.viewport.get_maximum_framebuffer_size(
+            self.tileset.get_tile_dimensions_i32(),
+            self.max_window_scale,
+        );
+        self.windowed_context()
+            .window()
+            .set_max_inner_size(maximum_window_size);
+
+        // a fullscreen window keeps the size of the monitor, the scene is letterboxed instead.
+        if !self.fullscreen {
+            self.windowed_context()
+                .window()
+                .set_inner_size(self.framebuffer_size);
+        }
+
+        self.pending_render = true;
+    }
+
+    pub fn set_resizable(&mut self, resizable: bool) {
+        self.resizable = resizable;
+        self.windowed_context().window().set_resizable(resizable);
+
+        // a window that can no longer be resized returns to the size of the viewport.
+        if !resizable && !self.fullscreen {
+            self.windowed_context()
+                .window()
+                .set_inner_size(self.framebuffer_size);
+        }
+
+        self.pending_render = true;
+    }
+
+    /// Change the mouse cursor shown over the window, unknown names leave the cursor unchanged.
+    pub fn set_cursor_icon(&mut self, name: &str) {
+        let cursor_icon = match cursor_icon_from_name(name) {
+            Some(cursor_icon) => cursor_icon,
+            None => {
+                pyrite_log!("Unknown cursor icon \"{}\", the cursor is unchanged", name);
+                return;
+            }
+        };
+
+        if cursor_icon != self.cursor_icon {
+            self.cursor_icon = cursor_icon;
+            self.windowed_context()
+                .window()
+                .set_cursor_icon(cursor_icon);
+        }
+    }
+
+    /// Every display the window can be shown on, in the order set_monitor indexes them.
+    pub fn list_monitors(&self) -> Vec<MonitorInfo> {
+        let window = self.windowed_context().window();
+        let primary_monitor = window.primary_monitor();
+
+        window
+            .available_monitors()
+            .map(|monitor| {
+                let size = monitor.size();
+                let position = monitor.position();
+
+                MonitorInfo {
+                    name: monitor.name().unwrap_or_default(),
+                    size: (size.width, size.height),
+                    position: (position.x, position.y),
+                    scale_factor: monitor.scale_factor(),
+                    primary: monitor == primary_monitor,
+                }
+            })
+            .collect()
+    }
+
+    /// Move the window to the centre of a display, or make it the fullscreen display.
+    pub fn set_monitor(&mut self, index: usize) {
+        self.monitor = Some(index);
+
+        let window = self.windowed_context().window();
+        let monitor = select_monitor(window, index);
+
+        if self.fullscreen {
+            window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        } else {
+            center_window_on(window, &monitor);
+        }
+
+        self.pending_render = true;
+    }
+
+    /// Position of the window's top left corner on the desktop in pixels, None on platforms that
+    /// don't allow it such as Wayland.
+    pub fn get_window_position(&self) -> Option<(i32, i32)> {
+        self.windowed_context()
+            .window()
+            .outer_position()
+            .ok()
+            .map(|position| (position.x, position.y))
+    }
+
+    pub fn set_window_position(&mut self, position: (i32, i32)) {
+        self.windowed_context()
+            .window()
+            .set_outer_position(PhysicalPosition::new(position.0, position.1));
+    }
+
+    /// Move the window to the centre of the display it's currently on.
+    pub fn center_window(&mut self) {
+        let window = self.windowed_context().window();
+        center_window_on(window, &window.current_monitor());
+    }
+
+    pub fn minimize_window(&mut self) {
+        self.windowed_context().window().set_minimized(true);
+    }
+
+    /// Only resizable windows can be maximized, a fullscreen window already fills its display.
+    pub fn maximize_window(&mut self) {
+        if self.fullscreen {
+            pyrite_log!("A fullscreen window can't be maximized");
+            return;
+        }
+
+        if !self.resizable {
+            pyrite_log!("A window that isn't resizable can't be maximized");
+            return;
+        }
+
+        self.windowed_context().window().set_maximized(true);
+        self.pending_render = true;
+    }
+
+    /// Return a minimized or maximized window to its regular size.
+    pub fn restore_window(&mut self) {
+        let window = self.windowed_context().window();
+        window.set_minimized(false);
+        window.set_maximized(false);
+        self.pending_render = true;
+    }
+
+    /// Should be called when the window moves to a display with a different scale factor.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.viewport.set_scale_factor(scale_factor);
+
+        let (width, height) = self.viewport.get_dimensions();
+        self.set_viewport(width, height, self.viewport.scale);
+    }
+
+    /// Apply the parts of a new configuration that can be changed while running.
+    ///
+    /// msaa_samples can only be set when the context is created. If the tileset fails to load
+    /// nothing is changed.
+    pub fn reconfigure(
+        &mut self,
+        config: &engine::Config,
+        resources: &Arc<dyn resources::Provider>,
+        reload_tileset: bool,
+    ) -> Result<(), String> {
+        if reload_tileset {
+            // the engine clears the scene afterwards, its tiles hold positions in the old tileset.
+            self.tileset = Tileset::from_config(config, resources)?;
+        }
+
+        self.windowed_context()
             .window()
-            .set_inner_size(self.framebuffer_size);
+            .set_title(&config.application_name);
+
+        self.letterbox = config.letterbox;
+        self.background_color = config.background_color;
+        self.maintain_aspect_ratio = config.maintain_aspect_ratio;
+        self.max_window_scale = config.max_window_scale;
+        self.strict_tile_names = config.strict_tile_names;
+
+        let scale_factor = self.windowed_context().window().scale_factor();
+        self.viewport
+            .set_auto_scale(config.auto_scale, scale_factor);
+
+        if config.monitor != self.monitor {
+            if let Some(index) = config.monitor {
+                self.set_monitor(index);
+            }
+        }
+
+        self.set_resizable(config.resizable);
+        self.set_fullscreen(config.fullscreen);
+        self.set_viewport(
+            config.viewport_width,
+            config.viewport_height,
+            config.viewport_scale,
+        );
+
+        Ok(())
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if self.fullscreen == fullscreen {
+            return;
+        }
+        self.fullscreen = fullscreen;
+
+        let window = self.windowed_context().window();
+        if fullscreen {
+            let monitor = match self.monitor {
+                Some(index) => select_monitor(window, index),
+                None => window.current_monitor(),
+            };
+            window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        } else {
+            window.set_fullscreen(None);
+            window.set_inner_size(self.framebuffer_size);
+        }
+
+        self.pending_render = true;
+    }
+
+    pub fn set_palette(&mut self, start_index: usize, colors: &[(u8, u8, u8)]) {
+        self.palette.set(start_index, colors);
+        self.pending_render = true;
+    }
+
+    pub fn clear_palette(&mut self) {
+        self.palette.clear();
+        self.pending_render = true;
+    }
+
+    /// Move the camera so the tile at position is drawn in the top left corner of the viewport.
+    ///
+    /// The camera is kept within the scene, so the viewport never shows beyond its edges.
+    pub fn set_camera(&mut self, position: (i32, i32)) {
+        let (viewport_width, viewport_height) = self.viewport.get_dimensions();
+        let camera = (
+            position.0.max(0).min(self.scene.size.0 - viewport_width),
+            position.1.max(0).min(self.scene.size.1 - viewport_height),
+        );
+
+        if self.camera != camera {
+            self.camera = camera;
+            self.pending_render = true;
+        }
+    }
+
+    pub fn get_camera(&self) -> (i32, i32) {
+        self.camera
+    }
+
+    /// The size of the scene in tiles, tiles can be set anywhere within it.
+    pub fn get_scene_size(&self) -> (i32, i32) {
+        self.scene.size
+    }
+
+    pub fn set_tint(&mut self, tint: (u8, u8, u8, u8)) {
+        if self.screen_tint != tint {
+            self.screen_tint = tint;
+            self.pending_render = true;
+        }
+    }
+
+    /// Should be called when the window changes size so the scene is redrawn to fit it.
+    pub fn window_resized(&mut self) {
+        self.pending_render = true;
+    }
+
+    /// Flag that a new frame should be rendered, even if the scene hasn't changed.
+    pub fn request_redraw(&mut self) {
+        self.pending_render = true;
+    }
+
+    /// Block until the gpu has finished every command issued so far, including presenting.
+    pub fn finish(&self) {
+        unsafe { gl::Finish() };
+        gl_check_error("finishing");
+    }
+
+    /// Show frame rate and frame time over the scene, the overlay is redrawn every frame.
+    pub fn set_debug_overlay(&mut self, debug_overlay: bool) {
+        self.debug_overlay = debug_overlay;
+        self.pending_render = true;
+    }
+
+    /// When enabled a frame is rendered every time, instead of only when the scene changes.
+    pub fn set_continuous_rendering(&mut self, continuous_rendering: bool) {
+        self.continuous_rendering = continuous_rendering;
+    }
+
+    /// When disabled the scene is drawn into a frame target that's never cleared, blended over the
+    /// previous frame so it shows through transparent parts of the scene.
+    pub fn set_clear_enabled(&mut self, clear_enabled: bool) {
+        self.clear_enabled = clear_enabled;
+        self.pending_render = true;
+
+        if clear_enabled {
+            self.frame_target = None;
+        }
+    }
+
+    pub fn get_clear_enabled(&self) -> bool {
+        self.clear_enabled
+    }
+
+    pub fn get_render_region(&self) -> RenderRegion {
+        self.render_region
+    }
+
+    /// True if the tile name can be drawn, including the built in and animated tiles.
+    pub fn has_tile(&self, tile_name: &str) -> bool {
+        self.tileset.get_tile_location(tile_name).is_some()
+    }
+
+    pub fn get_tileset_info(&self) -> TilesetInfo {
+        self.tileset.get_info()
+    }
+
+    /// The width and height of the tileset image, and its pixels as RGBA bytes row by row.
+    pub fn get_tileset_pixels(&self) -> (u32, u32, &[u8]) {
+        self.tileset.get_pixels()
+    }
+
+    pub fn get_renderer_info(&self) -> &RendererInfo {
+        &self.renderer_info
+    }
+
+    /// Take how much of the scene changed since the last call, resetting the statistics.
+    pub fn take_scene_statistics(&mut self) -> SceneStatistics {
+        mem::replace(&mut self.scene.statistics, SceneStatistics::default())
+    }
+
+    pub fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    /// Render the scene and present that frame to the screen.
+    ///
+    /// Only renders if it has been flagged that the scene content changed, or continuous rendering
+    /// is enabled.
+    ///
+    /// Returns true if a new frame was rendered and presented.
+    pub fn present_frame(&mut self) -> bool {
+        // Only render and swap buffers if there's actually something new to show
+        if !self.pending_render && !self.continuous_rendering && !self.debug_overlay {
+            return false;
+        }
+        self.pending_render = false;
+
+        // the clear covers the whole window, so any letterbox margins show the background color.
+        self.clear_frame();
+
+        // ensure frame buffer is the correct size before rendering.
+        // Sometimes the platform doesn't keep up and might not have resized the buffer yet.
+        self.update_render_region();
+
+        // what the window's buffers hold after a swap is up to the driver, so frames that keep the
+        // previous one are drawn into a target of their own and copied to the window.
+        let keep_previous_frame = !self.clear_enabled && self.bind_frame_target();
+        if !keep_previous_frame {
+            self.apply_viewport_framebuffer();
+        }
+
+        self.scene.upload();
+        gl_check_error("scene upload");
+
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+        self.tileset.texture.bind();
+        unsafe { gl::ActiveTexture(gl::TEXTURE1) };
+        self.scene.tiles_texture.bind();
+        unsafe { gl::ActiveTexture(gl::TEXTURE2) };
+        self.scene.front_tiles_modifiers_texture.bind();
+        unsafe { gl::ActiveTexture(gl::TEXTURE3) };
+        self.scene.back_tiles_modifiers_texture.bind();
+        unsafe { gl::ActiveTexture(gl::TEXTURE5) };
+        self.scene.tiles_depths_texture.bind();
+
+        self.tileset.upload_animation_frames();
+        unsafe { gl::ActiveTexture(gl::TEXTURE6) };
+        self.tileset.animation_frames_texture.bind();
+
+        self.palette.upload();
+        unsafe { gl::ActiveTexture(gl::TEXTURE4) };
+        self.palette.texture.bind();
+        gl_check_error("texture binding");
+
+        self.shader.bind();
+
+        self.shader
+            .set_uniform_2u("viewport_size", self.viewport.get_dimensions_u32());
+
+        self.shader
+            .set_uniform_2u("tileset_size", self.tileset.get_dimensions_u32());
+
+        // the shader divides this by scale to find the scene size in pixels, which doesn't always
+        // match the render region when the scene is stretched over a resizable window.
+        let tile_size = self.tileset.get_tile_dimensions_i32();
+        let (viewport_width, viewport_height) = self.viewport.get_dimensions();
+        let scale = self.render_region.scale;
+        self.shader.set_uniform_2u(
+            "framebuffer_size",
+            (
+                (viewport_width * tile_size.0 * scale) as u32,
+                (viewport_height * tile_size.1 * scale) as u32,
+            ),
+        );
+
+        self.shader
+            .set_uniform_2i("tile_size", self.tileset.get_tile_dimensions_i32());
+
+        self.shader.set_uniform_2i("camera", self.camera);
+
+        self.shader
+            .set_uniform_1u("scale", self.render_region.scale as u32);
+
+        // set tileset texture to texture unit 0
+        self.shader.set_uniform_1i("tileset", 0);
+        self.shader.set_uniform_1i("scene_tiles", 1);
+        self.shader.set_uniform_1i("front_scene_tiles_modifiers", 2);
+        self.shader.set_uniform_1i("back_scene_tiles_modifiers", 3);
+        self.shader.set_uniform_1i("palette", 4);
+        self.shader.set_uniform_1i("scene_tiles_depths", 5);
+        self.shader.set_uniform_1i("tileset_animations", 6);
+        self.shader
+            .set_uniform_1i("palette_enabled", self.palette.enabled as i32);
+        self.shader
+            .set_uniform_1i("transparent", self.transparent as i32);
+        self.shader.set_uniform_1i(
+            "linear_filtering",
+            (self.tileset.filter == TextureFilter::Linear) as i32,
+        );
+
+        let (r, g, b, a) = self.screen_tint;
+        let screen_tint = (
+            r as f32 / 255.,
+            g as f32 / 255.,
+            b as f32 / 255.,
+            a as f32 / 255.,
+        );
+        self.shader.set_uniform_4f("screen_tint", screen_tint);
+        self.shader.set_uniform_1f("zoom", self.viewport.get_zoom());
+
+        gl_check_error("setting uniforms");
+
+        // the previous frame is kept wherever the scene is transparent, for trail effects.
+        if keep_previous_frame {
+            unsafe {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
+        }
+
+        self.quad.draw();
+
+        if keep_previous_frame {
+            unsafe { gl::Disable(gl::BLEND) };
+        }
+        gl_check_error("drawing scene");
+
+        if let Some(pixels_texture) = &self.pixels_texture {
+            unsafe {
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+            pixels_texture.bind();
+
+            self.pixels_shader.bind();
+            self.pixels_shader.set_uniform_1i("pixels", 0);
+            self.pixels_shader
+                .set_uniform_1f("zoom", self.viewport.get_zoom());
+            self.pixels_shader
+                .set_uniform_4f("screen_tint", screen_tint);
+
+            self.quad.draw();
+
+            unsafe { gl::Disable(gl::BLEND) };
+            gl_check_error("drawing pixels");
+        }
 
+        if !self.particles.is_empty() {
+            self.particles.draw(
+                &self.tileset.texture,
+                (
+                    (viewport_width * tile_size.0) as f32,
+                    (viewport_height * tile_size.1) as f32,
+                ),
+                (
+                    (self.camera.0 * tile_size.0) as f32,
+                    (self.camera.1 * tile_size.1) as f32,
+                ),
+                self.viewport.get_zoom(),
+                screen_tint,
+            );
+            gl_check_error("drawing particles");
+
+            // particles only last one frame, render again so they disappear if the game doesn't
+            // draw them again.
+            self.pending_render = true;
+        }
+
+        if keep_previous_frame {
+            if let Some(frame_target) = &self.frame_target {
+                let window_height = self.windowed_context().window().inner_size().height;
+                frame_target.copy_to_window(&self.render_region, window_height);
+                gl_check_error("copying frame target");
+            }
+        }
+
+        // debug text is drawn over the window, so it never leaves trails.
+        if !self.debug_text.is_empty() {
+            self.draw_debug_text();
+            gl_check_error("drawing debug text");
+
+            // like particles, debug text only lasts one frame.
+            self.pending_render = true;
+        }
+
+        // captured before the debug overlay, so it doesn't end up in screenshots.
+        if self.capture_requested {
+            self.capture_requested = false;
+            self.captured_frame = Some(self.read_render_region());
+            gl_check_error("capturing frame");
+        }
+
+        if self.debug_overlay {
+            self.draw_debug_overlay();
+            gl_check_error("drawing debug overlay");
+        }
+
+        self.windowed_context().swap_buffers().unwrap();
+
+        // We rendered a frame, so return true as per the doc comment.
+        return true;
+    }
+
+    /// Capture the scene as it's drawn in the next frame, collect it with take_captured_frame.
+    pub fn request_capture(&mut self) {
+        self.capture_requested = true;
         self.pending_render = true;
     }
 
-    pub fn get_viewport(&self) -> &Viewport {
-        &self.viewport
+    pub fn take_captured_frame(&mut self) -> Option<image::RgbaImage> {
+        self.captured_frame.take()
+    }
+
+    /// Read back the pixels of the scene, without any letterbox margins.
+    fn read_render_region(&self) -> image::RgbaImage {
+        let region = self.render_region;
+        let window_size = self.windowed_context().window().inner_size();
+        let mut pixels = vec![0u8; (region.width * region.height * 4) as usize];
+
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                region.x,
+                // the region is measured from the top, gl from the bottom of the window
+                window_size.height as i32 - region.y - region.height as i32,
+                region.width as i32,
+                region.height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut ffi::c_void,
+            );
+        }
+
+        let image = image::RgbaImage::from_raw(region.width, region.height, pixels)
+            .expect("frame capture buffer has the wrong size");
+
+        // gl rows start at the bottom of the image
+        image::imageops::flip_vertical(&image)
+    }
+
+    fn draw_debug_overlay(&mut self) {
+        // smooth the frame time so the numbers are readable
+        let frame_time = self.last_present_time.elapsed().as_secs_f64();
+        self.last_present_time = Instant::now();
+        self.frame_time = self.frame_time * 0.9 + frame_time * 0.1;
+
+        let fps = if self.frame_time > 0. {
+            1. / self.frame_time
+        } else {
+            0.
+        };
+        let text = format!("FPS {:.0}\n{:.2} MS", fps, self.frame_time * 1000.);
+
+        let scale = 2.;
+        let margin = 4.;
+        let text_size = Overlay::text_size(&text, scale);
+
+        self.overlay.push_rect(
+            (0., 0.),
+            (text_size.0 + margin * 2., text_size.1 + margin * 2.),
+            (0., 0., 0., 0.6),
+        );
+        self.overlay
+            .push_text((margin, margin), scale, &text, (1., 1., 1., 1.));
+
+        self.overlay
+            .draw(self.windowed_context().window().inner_size());
+    }
+
+    /// Work out where in the window the scene should be drawn.
+    ///
+    /// When letterboxing (or fullscreen, or resizable while maintaining aspect ratio) the scene is
+    /// drawn at the largest integer scale that fits the window and centred. A resizable window that
+    /// doesn't maintain aspect ratio stretches the scene over the whole window. Otherwise it's
+    /// drawn at the viewport scale in the top left corner.
+    fn update_render_region(&mut self) {
+        let window_size = self.windowed_context().window().inner_size();
+        let tile_size = self.tileset.get_tile_dimensions_i32();
+        let (viewport_width, viewport_height) = self.viewport.get_dimensions();
+        let scene_width = viewport_width * tile_size.0;
+        let scene_height = viewport_height * tile_size.1;
+
+        let letterboxed =
+            self.letterbox || self.fullscreen || (self.resizable && self.maintain_aspect_ratio);
+
+        if self.resizable && !letterboxed {
+            self.render_region = RenderRegion {
+                x: 0,
+                y: 0,
+                width: window_size.width,
+                height: window_size.height,
+                scale: 1,
+            };
+            return;
+        }
+
+        let scale = if letterboxed {
+            // a fullscreen window can be larger than the maximum window size, the extra space
+            // becomes margin instead of a larger scale.
+            (window_size.width as i32 / scene_width)
+                .min(window_size.height as i32 / scene_height)
+                .min(self.max_window_scale.unwrap_or(i32::max_value()))
+                .max(1)
+        } else {
+            self.viewport.get_scale()
+        };
+
+        let width = (scene_width * scale) as u32;
+        let height = (scene_height * scale) as u32;
+
+        let (x, y) = if letterboxed {
+            (
+                (window_size.width as i32 - width as i32) / 2,
+                (window_size.height as i32 - height as i32) / 2,
+            )
+        } else {
+            // gl draws from the bottom left, so an oversized window leaves the gap at the top.
+            (0, window_size.height as i32 - height as i32)
+        };
+
+        self.render_region = RenderRegion {
+            x,
+            y,
+            width,
+            height,
+            scale,
+        };
+    }
+
+    fn apply_viewport_framebuffer(&self) {
+        let window_size = self.windowed_context().window().inner_size();
+        let region = &self.render_region;
+
+        unsafe {
+            // gl viewport coordinates start at the bottom left of the window.
+            gl::Viewport(
+                region.x,
+                window_size.height as i32 - region.y - region.height as i32,
+                region.width as i32,
+                region.height as i32,
+            );
+        }
     }
 
-    /// Render the scene and present that frame to the screen.
-    ///
-    /// Only renders if it has been flagged that the scene content changed.
-    ///
-    /// Returns true if a new frame was rendered and presented.
-    pub fn present_frame(&mut self) -> bool {
-        // Only render and swap buffers if there's actually something new to show
-        if !self.pending_render {
+    /// Draw into the frame target, making a new one if the render region has changed size. Returns
+    /// false if there's nothing to draw into, the frame is drawn to the window instead.
+    fn bind_frame_target(&mut self) -> bool {
+        let size = (self.render_region.width, self.render_region.height);
+        // a minimised window has nothing to draw into.
+        if size.0 == 0 || size.1 == 0 {
             return false;
         }
-        self.pending_render = false;
 
-        // ensure frame buffer is the correct size before rendering.
-        // Sometimes the platform doesn't keep up and might not have resized the buffer yet.
-        self.apply_viewport_framebuffer();
+        if self.frame_target.as_ref().map(|target| target.size) != Some(size) {
+            // a new target starts from the background, the previous frame can't be stretched to fit.
+            let (r, g, b) = self.background_color;
+            let a = if self.transparent { 0 } else { 255 };
+
+            self.frame_target = match FrameTarget::new(size, (r, g, b, a)) {
+                Ok(frame_target) => Some(frame_target),
+                Err(e) => {
+                    pyrite_log!("Failed to keep the previous frame, clearing instead: {}", e);
+                    self.clear_enabled = true;
+                    return false;
+                }
+            };
+        }
 
-        self.clear_frame();
+        match &self.frame_target {
+            Some(frame_target) => {
+                frame_target.bind();
+                true
+            }
+            None => false,
+        }
+    }
 
-        self.scene.upload();
+    /// Fill the entire window with the background color, including any margins outside the render
+    /// region.
+    ///
+    /// glClear ignores the viewport but is limited by the scissor test, so the scissor test is
+    /// disabled first to guarantee no stale pixels are left in the margins.
+    fn clear_frame(&self) {
+        let (r, g, b) = self.background_color;
+        // zero alpha lets the desktop show through a transparent window
+        let a = if self.transparent { 0. } else { 1. };
 
-        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
-        self.tileset.texture.bind();
-        unsafe { gl::ActiveTexture(gl::TEXTURE1) };
-        self.scene.tiles_texture.bind();
-        unsafe { gl::ActiveTexture(gl::TEXTURE2) };
-        self.scene.front_tiles_modifiers_texture.bind();
-        unsafe { gl::ActiveTexture(gl::TEXTURE3) };
-        self.scene.back_tiles_modifiers_texture.bind();
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(r as f32 / 255., g as f32 / 255., b as f32 / 255., a);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+    }
+}
 
-        self.shader.bind();
+/// An OpenGL api and version a context can be requested with.
+#[derive(Clone, Copy, Debug)]
+struct GlVersion {
+    api: Api,
+    version: (u8, u8),
+}
 
-        self.shader
-            .set_uniform_2u("viewport_size", self.viewport.get_dimensions_u32());
+impl GlVersion {
+    /// The shaders are written for GLSL 3.30, older versions can't run them.
+    fn is_supported(&self) -> bool {
+        match self.api {
+            Api::OpenGl => self.version >= (3, 3),
+            Api::OpenGlEs => self.version >= (3, 0),
+            _ => false,
+        }
+    }
+}
 
-        self.shader
-            .set_uniform_2u("tileset_size", self.tileset.get_dimensions_u32());
+impl std::fmt::Display for GlVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let api = match self.api {
+            Api::OpenGlEs => "GLES",
+            _ => "GL",
+        };
 
-        self.shader.set_uniform_2u(
-            "framebuffer_size",
-            (
-                self.framebuffer_size.width as u32,
-                self.framebuffer_size.height as u32,
-            ),
-        );
+        write!(f, "{} {}.{}", api, self.version.0, self.version.1)
+    }
+}
 
-        self.shader
-            .set_uniform_2i("tile_size", self.tileset.get_tile_dimensions_i32());
+/// Parse the requested gl versions, such as "GL 3.3" or "GLES 3.0". Versions that can't be parsed
+/// or that are too old to run the shaders are skipped, if none remain GL 3.3 is requested.
+fn parse_gl_versions(gl_versions: &[String]) -> Vec<GlVersion> {
+    let parsed: Vec<GlVersion> = gl_versions
+        .iter()
+        .filter_map(|gl_version| parse_gl_version(gl_version).filter(GlVersion::is_supported))
+        .collect();
+
+    if parsed.is_empty() {
+        vec![GlVersion {
+            api: Api::OpenGl,
+            version: (3, 3),
+        }]
+    } else {
+        parsed
+    }
+}
 
-        self.shader
-            .set_uniform_1u("scale", self.viewport.get_scale() as u32);
+/// The gl versions a context is requested with for the configured gl_versions, such as
+/// "GL 4.1 or GL 3.3".
+pub fn describe_gl_versions(gl_versions: &[String]) -> String {
+    parse_gl_versions(gl_versions)
+        .iter()
+        .map(|gl_version| gl_version.to_string())
+        .collect::<Vec<String>>()
+        .join(" or ")
+}
 
-        // set tileset texture to texture unit 0
-        self.shader.set_uniform_1i("tileset", 0);
-        self.shader.set_uniform_1i("scene_tiles", 1);
-        self.shader.set_uniform_1i("front_scene_tiles_modifiers", 2);
-        self.shader.set_uniform_1i("back_scene_tiles_modifiers", 3);
+fn parse_gl_version(gl_version: &str) -> Option<GlVersion> {
+    let mut parts = gl_version.split_whitespace();
 
-        self.quad.draw();
+    let api = match parts.next()?.to_uppercase().as_str() {
+        "GL" => Api::OpenGl,
+        "GLES" => Api::OpenGlEs,
+        _ => return None,
+    };
 
-        self.windowed_context.swap_buffers().unwrap();
+    let mut version = parts.next()?.split('.');
+    let major = version.next()?.parse().ok()?;
+    let minor = version.next().unwrap_or("0").parse().ok()?;
 
-        // We rendered a frame, so return true as per the doc comment.
-        return true;
+    if parts.next().is_some() || version.next().is_some() {
+        return None;
     }
 
-    fn apply_viewport_framebuffer(&self) {
-        unsafe {
-            gl::Viewport(
-                0,
-                0,
-                self.framebuffer_size.width as i32,
-                self.framebuffer_size.height as i32,
+    Some(GlVersion {
+        api,
+        version: (major, minor),
+    })
+}
+
+fn build_windowed_context(
+    window_builder: WindowBuilder,
+    platform: &platform::Platform,
+    gl_version: GlVersion,
+    msaa_samples: u16,
+    vsync: bool,
+) -> Result<WindowedContext<NotCurrent>, CreationError> {
+    match build_windowed_context_with_samples(
+        window_builder.clone(),
+        platform,
+        gl_version,
+        msaa_samples,
+        vsync,
+    ) {
+        // the driver may not support the requested sample count, fall back to no msaa
+        Err(e) if msaa_samples > 0 => {
+            pyrite_log!(
+                "Failed to create graphics context with {} msaa samples, retrying without: {}",
+                msaa_samples,
+                e
             );
+            build_windowed_context_with_samples(window_builder, platform, gl_version, 0, vsync)
         }
+        result => result,
     }
+}
 
-    fn clear_frame(&self) {
-        unsafe {
-            gl::ClearColor(1., 1., 1., 1.);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
+fn build_windowed_context_with_samples(
+    window_builder: WindowBuilder,
+    platform: &platform::Platform,
+    gl_version: GlVersion,
+    msaa_samples: u16,
+    vsync: bool,
+) -> Result<WindowedContext<NotCurrent>, CreationError> {
+    let context_builder = ContextBuilder::new()
+        .with_gl(GlRequest::Specific(gl_version.api, gl_version.version))
+        .with_vsync(vsync)
+        .with_multisampling(msaa_samples);
+
+    // profiles only apply to desktop gl
+    let context_builder = match gl_version.api {
+        Api::OpenGl => context_builder.with_gl_profile(GlProfile::Core),
+        _ => context_builder,
+    };
+
+    context_builder.build_windowed(
+        window_builder,
+        platform
+            .events
+            .as_ref()
+            .expect("failed to build window as platform didn't provide an events loop"),
+    )
+}
+
+/// The monitor at the index in list_monitors order, or the primary monitor if there isn't one.
+fn select_monitor(window: &Window, index: usize) -> MonitorHandle {
+    match window.available_monitors().nth(index) {
+        Some(monitor) => monitor,
+        None => {
+            pyrite_log!(
+                "Monitor {} isn't connected, using the primary monitor",
+                index
+            );
+            window.primary_monitor()
         }
     }
 }
 
+fn center_window_on(window: &Window, monitor: &MonitorHandle) {
+    let monitor_position = monitor.position();
+    let monitor_size = monitor.size();
+    let window_size = window.outer_size();
+
+    window.set_outer_position(PhysicalPosition::new(
+        monitor_position.x + (monitor_size.width as i32 - window_size.width as i32) / 2,
+        monitor_position.y + (monitor_size.height as i32 - window_size.height as i32) / 2,
+    ));
+}
+
+/// MSAA sample counts must be a power of two, anything else disables msaa.
+fn validate_msaa_samples(msaa_samples: u16) -> u16 {
+    if msaa_samples == 0 || msaa_samples.is_power_of_two() {
+        msaa_samples
+    } else {
+        pyrite_log!(
+            "msaa_samples must be a power of two, got {}, msaa disabled",
+            msaa_samples
+        );
+        0
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Viewport {
     width: i32,
     height: i32,
+    // the viewport can't show more than the scene holds
+    max_size: (i32, i32),
     scale: i32,
+    auto_scale: bool,
+    scale_factor: f64,
+    // magnification about the centre of the viewport, applied when drawing so it can be fractional
+    zoom: f32,
 }
 
 #[allow(dead_code)]
 impl Viewport {
-    pub fn new(width: i32, height: i32, scale: i32) -> Self {
+    pub fn new(width: i32, height: i32, scale: i32, max_size: (i32, i32)) -> Self {
         Self {
-            width: width.min(1024).max(3),
-            height: height.min(1024).max(3),
+            width: width.min(max_size.0).max(3),
+            height: height.min(max_size.1).max(3),
+            max_size,
             scale: scale.max(1),
+            auto_scale: false,
+            scale_factor: 1.,
+            zoom: 1.,
         }
     }
 
-    pub fn contains(&self, x: i32, y: i32) -> bool {
-        x >= 0 && x <= self.width && y >= 0 && y <= self.height
+    /// Zooming out isn't supported, the viewport would show tiles outside the camera's view.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.max(1.);
+    }
+
+    pub fn get_zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// When auto scale is enabled the scale is multiplied by the display scale factor, so the
+    /// game appears the same physical size on high dpi displays.
+    pub fn set_auto_scale(&mut self, auto_scale: bool, scale_factor: f64) {
+        self.auto_scale = auto_scale;
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
     }
 
     pub fn set(&mut self, width: i32, height: i32, scale: i32) {
-        self.width = width.min(1024).max(3);
-        self.height = height.min(1024).max(3);
+        if width > self.max_size.0 || height > self.max_size.1 {
+            pyrite_log!(
+                "Viewport {}x{} is larger than the {}x{} scene, it's limited to the scene size",
+                width,
+                height,
+                self.max_size.0,
+                self.max_size.1
+            );
+        }
+
+        self.width = width.min(self.max_size.0).max(3);
+        self.height = height.min(self.max_size.1).max(3);
         self.scale = scale.max(1);
     }
 
@@ -264,14 +1636,46 @@ impl Viewport {
         (self.width, self.height)
     }
 
+    /// The effective scale, including the display scale factor when auto scaling.
     pub fn get_scale(&self) -> i32 {
-        self.scale
+        if self.auto_scale {
+            // keep the scale whole so pixels stay crisp
+            ((self.scale as f64 * self.scale_factor).round() as i32).max(1)
+        } else {
+            self.scale
+        }
+    }
+
+    /// The smallest the window can be while still showing every pixel of the scene.
+    pub fn get_minimum_framebuffer_size(&self, tile_size: (i32, i32)) -> PhysicalSize<u32> {
+        PhysicalSize::new(
+            (self.width * tile_size.0) as u32,
+            (self.height * tile_size.1) as u32,
+        )
+    }
+
+    pub fn get_maximum_framebuffer_size(
+        &self,
+        tile_size: (i32, i32),
+        max_scale: Option<i32>,
+    ) -> Option<PhysicalSize<u32>> {
+        max_scale.map(|max_scale| {
+            // never smaller than the current size, otherwise the window would be forced to shrink.
+            let max_scale = max_scale.max(self.get_scale());
+
+            PhysicalSize::new(
+                (self.width * tile_size.0 * max_scale) as u32,
+                (self.height * tile_size.1 * max_scale) as u32,
+            )
+        })
     }
 
     pub fn get_framebuffer_size(&self, tile_size: (i32, i32)) -> PhysicalSize<u32> {
+        let scale = self.get_scale();
+
         PhysicalSize::new(
-            (self.width * (tile_size.0 * self.scale)) as u32,
-            (self.height * (tile_size.1 * self.scale)) as u32,
+            (self.width * (tile_size.0 * scale)) as u32,
+            (self.height * (tile_size.1 * scale)) as u32,
         )
     }
 
@@ -284,6 +1688,71 @@ impl Viewport {
     }
 }
 
+const PALETTE_SIZE: usize = 256;
+
+/// Colors that replace tileset pixels by luminance, tileset pixels with a luminance matching a set
+/// palette index are drawn with the palette color instead.
+struct Palette {
+    entries: Vec<(u8, u8, u8, u8)>,
+    texture: Texture,
+    enabled: bool,
+    upload_pending: bool,
+}
+
+impl Palette {
+    fn new() -> Result<Self, String> {
+        let entries = vec![(0, 0, 0, 0); PALETTE_SIZE];
+        let texture = Texture::from_vec4_u8(PALETTE_SIZE as i32, 1, &entries)?;
+
+        Ok(Self {
+            entries,
+            texture,
+            enabled: false,
+            upload_pending: false,
+        })
+    }
+
+    fn set(&mut self, start_index: usize, colors: &[(u8, u8, u8)]) {
+        if start_index >= PALETTE_SIZE || colors.is_empty() {
+            pyrite_log!(
+                "Failed to set palette: no colors to set from index {}, the palette has {} entries",
+                start_index,
+                PALETTE_SIZE
+            );
+            return;
+        }
+
+        // entries with an alpha of zero are unset and leave the tileset pixel unchanged
+        for (entry, (r, g, b)) in self.entries.iter_mut().skip(start_index).zip(colors) {
+            *entry = (*r, *g, *b, 255);
+        }
+
+        self.enabled = true;
+        self.upload_pending = true;
+    }
+
+    fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = (0, 0, 0, 0);
+        }
+
+        self.enabled = false;
+        self.upload_pending = true;
+    }
+
+    fn upload(&mut self) {
+        if self.upload_pending {
+            self.texture
+                .update_from_vec4_u8(PALETTE_SIZE as i32, 1, &self.entries);
+            self.upload_pending = false;
+        }
+    }
+}
+
+/// Front and back layer depths used when set_tile isn't given any, the front layer draws over
+/// the back layer.
+pub const DEFAULT_TILE_DEPTHS: (u8, u8) = (1, 0);
+
 struct Scene {
     tiles: Vec<(f32, f32, f32, f32)>,
     tiles_upload_buffer: Vec<(f32, f32, f32, f32)>,
@@ -291,65 +1760,100 @@ struct Scene {
     front_tiles_modifiers_upload_buffer: Vec<(u8, u8, u8, u8)>,
     back_tiles_modifiers: Vec<(u8, u8, u8, u8)>,
     back_tiles_modifiers_upload_buffer: Vec<(u8, u8, u8, u8)>,
+    // front depth, back depth, unused, unused
+    tiles_depths: Vec<(u8, u8, u8, u8)>,
+    tiles_depths_upload_buffer: Vec<(u8, u8, u8, u8)>,
 
     tiles_texture: Texture,
     front_tiles_modifiers_texture: Texture,
     back_tiles_modifiers_texture: Texture,
+    tiles_depths_texture: Texture,
 
     upload_pending: bool,
     upload_region_top_left: (u32, u32),
     upload_region_bottom_right: (u32, u32),
+
+    statistics: SceneStatistics,
+    // width and height in tiles
+    size: (i32, i32),
 }
 
 impl Scene {
-    const SCENE_MAX_SIZE: (i32, i32) = (1024, 1024);
-    const SCENE_TILE_COUNT: usize = (Self::SCENE_MAX_SIZE.0 * Self::SCENE_MAX_SIZE.1) as usize;
+    fn contains(&self, position: (i32, i32)) -> bool {
+        size_contains(self.size, position)
+    }
+
+    /// The size is limited to what the graphics card can hold in a texture, and to at least the
+    /// smallest viewport.
+    fn new(size: (i32, i32)) -> Result<Self, String> {
+        let mut max_texture_size = 0;
+        unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_texture_size) };
+
+        let requested_size = size;
+        let size = (
+            size.0.max(3).min(max_texture_size),
+            size.1.max(3).min(max_texture_size),
+        );
+
+        if size != requested_size {
+            pyrite_log!(
+                "Scene size {}x{} isn't supported, using {}x{}",
+                requested_size.0,
+                requested_size.1,
+                size.0,
+                size.1
+            );
+        }
+
+        let tile_count = (size.0 * size.1) as usize;
 
-    fn new() -> Self {
         // Front tiles initialised to "none" and back tiles to "fill"
-        let tiles = vec![(-1.0, 0.0, -2.0, 0.0); Self::SCENE_TILE_COUNT];
+        let tiles = vec![(-1.0, 0.0, -2.0, 0.0); tile_count];
         let tiles_upload_buffer = tiles.clone();
 
-        let front_tiles_modifiers = vec![(255, 255, 255, 0); Self::SCENE_TILE_COUNT];
+        let front_tiles_modifiers = vec![(255, 255, 255, 0); tile_count];
         let front_tiles_modifiers_upload_buffer = front_tiles_modifiers.clone();
 
-        let back_tiles_modifiers = vec![(255, 255, 255, 0); Self::SCENE_TILE_COUNT];
+        let back_tiles_modifiers = vec![(255, 255, 255, 0); tile_count];
         let back_tiles_modifiers_upload_buffer = back_tiles_modifiers.clone();
 
+        let tiles_depths = vec![(DEFAULT_TILE_DEPTHS.0, DEFAULT_TILE_DEPTHS.1, 0, 0); tile_count];
+        let tiles_depths_upload_buffer = tiles_depths.clone();
+
         // create scene textures and upload scene data
-        let tiles_texture =
-            Texture::from_vec4_f32(Self::SCENE_MAX_SIZE.0, Self::SCENE_MAX_SIZE.1, &tiles);
+        let tiles_texture = Texture::from_vec4_f32(size.0, size.1, &tiles)?;
 
-        let front_tiles_modifiers_texture = Texture::from_vec4_u8(
-            Self::SCENE_MAX_SIZE.0,
-            Self::SCENE_MAX_SIZE.1,
-            &front_tiles_modifiers,
-        );
+        let front_tiles_modifiers_texture =
+            Texture::from_vec4_u8(size.0, size.1, &front_tiles_modifiers)?;
 
-        let back_tiles_modifiers_texture = Texture::from_vec4_u8(
-            Self::SCENE_MAX_SIZE.0,
-            Self::SCENE_MAX_SIZE.1,
-            &back_tiles_modifiers,
-        );
+        let back_tiles_modifiers_texture =
+            Texture::from_vec4_u8(size.0, size.1, &back_tiles_modifiers)?;
+
+        let tiles_depths_texture = Texture::from_vec4_u8(size.0, size.1, &tiles_depths)?;
 
         let upload_pending = false;
-        let upload_region_top_left = (1024, 1024);
+        let upload_region_top_left = (size.0 as u32, size.1 as u32);
         let upload_region_bottom_right = (0, 0);
 
-        Self {
+        Ok(Self {
             tiles,
             tiles_upload_buffer,
             front_tiles_modifiers,
             front_tiles_modifiers_upload_buffer,
             back_tiles_modifiers,
             back_tiles_modifiers_upload_buffer,
+            tiles_depths,
+            tiles_depths_upload_buffer,
             tiles_texture,
             front_tiles_modifiers_texture,
             back_tiles_modifiers_texture,
+            tiles_depths_texture,
             upload_pending,
             upload_region_top_left,
             upload_region_bottom_right,
-        }
+            statistics: SceneStatistics::default(),
+            size,
+        })
     }
 
     fn upload(&mut self) {
@@ -357,6 +1861,13 @@ impl Scene {
             // calculate update region x and y offset, and width and height
             let update_region_xy_wh = self.get_update_region();
 
+            // a frame may upload more than once, such as when it's presented early.
+            let upload_region = &mut self.statistics.upload_region;
+            *upload_region = (
+                upload_region.0.max(update_region_xy_wh.2 as u32),
+                upload_region.1.max(update_region_xy_wh.3 as u32),
+            );
+
             self.copy_update_region_to_upload_buffers(update_region_xy_wh);
 
             // preform partial update
@@ -386,9 +1897,17 @@ impl Scene {
                     &self.back_tiles_modifiers_upload_buffer,
                 );
 
+            self.tiles_depths_texture.partial_update_from_vec4_u8(
+                update_region_xy_wh.0,
+                update_region_xy_wh.1,
+                update_region_xy_wh.2,
+                update_region_xy_wh.3,
+                &self.tiles_depths_upload_buffer,
+            );
+
             // reset update region tracking
             self.upload_pending = false;
-            self.upload_region_top_left = (1024, 1024);
+            self.upload_region_top_left = (self.size.0 as u32, self.size.1 as u32);
             self.upload_region_bottom_right = (0, 0);
         }
     }
@@ -416,7 +1935,7 @@ impl Scene {
                 let global_y = local_y + region.1;
 
                 let local_index = local_y * region.2 + local_x;
-                let global_index = global_y * Self::SCENE_MAX_SIZE.0 as u32 + global_x;
+                let global_index = global_y * self.size.0 as u32 + global_x;
 
                 self.tiles_upload_buffer[local_index as usize] = self.tiles[global_index as usize];
 
@@ -424,6 +1943,8 @@ impl Scene {
                     self.front_tiles_modifiers[global_index as usize];
                 self.back_tiles_modifiers_upload_buffer[local_index as usize] =
                     self.back_tiles_modifiers[global_index as usize];
+                self.tiles_depths_upload_buffer[local_index as usize] =
+                    self.tiles_depths[global_index as usize];
             }
         }
     }
@@ -439,6 +1960,7 @@ impl Scene {
         back_tile: &str,
         back_color: (u8, u8, u8),
         back_flip: (bool, bool),
+        depths: (u8, u8),
     ) -> bool {
         // we don't care about negative locations, but it makes easier for other systems to
         // interact when we accept a signed number, so we convert here.
@@ -446,22 +1968,11 @@ impl Scene {
         let y = position.1 as u32;
 
         // find liner index
-        let index = (y * Self::SCENE_MAX_SIZE.0 as u32 + x) as usize;
+        let index = (y * self.size.0 as u32 + x) as usize;
 
         // determine flip value
-        let front_flip = match (front_flip.0, front_flip.1) {
-            (false, false) => 0,  // flip none = 0
-            (true, false) => 51,  // flip x = 0.2
-            (false, true) => 102, // flip y = 0.4
-            (true, true) => 153,  // flip x and y = .6
-        };
-
-        let back_flip = match (back_flip.0, back_flip.1) {
-            (false, false) => 0,  // flip none = 0
-            (true, false) => 51,  // flip x = 0.2
-            (false, true) => 102, // flip y = 0.4
-            (true, true) => 153,  // flip x and y = .6
-        };
+        let front_flip = Self::flip_modifier(front_flip);
+        let back_flip = Self::flip_modifier(back_flip);
 
         // if all the required resources are available, we preform a tile update
         match (
@@ -470,70 +1981,334 @@ impl Scene {
             self.tiles.get_mut(index),
             self.front_tiles_modifiers.get_mut(index),
             self.back_tiles_modifiers.get_mut(index),
+            self.tiles_depths.get_mut(index),
         ) {
             (
-                Some(front_tile),
-                Some(back_tile),
-                Some(tile_pair),
-                Some(front_modifiers),
-                Some(back_modifiers),
-            ) => {
-                let pending_modifiers = (
-                    (front_color.0, front_color.1, front_color.2, front_flip),
-                    (back_color.0, back_color.1, back_color.2, back_flip),
-                );
+                Some(front_tile),
+                Some(back_tile),
+                Some(tile_pair),
+                Some(front_modifiers),
+                Some(back_modifiers),
+                Some(tile_depths),
+            ) => {
+                let pending_modifiers = (
+                    (front_color.0, front_color.1, front_color.2, front_flip),
+                    (back_color.0, back_color.1, back_color.2, back_flip),
+                );
+
+                // we should update the data only if the new data is different
+                let should_update_data = front_tile != (tile_pair.0, tile_pair.1)
+                    || back_tile != (tile_pair.2, tile_pair.3)
+                    || (*front_modifiers, *back_modifiers) != pending_modifiers
+                    || (tile_depths.0, tile_depths.1) != depths;
+
+                if should_update_data {
+                    *tile_pair = (front_tile.0, front_tile.1, back_tile.0, back_tile.1);
+                    *front_modifiers = pending_modifiers.0;
+                    *back_modifiers = pending_modifiers.1;
+                    *tile_depths = (depths.0, depths.1, 0, 0);
+
+                    self.mark_changed(x, y);
+
+                    return true;
+                }
+            }
+            _ => (),
+        };
+
+        return false;
+    }
+
+    /// The flip as it's stored in the last component of the modifiers.
+    fn flip_modifier(flip: (bool, bool)) -> u8 {
+        match flip {
+            (false, false) => 0,  // flip none = 0
+            (true, false) => 51,  // flip x = 0.2
+            (false, true) => 102, // flip y = 0.4
+            (true, true) => 153,  // flip x and y = .6
+        }
+    }
+
+    /// Store tile locations and modifiers as given, without translating a color and flip. The
+    /// depths are left as they are. Returns true if the scene was actually modified.
+    fn set_tile_raw(
+        &mut self,
+        position: (i32, i32),
+        front: ((f32, f32), (u8, u8, u8, u8)),
+        back: ((f32, f32), (u8, u8, u8, u8)),
+    ) -> bool {
+        let x = position.0 as u32;
+        let y = position.1 as u32;
+        let index = (y * self.size.0 as u32 + x) as usize;
+
+        let pending_tile_pair = ((front.0).0, (front.0).1, (back.0).0, (back.0).1);
+        if self.tiles[index] == pending_tile_pair
+            && self.front_tiles_modifiers[index] == front.1
+            && self.back_tiles_modifiers[index] == back.1
+        {
+            return false;
+        }
+
+        self.tiles[index] = pending_tile_pair;
+        self.front_tiles_modifiers[index] = front.1;
+        self.back_tiles_modifiers[index] = back.1;
+
+        self.mark_changed(x, y);
+
+        true
+    }
+
+    /// Set the tile, color and flip of one layer, leaving the other layer and the depths as they
+    /// are. Returns true if the scene was actually modified.
+    fn set_layer_tile(
+        &mut self,
+        tileset: &Tileset,
+        position: (i32, i32),
+        layer: Layer,
+        tile_name: &str,
+        color: (u8, u8, u8),
+        flip: (bool, bool),
+    ) -> bool {
+        let tile_location = match tileset.get_tile_location(tile_name) {
+            Some(location) => location,
+            None => return false,
+        };
+
+        let x = position.0 as u32;
+        let y = position.1 as u32;
+        let index = (y * self.size.0 as u32 + x) as usize;
+
+        let pending_modifiers = (color.0, color.1, color.2, Self::flip_modifier(flip));
+        let tile_pair = &mut self.tiles[index];
+
+        let (tile, modifiers) = match layer {
+            Layer::Front => (
+                (&mut tile_pair.0, &mut tile_pair.1),
+                &mut self.front_tiles_modifiers[index],
+            ),
+            Layer::Back => (
+                (&mut tile_pair.2, &mut tile_pair.3),
+                &mut self.back_tiles_modifiers[index],
+            ),
+        };
+
+        if (*tile.0, *tile.1) == tile_location && *modifiers == pending_modifiers {
+            return false;
+        }
+
+        *tile.0 = tile_location.0;
+        *tile.1 = tile_location.1;
+        *modifiers = pending_modifiers;
+
+        self.mark_changed(x, y);
+
+        true
+    }
+
+    fn get_tile_color(&self, position: (i32, i32), layer: Layer) -> (u8, u8, u8) {
+        let index = (position.1 * self.size.0 + position.0) as usize;
+
+        let (r, g, b, _flip) = match layer {
+            Layer::Front => self.front_tiles_modifiers[index],
+            Layer::Back => self.back_tiles_modifiers[index],
+        };
+
+        (r, g, b)
+    }
+
+    /// Returns true if the scene was actually modified
+    fn tint_tile(
+        &mut self,
+        position: (i32, i32),
+        front_color: (u8, u8, u8),
+        back_color: Option<(u8, u8, u8)>,
+    ) -> bool {
+        let x = position.0 as u32;
+        let y = position.1 as u32;
+        let index = (y * self.size.0 as u32 + x) as usize;
+
+        let front_modifiers = &mut self.front_tiles_modifiers[index];
+        let back_modifiers = &mut self.back_tiles_modifiers[index];
+
+        // the flip is kept in the last component of the modifiers
+        let back_color =
+            back_color.unwrap_or((back_modifiers.0, back_modifiers.1, back_modifiers.2));
+        let pending_modifiers = (
+            (
+                front_color.0,
+                front_color.1,
+                front_color.2,
+                front_modifiers.3,
+            ),
+            (back_color.0, back_color.1, back_color.2, back_modifiers.3),
+        );
 
-                // we should update the data only if the new data is different
-                let should_update_data = front_tile != (tile_pair.0, tile_pair.1)
-                    || back_tile != (tile_pair.2, tile_pair.3)
-                    || (*front_modifiers, *back_modifiers) != pending_modifiers;
+        if (*front_modifiers, *back_modifiers) == pending_modifiers {
+            return false;
+        }
 
-                if should_update_data {
-                    *tile_pair = (front_tile.0, front_tile.1, back_tile.0, back_tile.1);
-                    *front_modifiers = pending_modifiers.0;
-                    *back_modifiers = pending_modifiers.1;
+        *front_modifiers = pending_modifiers.0;
+        *back_modifiers = pending_modifiers.1;
 
-                    self.upload_region_top_left = (
-                        self.upload_region_top_left.0.min(x),
-                        self.upload_region_top_left.1.min(y),
-                    );
+        self.mark_changed(x, y);
 
-                    self.upload_region_bottom_right = (
-                        self.upload_region_bottom_right.0.max(x),
-                        self.upload_region_bottom_right.1.max(y),
-                    );
+        true
+    }
 
-                    self.upload_pending = true;
+    /// Reset one layer of every tile within the region to how the scene starts, the other layer
+    /// is left untouched. Returns true if the scene was actually modified.
+    fn clear_layer(
+        &mut self,
+        tileset: &Tileset,
+        region: (i32, i32, i32, i32),
+        layer: Layer,
+    ) -> bool {
+        let (left, top, right, bottom) = (
+            region.0.max(0),
+            region.1.max(0),
+            region.2.min(self.size.0),
+            region.3.min(self.size.1),
+        );
 
-                    return true;
+        let cleared_tile = match layer {
+            Layer::Front => tileset.get_tile_location("none"),
+            Layer::Back => tileset.get_tile_location("fill"),
+        }
+        .expect("built in tiles are always available");
+        let cleared_modifiers = (0, 0, 0, 0);
+
+        let mut scene_changed = false;
+
+        for y in top..bottom {
+            for x in left..right {
+                let index = (y * self.size.0 + x) as usize;
+                let tile_pair = &mut self.tiles[index];
+
+                let (tile, modifiers) = match layer {
+                    Layer::Front => (
+                        (&mut tile_pair.0, &mut tile_pair.1),
+                        &mut self.front_tiles_modifiers[index],
+                    ),
+                    Layer::Back => (
+                        (&mut tile_pair.2, &mut tile_pair.3),
+                        &mut self.back_tiles_modifiers[index],
+                    ),
+                };
+
+                if (*tile.0, *tile.1) != cleared_tile || *modifiers != cleared_modifiers {
+                    *tile.0 = cleared_tile.0;
+                    *tile.1 = cleared_tile.1;
+                    *modifiers = cleared_modifiers;
+
+                    self.mark_changed(x as u32, y as u32);
+                    scene_changed = true;
                 }
             }
-            _ => (),
-        };
+        }
 
-        return false;
+        scene_changed
+    }
+
+    /// Grow the region uploaded on the next frame to include a changed tile.
+    fn mark_changed(&mut self, x: u32, y: u32) {
+        self.statistics.tiles_changed += 1;
+
+        self.upload_region_top_left = (
+            self.upload_region_top_left.0.min(x),
+            self.upload_region_top_left.1.min(y),
+        );
+
+        self.upload_region_bottom_right = (
+            self.upload_region_bottom_right.0.max(x),
+            self.upload_region_bottom_right.1.max(y),
+        );
+
+        self.upload_pending = true;
     }
 }
 
+/// One of the two layers each tile in the scene is drawn from.
+#[derive(Clone, Copy, Debug)]
+pub enum Layer {
+    Front,
+    Back,
+}
+
 struct Tileset {
     pub texture: Texture,
+    pub filter: TextureFilter,
+    // a cpu side copy of the RGBA pixels, the texture can't be read back on every platform.
+    pixels: Vec<u8>,
+    image_dimensions: (u32, u32),
     set_dimensions: (u32, u32),
     tile_dimensions: (u32, u32),
     names_to_positions: HashMap<String, (f32, f32)>,
+    names_to_animations: HashMap<String, usize>,
+    animations: Vec<TileAnimation>,
+    animation_frames: Vec<(f32, f32)>,
+    pub animation_frames_texture: Texture,
+    animation_frames_upload_pending: bool,
+}
+
+/// Scene tiles showing a tileset animation store this in place of a tileset row, with the
+/// animation index in place of the column. The shader looks up the current frame of the animation.
+const ANIMATED_TILE_ROW: f32 = -3.0;
+
+/// A tile name that flips through a sequence of tileset cells.
+struct TileAnimation {
+    frames: Vec<(f32, f32)>,
+    frame_duration: f64,
+}
+
+impl TileAnimation {
+    fn frame_at(&self, time: f64) -> (f32, f32) {
+        let frame = (time / self.frame_duration) as usize % self.frames.len();
+        self.frames[frame]
+    }
 }
 
 impl Tileset {
-    fn from_config(config: &engine::Config, resources: &Box<dyn resources::Provider>) -> Self {
+    fn from_config(
+        config: &engine::Config,
+        resources: &Arc<dyn resources::Provider>,
+    ) -> Result<Self, String> {
         let image_bytes = resources
             .read_to_bytes(&config.tileset_path)
-            .expect("failed to load tileset image");
-        let tileset_image = image::load_from_memory(&image_bytes).expect("failed to load tileset");
+            .ok_or_else(|| format!("failed to load tileset image {}", config.tileset_path))?;
+        let decoded_image = image::load_from_memory(&image_bytes)
+            .map_err(|e| format!("failed to load tileset {}: {}", config.tileset_path, e))?;
+
+        // Palette and 16 bit images are expanded to 8 bits per channel by the decoder, everything
+        // is then normalised to RGBA. Images without an alpha channel become fully opaque, so
+        // every cell of them is filled.
+        let tile_fill = if config.legacy_tile_detection {
+            TileFill::AnyChannel
+        } else {
+            TileFill::Alpha
+        };
+        let tileset_image = image::DynamicImage::ImageRgba8(decoded_image.to_rgba());
+
+        let filter = match config.tile_filter.to_lowercase().as_str() {
+            "nearest" => TextureFilter::Nearest,
+            "linear" => TextureFilter::Linear,
+            _ => {
+                pyrite_log!(
+                    "Unknown tile_filter \"{}\", expected \"nearest\" or \"linear\"",
+                    config.tile_filter
+                );
+                TextureFilter::Nearest
+            }
+        };
 
-        let tileset = Tileset::new(
+        let mut tileset = Tileset::new(
             &tileset_image,
             (config.tileset_width, config.tileset_height),
             config.tile_names.clone(),
-        );
+            tile_fill,
+            filter,
+        )
+        .map_err(|e| format!("failed to load tileset {}: {}", config.tileset_path, e))?;
+        tileset.add_animations(&config.tile_animations);
 
         pyrite_log!(
             "Loaded tileset {} (tiles: {}x{}) (pixels: {}x{})",
@@ -544,63 +2319,125 @@ impl Tileset {
             tileset_image.height(),
         );
 
-        return tileset;
+        Ok(tileset)
     }
 
     fn new(
         image: &image::DynamicImage,
         set_dimensions: (u32, u32),
-        mut tile_names: Vec<String>,
-    ) -> Self {
-        let texture = Texture::from_image(image);
+        tile_names: Vec<String>,
+        tile_fill: TileFill,
+        filter: TextureFilter,
+    ) -> Result<Self, String> {
+        let texture = Texture::from_image(image, filter)?;
         let tileset_image_dimensions = image.dimensions();
         let tile_dimensions = (
             tileset_image_dimensions.0 / set_dimensions.0,
             tileset_image_dimensions.1 / set_dimensions.1,
         );
-        let mut names_to_positions = HashMap::new();
-
-        tile_names.reverse();
-
-        // iterate each tile
-        for tile_y in 0..set_dimensions.1 {
-            for tile_x in 0..set_dimensions.0 {
-                let mut tile_filled = false;
-                // iterate each pixel of each tile
-                'pixels: for tile_pixel_x in
-                    (0..tile_dimensions.0).map(|x| x + tile_x * tile_dimensions.0)
-                {
-                    for tile_pixel_y in
-                        (0..tile_dimensions.1).map(|y| y + tile_y * tile_dimensions.1)
-                    {
-                        // check if pixel has colour
-                        if image
-                            .get_pixel(tile_pixel_x, tile_pixel_y)
-                            .channels()
-                            .into_iter()
-                            .fold(false, |has_color, pixel| *pixel > 0 || has_color)
-                        {
-                            tile_filled = true;
-                            break 'pixels;
-                        }
-                    }
-                }
+        let rgba_image = image.to_rgba();
+        let names_to_positions =
+            assign_tile_names(&rgba_image, set_dimensions, tile_names, tile_fill);
+        let pixels = rgba_image.into_raw();
 
-                if tile_filled {
-                    if let Some(tile_name) = tile_names.pop() {
-                        names_to_positions.insert(tile_name, (tile_x as f32, tile_y as f32));
-                    } else {
-                        println!("Tile name list has been exhausted, but another tile was found at ({}, {})",tile_x, tile_y);
-                    }
-                }
-            }
-        }
+        // textures can't be empty, the placeholder frame is never looked up.
+        let animation_frames_texture = Texture::from_vec2_f32(1, 1, &[(0.0, 0.0)])?;
 
-        Self {
+        Ok(Self {
             texture,
+            filter,
+            pixels,
+            image_dimensions: tileset_image_dimensions,
             set_dimensions,
             tile_dimensions,
             names_to_positions,
+            names_to_animations: HashMap::new(),
+            animations: Vec::new(),
+            animation_frames: Vec::new(),
+            animation_frames_texture,
+            animation_frames_upload_pending: false,
+        })
+    }
+
+    /// Name sequences of tiles, the frames are tile names that must already be in the tileset.
+    fn add_animations(&mut self, tile_animations: &HashMap<String, (Vec<String>, f64)>) {
+        for (name, (frame_names, frame_duration)) in tile_animations {
+            if self.names_to_positions.contains_key(name) {
+                pyrite_log!(
+                    "Skipping tile animation \"{}\", the name is already used by a tile",
+                    name
+                );
+                continue;
+            }
+
+            if *frame_duration <= 0.0 {
+                pyrite_log!(
+                    "Skipping tile animation \"{}\", the frame duration must be above zero",
+                    name
+                );
+                continue;
+            }
+
+            let mut frames = Vec::new();
+
+            for frame_name in frame_names {
+                match self.names_to_positions.get(frame_name) {
+                    Some(position) => frames.push(*position),
+                    None => pyrite_log!(
+                        "Tile animation \"{}\" frame \"{}\" isn't in the tileset",
+                        name,
+                        frame_name
+                    ),
+                }
+            }
+
+            if frames.is_empty() {
+                pyrite_log!("Skipping tile animation \"{}\", it has no frames", name);
+                continue;
+            }
+
+            self.names_to_animations
+                .insert(name.clone(), self.animations.len());
+            self.animation_frames.push(frames[0]);
+            self.animations.push(TileAnimation {
+                frames,
+                frame_duration: *frame_duration,
+            });
+        }
+
+        self.animation_frames_upload_pending = !self.animations.is_empty();
+    }
+
+    /// Move every animation to its frame at the clock time, in seconds. Returns true if any
+    /// animation changed frame.
+    fn advance_animations(&mut self, time: f64) -> bool {
+        let mut frames_changed = false;
+
+        for (animation, current_frame) in
+            self.animations.iter().zip(self.animation_frames.iter_mut())
+        {
+            let frame = animation.frame_at(time);
+
+            if *current_frame != frame {
+                *current_frame = frame;
+                frames_changed = true;
+            }
+        }
+
+        self.animation_frames_upload_pending =
+            self.animation_frames_upload_pending || frames_changed;
+
+        frames_changed
+    }
+
+    fn upload_animation_frames(&mut self) {
+        if self.animation_frames_upload_pending {
+            self.animation_frames_texture.update_from_vec2_f32(
+                self.animation_frames.len() as i32,
+                1,
+                &self.animation_frames,
+            );
+            self.animation_frames_upload_pending = false;
         }
     }
 
@@ -612,23 +2449,185 @@ impl Tileset {
         (self.tile_dimensions.0 as i32, self.tile_dimensions.1 as i32)
     }
 
+    fn get_pixels(&self) -> (u32, u32, &[u8]) {
+        (
+            self.image_dimensions.0,
+            self.image_dimensions.1,
+            &self.pixels,
+        )
+    }
+
+    fn get_info(&self) -> TilesetInfo {
+        let mut tile_names: Vec<String> = self
+            .names_to_positions
+            .keys()
+            .chain(self.names_to_animations.keys())
+            .cloned()
+            .collect();
+        tile_names.sort();
+
+        TilesetInfo {
+            tile_width: self.tile_dimensions.0,
+            tile_height: self.tile_dimensions.1,
+            set_width: self.set_dimensions.0,
+            set_height: self.set_dimensions.1,
+            tile_names,
+        }
+    }
+
+    /// Where a tile is drawn from right now, animated tiles give their current frame.
+    fn get_frame_location(&self, tile_name: &str) -> Option<(f32, f32)> {
+        match self.names_to_animations.get(tile_name) {
+            Some(index) => Some(self.animation_frames[*index]),
+            None => self.names_to_positions.get(tile_name).cloned(),
+        }
+    }
+
+    /// The location of a cell of the tileset grid, counted left-to-right, top-to-bottom including
+    /// empty cells. -1 and -2 are the "none" and "fill" tiles.
+    fn get_index_location(&self, index: i32) -> Option<(f32, f32)> {
+        let (set_width, set_height) = self.set_dimensions;
+
+        match index {
+            -1 => Some((-1.0, 0.0)),
+            -2 => Some((-2.0, 0.0)),
+            _ if index >= 0 && (index as u32) < set_width * set_height => Some((
+                (index as u32 % set_width) as f32,
+                (index as u32 / set_width) as f32,
+            )),
+            _ => None,
+        }
+    }
+
     fn get_tile_location(&self, tile_name: &str) -> Option<(f32, f32)> {
         match tile_name {
             "none" => Some((-1.0, 0.0)),
             "fill" => Some((-2.0, 0.0)),
-            _ => self.names_to_positions.get(tile_name).cloned(),
+            _ => self.names_to_positions.get(tile_name).cloned().or_else(|| {
+                self.names_to_animations
+                    .get(tile_name)
+                    .map(|index| (*index as f32, ANIMATED_TILE_ROW))
+            }),
+        }
+    }
+}
+
+/// Give each filled cell of the tileset grid the next tile name, left-to-right, top-to-bottom.
+fn assign_tile_names(
+    image: &image::RgbaImage,
+    set_dimensions: (u32, u32),
+    mut tile_names: Vec<String>,
+    tile_fill: TileFill,
+) -> HashMap<String, (f32, f32)> {
+    let tile_dimensions = (
+        image.width() / set_dimensions.0,
+        image.height() / set_dimensions.1,
+    );
+    let mut names_to_positions = HashMap::new();
+
+    tile_names.reverse();
+
+    // iterate each tile
+    for tile_y in 0..set_dimensions.1 {
+        for tile_x in 0..set_dimensions.0 {
+            let mut tile_filled = false;
+            // iterate each pixel of each tile
+            'pixels: for tile_pixel_x in
+                (0..tile_dimensions.0).map(|x| x + tile_x * tile_dimensions.0)
+            {
+                for tile_pixel_y in (0..tile_dimensions.1).map(|y| y + tile_y * tile_dimensions.1) {
+                    let [red, green, blue, alpha] = image.get_pixel(tile_pixel_x, tile_pixel_y).0;
+                    let pixel_filled = match tile_fill {
+                        TileFill::Alpha => alpha > 0,
+                        TileFill::AnyChannel => red > 0 || green > 0 || blue > 0 || alpha > 0,
+                    };
+
+                    if pixel_filled {
+                        tile_filled = true;
+                        break 'pixels;
+                    }
+                }
+            }
+
+            if tile_filled {
+                if let Some(tile_name) = tile_names.pop() {
+                    names_to_positions.insert(tile_name, (tile_x as f32, tile_y as f32));
+                } else {
+                    println!(
+                        "Tile name list has been exhausted, but another tile was found at ({}, {})",
+                        tile_x, tile_y
+                    );
+                }
+            }
         }
     }
+
+    names_to_positions
+}
+
+/// How a tileset pixel is decided to be part of a tile, tiles without any filled pixels are
+/// skipped when assigning names.
+#[derive(Clone, Copy)]
+enum TileFill {
+    /// Any pixel that isn't fully transparent, so opaque black sprites are kept.
+    Alpha,
+    /// Any channel above zero, matching how older versions scanned tilesets.
+    AnyChannel,
 }
 
 pub struct Texture {
     texture: u32,
 }
 
+/// How an image texture is sampled between its pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureFilter {
+    /// Sharp pixels, the look pyrite is designed around.
+    Nearest,
+    /// Smoothly blended pixels, for smooth scaled art.
+    Linear,
+}
+
+impl TextureFilter {
+    fn gl_filter(self) -> i32 {
+        match self {
+            TextureFilter::Nearest => gl::NEAREST as i32,
+            TextureFilter::Linear => gl::LINEAR as i32,
+        }
+    }
+}
+
 #[allow(dead_code)]
 impl Texture {
-    fn from_image(image: &image::DynamicImage) -> Self {
+    /// Check a new texture was created and its pixels were allocated. Drivers report failures,
+    /// such as running out of memory or a size over GL_MAX_TEXTURE_SIZE, through the gl error
+    /// flag rather than the texture name.
+    unsafe fn check_created(texture: u32, width: i32, height: i32) -> Result<Self, String> {
+        let error = gl::GetError();
+        // dropped on failure, deleting a texture that was never created is ignored.
+        let texture = Self { texture };
+
+        if texture.texture == 0 {
+            return Err("failed to create texture".to_owned());
+        }
+
+        if error != gl::NO_ERROR {
+            return Err(format!(
+                "failed to create {}x{} texture: {} (0x{:x})",
+                width,
+                height,
+                gl_error_name(error),
+                error
+            ));
+        }
+
+        Ok(texture)
+    }
+
+    fn from_image(image: &image::DynamicImage, filter: TextureFilter) -> Result<Self, String> {
         unsafe {
+            gl_clear_errors();
+
             let mut texture = 0;
             gl::GenTextures(1, &mut texture);
 
@@ -636,8 +2635,8 @@ impl Texture {
 
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter.gl_filter());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter.gl_filter());
 
             let pixels: Vec<u8> = image.to_rgba().into_raw();
 
@@ -653,22 +2652,18 @@ impl Texture {
                 std::mem::transmute(&pixels.as_slice()[0]),
             );
 
-            if texture <= 0 {
-                panic!("texture creation failed");
-            }
-
-            Self { texture }
+            Self::check_created(texture, image.width() as i32, image.height() as i32)
         }
     }
 
-    fn update_from_image(&mut self, image: &image::DynamicImage) {
+    fn update_from_image(&mut self, image: &image::DynamicImage, filter: TextureFilter) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
 
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter.gl_filter());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter.gl_filter());
 
             let pixels: Vec<u8> = image.to_rgba().into_raw();
 
@@ -686,8 +2681,10 @@ impl Texture {
         }
     }
 
-    fn from_vec2_f32(width: i32, height: i32, data: &[(f32, f32)]) -> Self {
+    fn from_vec2_f32(width: i32, height: i32, data: &[(f32, f32)]) -> Result<Self, String> {
         unsafe {
+            gl_clear_errors();
+
             let mut texture = 0;
             gl::GenTextures(1, &mut texture);
 
@@ -710,11 +2707,7 @@ impl Texture {
                 std::mem::transmute(&data[0]),
             );
 
-            if texture <= 0 {
-                panic!("texture creation failed");
-            }
-
-            Self { texture }
+            Self::check_created(texture, width, height)
         }
     }
 
@@ -771,8 +2764,14 @@ impl Texture {
         }
     }
 
-    fn from_vec4_f32(width: i32, height: i32, data: &[(f32, f32, f32, f32)]) -> Self {
+    fn from_vec4_f32(
+        width: i32,
+        height: i32,
+        data: &[(f32, f32, f32, f32)],
+    ) -> Result<Self, String> {
         unsafe {
+            gl_clear_errors();
+
             let mut texture = 0;
             gl::GenTextures(1, &mut texture);
 
@@ -795,11 +2794,7 @@ impl Texture {
                 std::mem::transmute(&data[0]),
             );
 
-            if texture <= 0 {
-                panic!("texture creation failed");
-            }
-
-            Self { texture }
+            Self::check_created(texture, width, height)
         }
     }
 
@@ -856,8 +2851,14 @@ impl Texture {
         }
     }
 
-    fn from_vec4_u8(width: i32, height: i32, data: &[(u8, u8, u8, u8)]) -> Self {
+    pub fn from_vec4_u8(
+        width: i32,
+        height: i32,
+        data: &[(u8, u8, u8, u8)],
+    ) -> Result<Self, String> {
         unsafe {
+            gl_clear_errors();
+
             let mut texture = 0;
             gl::GenTextures(1, &mut texture);
 
@@ -880,11 +2881,7 @@ impl Texture {
                 std::mem::transmute(&data[0]),
             );
 
-            if texture <= 0 {
-                panic!("texture creation failed");
-            }
-
-            Self { texture }
+            Self::check_created(texture, width, height)
         }
     }
 
@@ -941,7 +2938,7 @@ impl Texture {
         }
     }
 
-    fn bind(&self) {
+    pub fn bind(&self) {
         unsafe {
             gl::BindTexture(gl::TEXTURE_2D, self.texture);
         }
@@ -962,14 +2959,15 @@ pub struct Shader {
 
 #[allow(dead_code)]
 impl Shader {
-    pub fn new(vertex_shader_source: &str, fragment_shader_source: &str) -> Self {
+    pub fn new(vertex_shader_source: &str, fragment_shader_source: &str) -> Result<Self, String> {
         unsafe {
-            let vertex_shader = Self::compile_shader(vertex_shader_source, gl::VERTEX_SHADER);
-            let fragment_shader = Self::compile_shader(fragment_shader_source, gl::FRAGMENT_SHADER);
+            let vertex_shader = Self::compile_shader(vertex_shader_source, gl::VERTEX_SHADER)?;
+            let fragment_shader =
+                Self::compile_shader(fragment_shader_source, gl::FRAGMENT_SHADER)?;
 
-            let program = Self::link_shaders(vertex_shader, fragment_shader);
+            let program = Self::link_shaders(vertex_shader, fragment_shader)?;
 
-            Self { program }
+            Ok(Self { program })
         }
     }
 
@@ -1042,9 +3040,19 @@ impl Shader {
         }
     }
 
-    unsafe fn compile_shader(source: &str, shader_type: GLuint) -> u32 {
+    pub fn set_uniform_4f(&self, name: &str, value: (f32, f32, f32, f32)) {
+        unsafe {
+            let name = ffi::CString::new(name).unwrap();
+            let location = gl::GetUniformLocation(self.program, name.as_ptr());
+
+            gl::Uniform4f(location, value.0, value.1, value.2, value.3);
+        }
+    }
+
+    unsafe fn compile_shader(source: &str, shader_type: GLuint) -> Result<u32, String> {
         let shader = gl::CreateShader(shader_type);
 
+        let source = gl_shader_source(source);
         let c_str = ffi::CString::new(source.as_bytes()).unwrap();
         gl::ShaderSource(shader, 1, &c_str.as_ptr(), ptr::null());
         gl::CompileShader(shader);
@@ -1063,16 +3071,16 @@ impl Shader {
                 buf.as_mut_ptr() as *mut GLchar,
             );
 
-            panic!(
+            return Err(format!(
                 "failed to compile shader: {}",
-                str::from_utf8(&buf).expect("failed to decode error message")
-            );
+                String::from_utf8_lossy(&buf)
+            ));
         }
 
-        return shader;
+        Ok(shader)
     }
 
-    unsafe fn link_shaders(vertex_shader: u32, fragment_shader: u32) -> u32 {
+    unsafe fn link_shaders(vertex_shader: u32, fragment_shader: u32) -> Result<u32, String> {
         let program = gl::CreateProgram();
 
         gl::AttachShader(program, vertex_shader);
@@ -1094,13 +3102,13 @@ impl Shader {
                 buf.as_mut_ptr() as *mut GLchar,
             );
 
-            panic!(
+            return Err(format!(
                 "failed to link shaders: {}",
-                str::from_utf8(&buf).expect("failed to read error message")
-            );
+                String::from_utf8_lossy(&buf)
+            ));
         };
 
-        return program;
+        Ok(program)
     }
 }
 
@@ -1112,6 +3120,93 @@ impl Drop for Shader {
     }
 }
 
+/// A framebuffer drawn into instead of the window, its contents last until they're drawn over.
+struct FrameTarget {
+    framebuffer: u32,
+    // the framebuffer's color attachment, deleted along with it.
+    texture: Texture,
+    size: (u32, u32),
+}
+
+impl FrameTarget {
+    /// The target starts filled with the color.
+    fn new(size: (u32, u32), color: (u8, u8, u8, u8)) -> Result<Self, String> {
+        let pixels = vec![color; (size.0 * size.1) as usize];
+        let texture = Texture::from_vec4_u8(size.0 as i32, size.1 as i32, &pixels)?;
+
+        unsafe {
+            let mut framebuffer = 0;
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                texture.texture,
+                0,
+            );
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            // dropped on failure, which deletes the framebuffer.
+            let frame_target = Self {
+                framebuffer,
+                texture,
+                size,
+            };
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(format!(
+                    "the {}x{} frame target is incomplete (status {:#x})",
+                    size.0, size.1, status
+                ));
+            }
+
+            Ok(frame_target)
+        }
+    }
+
+    fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.size.0 as i32, self.size.1 as i32);
+        }
+    }
+
+    /// Copy the target into the render region of the window, and draw to the window again.
+    fn copy_to_window(&self, region: &RenderRegion, window_height: u32) {
+        // the region is measured from the top, gl from the bottom of the window
+        let bottom = window_height as i32 - region.y - region.height as i32;
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.size.0 as i32,
+                self.size.1 as i32,
+                region.x,
+                bottom,
+                region.x + region.width as i32,
+                bottom + region.height as i32,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for FrameTarget {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+        }
+    }
+}
+
 pub struct Quad {
     vao: u32,
     vbo: u32,
@@ -1119,14 +3214,15 @@ pub struct Quad {
 }
 
 impl Quad {
-    const QUAD_VERTS: [f32; 16] = [
+    const QUAD_VERTS: [GLfloat; 16] = [
         1., 1., 1., 0., // top right
         1., -1., 1., 1., // bottom right
         -1., -1., 0., 1., // bottom left
         -1., 1., 0., 0., // top left
     ];
 
-    const QUAD_INDICES: [i32; 6] = [
+    // indices are drawn as UNSIGNED_INT, so must be GLuint
+    const QUAD_INDICES: [GLuint; 6] = [
         0, 1, 3, // first triangle
         1, 2, 3, // second triangle
     ];
@@ -1152,7 +3248,7 @@ impl Quad {
             gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
             gl::BufferData(
                 gl::ELEMENT_ARRAY_BUFFER,
-                (Self::QUAD_INDICES.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
+                (Self::QUAD_INDICES.len() * mem::size_of::<GLuint>()) as GLsizeiptr,
                 mem::transmute(&Self::QUAD_INDICES[0]),
                 gl::STATIC_DRAW,
             );
@@ -1179,7 +3275,12 @@ impl Quad {
     fn draw(&self) {
         unsafe {
             gl::BindVertexArray(self.vao);
-            gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null());
+            gl::DrawElements(
+                gl::TRIANGLES,
+                Self::QUAD_INDICES.len() as GLsizei,
+                gl::UNSIGNED_INT,
+                ptr::null(),
+            );
         }
     }
 }
@@ -1194,15 +3295,128 @@ impl Drop for Quad {
     }
 }
 
-fn gl_log_info() {
+/// Shaders are written for desktop GLSL 3.30, on GLES the version line is swapped for the
+/// equivalent GLSL ES version and default precisions.
+fn gl_shader_source(source: &str) -> String {
+    if !gl_get_string(gl::VERSION).starts_with("OpenGL ES") {
+        return source.to_owned();
+    }
+
+    source.replacen(
+        "#version 330 core",
+        "#version 300 es\nprecision highp float;\nprecision highp int;",
+        1,
+    )
+}
+
+fn cursor_icon_from_name(name: &str) -> Option<CursorIcon> {
+    let cursor_icon = match name.to_lowercase().as_str() {
+        "default" => CursorIcon::Default,
+        "arrow" => CursorIcon::Arrow,
+        "hand" => CursorIcon::Hand,
+        "crosshair" => CursorIcon::Crosshair,
+        "text" => CursorIcon::Text,
+        "vertical_text" => CursorIcon::VerticalText,
+        "move" => CursorIcon::Move,
+        "wait" => CursorIcon::Wait,
+        "progress" => CursorIcon::Progress,
+        "help" => CursorIcon::Help,
+        "not_allowed" => CursorIcon::NotAllowed,
+        "no_drop" => CursorIcon::NoDrop,
+        "context_menu" => CursorIcon::ContextMenu,
+        "cell" => CursorIcon::Cell,
+        "alias" => CursorIcon::Alias,
+        "copy" => CursorIcon::Copy,
+        "grab" => CursorIcon::Grab,
+        "grabbing" => CursorIcon::Grabbing,
+        "all_scroll" => CursorIcon::AllScroll,
+        "zoom_in" => CursorIcon::ZoomIn,
+        "zoom_out" => CursorIcon::ZoomOut,
+        "n_resize" => CursorIcon::NResize,
+        "e_resize" => CursorIcon::EResize,
+        "s_resize" => CursorIcon::SResize,
+        "w_resize" => CursorIcon::WResize,
+        "ne_resize" => CursorIcon::NeResize,
+        "nw_resize" => CursorIcon::NwResize,
+        "se_resize" => CursorIcon::SeResize,
+        "sw_resize" => CursorIcon::SwResize,
+        "ew_resize" => CursorIcon::EwResize,
+        "ns_resize" => CursorIcon::NsResize,
+        "nesw_resize" => CursorIcon::NeswResize,
+        "nwse_resize" => CursorIcon::NwseResize,
+        "col_resize" => CursorIcon::ColResize,
+        "row_resize" => CursorIcon::RowResize,
+        _ => return None,
+    };
+
+    Some(cursor_icon)
+}
+
+/// True if the position is inside a grid of the size, counted from 0.
+fn size_contains(size: (i32, i32), position: (i32, i32)) -> bool {
+    position.0 >= 0 && position.0 < size.0 && position.1 >= 0 && position.1 < size.1
+}
+
+fn gl_log_info() -> RendererInfo {
     let version = gl_get_string(gl::VERSION);
     let _vendor = gl_get_string(gl::VENDOR);
     let renderer = gl_get_string(gl::RENDERER);
-    let shader_version = gl_get_string(gl::VERSION);
+    let shader_version = gl_get_string(gl::SHADING_LANGUAGE_VERSION);
 
     pyrite_log!("OpenGL: {}", version);
     pyrite_log!("GPU: {}", renderer);
     pyrite_log!("GLSL: {}", shader_version);
+
+    RendererInfo {
+        gl_version: version,
+        gpu: renderer,
+        glsl_version: shader_version,
+    }
+}
+
+/// Log any pending gl errors, labelled with the operation that was just performed.
+///
+/// Only checks in debug builds, as querying errors stalls the gl pipeline.
+fn gl_check_error(label: &str) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    loop {
+        let error = unsafe { gl::GetError() };
+
+        if error == gl::NO_ERROR {
+            break;
+        }
+
+        pyrite_log!(
+            "GL error after {}: {} (0x{:x})",
+            label,
+            gl_error_name(error),
+            error
+        );
+    }
+}
+
+fn gl_error_name(error: GLenum) -> &'static str {
+    match error {
+        gl::INVALID_ENUM => "INVALID_ENUM",
+        gl::INVALID_VALUE => "INVALID_VALUE",
+        gl::INVALID_OPERATION => "INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "OUT_OF_MEMORY",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Discard errors left by earlier calls, so a check only sees errors from the calls after it.
+/// The number of errors read is capped as a lost context can keep reporting errors.
+fn gl_clear_errors() {
+    for _ in 0..16 {
+        if unsafe { gl::GetError() } == gl::NO_ERROR {
+            break;
+        }
+    }
 }
 
 fn gl_get_string(name: u32) -> String {
@@ -1214,3 +3428,124 @@ fn gl_get_string(name: u32) -> String {
         String::from_utf8(data).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a 4x2 pixel, 8 bit png. The palette and transparency chunks are left out when
+    /// they're empty.
+    fn encode_png(
+        color: png::ColorType,
+        palette: &[u8],
+        transparency: &[u8],
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 4, 2);
+            encoder.set_color(color);
+            encoder.set_depth(png::BitDepth::Eight);
+
+            let mut writer = encoder.write_header().unwrap();
+            if !palette.is_empty() {
+                writer.write_chunk(*b"PLTE", palette).unwrap();
+            }
+            if !transparency.is_empty() {
+                writer.write_chunk(*b"tRNS", transparency).unwrap();
+            }
+            writer.write_image_data(data).unwrap();
+        }
+        bytes
+    }
+
+    /// Decode and assign names the way Tileset::from_config does, with a grid of two 2x2 cells.
+    fn names_for_png(png_bytes: &[u8], tile_fill: TileFill) -> HashMap<String, (f32, f32)> {
+        let image = image::load_from_memory(png_bytes).unwrap().to_rgba();
+        let tile_names = vec!["first".to_owned(), "second".to_owned()];
+
+        assign_tile_names(&image, (2, 1), tile_names, tile_fill)
+    }
+
+    #[test]
+    fn grayscale_black_cell_is_named() {
+        // the left cell is black, the right cell is white
+        let data = [0, 0, 255, 255, 0, 0, 255, 255];
+        let png_bytes = encode_png(png::ColorType::Grayscale, &[], &[], &data);
+
+        for tile_fill in &[TileFill::Alpha, TileFill::AnyChannel] {
+            let names = names_for_png(&png_bytes, *tile_fill);
+            assert_eq!(names.get("first"), Some(&(0.0, 0.0)));
+            assert_eq!(names.get("second"), Some(&(1.0, 0.0)));
+        }
+    }
+
+    #[test]
+    fn grayscale_alpha_transparent_cell_is_skipped() {
+        // gray and alpha pairs, the left cell is white but fully transparent
+        let data = [
+            255, 0, 255, 0, 0, 255, 0, 255, //
+            255, 0, 255, 0, 0, 255, 0, 255,
+        ];
+        let png_bytes = encode_png(png::ColorType::GrayscaleAlpha, &[], &[], &data);
+
+        let names = names_for_png(&png_bytes, TileFill::Alpha);
+        assert_eq!(names.get("first"), Some(&(1.0, 0.0)));
+        assert_eq!(names.get("second"), None);
+
+        // older versions counted the white color of the transparent cell
+        let names = names_for_png(&png_bytes, TileFill::AnyChannel);
+        assert_eq!(names.get("first"), Some(&(0.0, 0.0)));
+        assert_eq!(names.get("second"), Some(&(1.0, 0.0)));
+    }
+
+    #[test]
+    fn palette_black_cell_is_named() {
+        // index 0 is black and index 1 is red, the left cell is black
+        let palette = [0, 0, 0, 255, 0, 0];
+        let data = [0, 0, 1, 1, 0, 0, 1, 1];
+        let png_bytes = encode_png(png::ColorType::Indexed, &palette, &[], &data);
+
+        let names = names_for_png(&png_bytes, TileFill::Alpha);
+        assert_eq!(names.get("first"), Some(&(0.0, 0.0)));
+        assert_eq!(names.get("second"), Some(&(1.0, 0.0)));
+    }
+
+    #[test]
+    fn palette_transparent_cell_is_skipped() {
+        // index 0 is red and fully transparent, index 1 is opaque black
+        let palette = [255, 0, 0, 0, 0, 0];
+        let transparency = [0, 255];
+        let data = [0, 0, 1, 1, 0, 0, 1, 1];
+        let png_bytes = encode_png(png::ColorType::Indexed, &palette, &transparency, &data);
+
+        let names = names_for_png(&png_bytes, TileFill::Alpha);
+        assert_eq!(names.get("first"), Some(&(1.0, 0.0)));
+        assert_eq!(names.get("second"), None);
+    }
+
+    #[test]
+    fn positions_outside_the_scene_are_rejected() {
+        let size = (4, 3);
+
+        assert!(size_contains(size, (0, 0)));
+        assert!(size_contains(size, (3, 2)));
+        assert!(!size_contains(size, (-1, 0)));
+        assert!(!size_contains(size, (0, -1)));
+        assert!(!size_contains(size, (4, 0)));
+        assert!(!size_contains(size, (0, 3)));
+    }
+
+    #[test]
+    fn gl_versions_are_described_in_the_order_tried() {
+        let gl_versions = vec![
+            "GL 4.1".to_owned(),
+            "gl 2.1".to_owned(),
+            "GLES 3".to_owned(),
+        ];
+        assert_eq!(describe_gl_versions(&gl_versions), "GL 4.1 or GLES 3.0");
+
+        // nothing usable falls back to GL 3.3
+        assert_eq!(describe_gl_versions(&["GLES 2.0".to_owned()]), "GL 3.3");
+    }
+}
@@ -2,10 +2,20 @@ mod audio;
 mod binding;
 mod engine;
 mod graphics;
+mod loader;
+#[cfg(feature = "network")]
+mod network;
+mod overlay;
+mod particles;
 mod platform;
+mod replay;
 pub mod resources;
+mod socket;
 
 use pyo3::prelude::*;
+use std::env;
+use std::fs;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -16,41 +26,188 @@ macro_rules! pyrite_log {
     }
 }
 
+/// Environment variable naming the resource to run instead of entry.py, so a project can have more
+/// than one entry point, such as a level editor alongside the game.
+const ENTRY_PATH_VARIABLE: &str = "PYRITE_ENTRY";
+
+/// Environment variables naming a file to record the player's input to, or to play recorded input
+/// back from, for reproducing bugs.
+const RECORD_PATH_VARIABLE: &str = "PYRITE_RECORD";
+const REPLAY_PATH_VARIABLE: &str = "PYRITE_REPLAY";
+
+/// How long to sleep between frames while the window is minimized, events are still polled so the
+/// window can be restored.
+const MINIMIZED_SLEEP: Duration = Duration::from_millis(100);
+
+/// Sleep for most of the duration then spin for the rest, thread::sleep alone can overshoot by a
+/// few milliseconds on platforms with coarse timers, which causes missed frames on fast displays.
+fn precise_sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    let spin_time = Duration::from_millis(1);
+
+    if duration > spin_time {
+        thread::sleep(duration - spin_time);
+    }
+
+    while Instant::now() < deadline {}
+}
+
+/// Games run without a console have nowhere to show errors, so errors that stop the game are also
+/// written to a file next to the executable.
+fn write_error_log(message: &str) {
+    let path = match env::current_exe() {
+        Ok(executable) => executable.with_file_name("error.log"),
+        Err(_) => "error.log".into(),
+    };
+
+    match fs::write(&path, message) {
+        Ok(()) => pyrite_log!("Error written to {}", path.display()),
+        Err(e) => pyrite_log!("Failed to write error log {}: {}", path.display(), e),
+    }
+}
+
+/// Frame rate the main loop is paced to, frames may take longer but never run faster.
+const TARGET_DELTA_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Run a single frame, dispatching events, stepping the game logic and rendering. Returns false
+/// once the engine should stop running.
+///
+/// This is everything the main loop does except waiting between frames, so the engine can also
+/// be advanced a frame at a time, such as by step_once() from a debug loop in python.
+fn tick(py: Python, entry_module: &PyModule) -> bool {
+    // dispatch engine / platform events
+    let events_start_time = Instant::now();
+    for (event, timestamp) in engine!(poll_events()) {
+        binding::raise_event_at(py, entry_module, &event, timestamp);
+    }
+    let events_duration = events_start_time.elapsed();
+
+    // calculate time since last frame
+    let delta_time = engine!(measure_frame_delta_time());
+
+    // game logic sees the clamped and smoothed delta time, pacing still uses the real one.
+    let step_delta_time = engine!(smooth_delta_time(delta_time.as_secs_f64()));
+    let step_delta_time = engine!(replay_delta_time(step_delta_time));
+
+    // pass this frames delta time to the binding for the python delta_time() function to get
+    // its value. This value should only be set for the duration of the step event.
+    binding::set_delta_time(step_delta_time);
+
+    // Dispatch time step event with delta time
+    let step_start_time = Instant::now();
+    let step_result = binding::raise_event(
+        py,
+        entry_module,
+        &engine::Event::Step {
+            delta_time: step_delta_time,
+        },
+    );
+
+    let step_duration = step_start_time.elapsed();
+
+    // clear delta time before processing events that aren't logic steps
+    binding::set_delta_time(0.);
+
+    // Move tile animations on to their current frame.
+    engine!(update_animations(step_delta_time));
+
+    // Input that arrived during a slow STEP handler is gathered now, it's raised next frame.
+    engine!(sample_input());
+
+    // Allow the renderer to present a new frame if needed, nothing can be seen while the
+    // window is minimized so rendering is skipped. The STEP handler can return False to skip
+    // rendering this frame, or True to force a new frame.
+    let render_start_time = Instant::now();
+    if !engine!(get_minimized()) && step_result != Some(false) {
+        if step_result == Some(true) {
+            engine!(request_redraw());
+        }
+
+        engine!(render());
+    }
+    let render_duration = render_start_time.elapsed();
+
+    engine!(set_frame_timings(engine::FrameTimings {
+        events: events_duration,
+        step: step_duration,
+        render: render_duration,
+    }));
+
+    engine!(get_running())
+}
+
+/// Wait out the rest of the frame. The wait is based on the time since the previous frame began,
+/// rather than how long this one took.
+fn pace_frame() {
+    let delta_time = engine!(get_frame_delta_time());
+
+    // if we still have remaining time before we reach our target rate, sleep.
+    if delta_time < TARGET_DELTA_TIME {
+        let remaining_time = TARGET_DELTA_TIME - delta_time;
+
+        if engine!(get_precise_frame_pacing()) {
+            precise_sleep(remaining_time);
+        } else {
+            thread::sleep(remaining_time);
+        }
+    }
+
+    if engine!(get_minimized()) {
+        thread::sleep(MINIMIZED_SLEEP);
+    }
+}
+
 pub fn start<R: resources::Provider + 'static>(resource_provider: R) {
     pyrite_log!("Pyrite {}", env!("CARGO_PKG_VERSION"));
     pyrite_log!("Acquiring python environment lock");
     let py_lock = Python::acquire_gil();
     let py = py_lock.python();
 
-    pyrite_log!("Loading game entry source file");
-    let entry_path = "entry.py";
+    let entry_path = env::var(ENTRY_PATH_VARIABLE).unwrap_or_else(|_| "entry.py".to_owned());
+    pyrite_log!("Loading game entry source file {}", entry_path);
     let entry_source = resource_provider
-        .read_to_string(entry_path)
-        .expect("failed to load entry.py");
+        .read_to_string(&entry_path)
+        .unwrap_or_else(|| panic!("failed to load {}", entry_path));
 
     pyrite_log!("Building pyrite engine instance");
-    let resources = Box::new(resource_provider);
+    let resources = Arc::new(resource_provider);
     let engine = engine::Engine::new(resources);
     pyrite_log!("Building python bindings");
     binding::inject_engine(py, engine);
 
+    if let Ok(record_path) = env::var(RECORD_PATH_VARIABLE) {
+        engine!(record_replay(&record_path));
+    } else if let Ok(replay_path) = env::var(REPLAY_PATH_VARIABLE) {
+        engine!(play_replay(&replay_path));
+    }
+
     pyrite_log!("Injecting pyrite imports module");
     PyModule::from_code(py, include_str!("importer.py"), "importer.py", "importer")
         .expect("failed to create python resource importer hook");
 
     pyrite_log!("Loading entry module");
-    let entry_module = match PyModule::from_code(py, &entry_source, entry_path, "entry") {
+    let entry_module = match PyModule::from_code(py, &entry_source, &entry_path, "entry") {
         Ok(module) => module,
         Err(e) => {
-            pyrite_log!("An error occurred while importing the entry module");
-            e.print(py);
+            let message = binding::format_error(py, e);
+            pyrite_log!(
+                "An error occurred while importing the entry module:\n{}",
+                message
+            );
+            write_error_log(&message);
             return;
         }
     };
 
     // load configuration via callback.
     match binding::get_configuration(&entry_module) {
-        Some(config) => engine!().load_configuration(config),
+        Some(config) => {
+            if !engine!(load_configuration(config)) {
+                pyrite_log!("Failed to load configuration, exiting");
+                binding::destroy_engine();
+                return;
+            }
+        }
         None => {
             pyrite_log!("Failed to get configuration from __config__ in entry module");
             return;
@@ -62,46 +219,15 @@ pub fn start<R: resources::Provider + 'static>(resource_provider: R) {
 
     // dynamic update loop, may run slower than the target rate, but never faster.
     // It's important the game logic takes delta time into consideration, due to the variability of
-    // the time step delta.
-    let target_delta_time = Duration::from_secs_f64(1. / 60.);
-    let mut last_frame_time = Instant::now();
-    while engine!().get_running() {
-        // dispatch engine / platform events
-        for event in engine!().poll_events() {
-            binding::raise_event(py, entry_module, &event);
-        }
-
-        // calculate time since last frame, add it to the accumulator.
-        let delta_time = last_frame_time.elapsed();
-        last_frame_time = Instant::now();
-
-        // pass this frames delta time to the binding for the python delta_time() function to get
-        // its value. This value should only be set for the duration of the step event.
-        binding::set_delta_time(delta_time.as_secs_f64());
-
-        // Dispatch time step event with delta time
-        binding::raise_event(
-            py,
-            entry_module,
-            &engine::Event::Step {
-                delta_time: delta_time.as_secs_f64(),
-            },
-        );
-
-        // clear delta time before processing events that aren't logic steps
-        binding::set_delta_time(0.);
-
-        // Allow the renderer to present a new frame if needed.
-        engine!().render();
-
-        // if we still have remaining time before we reach our target rate, sleep.
-        if delta_time < target_delta_time {
-            let remaining_time = target_delta_time - delta_time;
-            thread::sleep(remaining_time);
+    // the time step delta. exit() may already have been called while loading.
+    if engine!(get_running()) {
+        while tick(py, entry_module) {
+            pace_frame();
         }
     }
 
-    // instruct game logic to exit
+    // instruct game logic to exit, the engine is only destroyed afterwards so games can still
+    // use it to save or clean up while handling the event.
     binding::raise_event(py, entry_module, &engine::Event::Exit);
 
     pyrite_log!("Cleaning up pyrite engine resources");
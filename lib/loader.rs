@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Loads run at the same time, further loads wait in a queue until a worker is free.
+const WORKER_COUNT: usize = 4;
+
+struct Job<T> {
+    handle: u32,
+    cancelled: Arc<AtomicBool>,
+    load: Box<dyn FnOnce() -> Option<T> + Send>,
+}
+
+/// Runs slow loads, such as reading resources or network requests, on a small pool of worker
+/// threads so they don't stall the main loop.
+///
+/// Workers never touch the python interpreter, results are handed back over a channel and only
+/// collected from the main thread.
+pub struct BackgroundLoads<T = Vec<u8>> {
+    next_handle: u32,
+    // workers are started as loads are spawned, up to WORKER_COUNT.
+    worker_count: usize,
+    jobs: Sender<Job<T>>,
+    shared_jobs: Arc<Mutex<Receiver<Job<T>>>>,
+    results_sender: Sender<(u32, Option<T>)>,
+    results: Receiver<(u32, Option<T>)>,
+    in_flight: HashMap<u32, Arc<AtomicBool>>,
+    finished: HashMap<u32, Option<T>>,
+}
+
+impl<T: Send + 'static> BackgroundLoads<T> {
+    pub fn new() -> Self {
+        let (jobs, shared_jobs) = mpsc::channel();
+        let (results_sender, results) = mpsc::channel();
+
+        Self {
+            next_handle: 0,
+            worker_count: 0,
+            jobs,
+            shared_jobs: Arc::new(Mutex::new(shared_jobs)),
+            results_sender,
+            results,
+            in_flight: HashMap::new(),
+            finished: HashMap::new(),
+        }
+    }
+
+    /// Queue a load for a worker thread, returning a handle to collect it with.
+    pub fn spawn<F>(&mut self, load: F) -> u32
+    where
+        F: FnOnce() -> Option<T> + Send + 'static,
+    {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+
+        if self.worker_count < WORKER_COUNT {
+            self.start_worker();
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.in_flight.insert(handle, cancelled.clone());
+
+        // workers only stop once the loads are dropped, so the queue is always open.
+        let _ = self.jobs.send(Job {
+            handle,
+            cancelled,
+            load: Box::new(load),
+        });
+
+        handle
+    }
+
+    fn start_worker(&mut self) {
+        let shared_jobs = self.shared_jobs.clone();
+        let results = self.results_sender.clone();
+
+        thread::spawn(move || loop {
+            // the lock is released as soon as a job is received, so other workers can wait.
+            let job = match shared_jobs.lock() {
+                Ok(jobs) => jobs.recv(),
+                Err(_) => return,
+            };
+
+            // the queue closes when the loads are dropped, such as when the engine shuts down.
+            let job = match job {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+
+            if job.cancelled.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            // a load that panics fails rather than taking the worker down with it.
+            let result = panic::catch_unwind(AssertUnwindSafe(job.load)).unwrap_or(None);
+
+            if results.send((job.handle, result)).is_err() {
+                return;
+            }
+        });
+
+        self.worker_count += 1;
+    }
+
+    /// Move results that have arrived from the workers into the finished loads.
+    fn collect(&mut self) {
+        while let Ok((handle, result)) = self.results.try_recv() {
+            // cancelled loads were already forgotten, their results are dropped.
+            if self.in_flight.remove(&handle).is_some() {
+                self.finished.insert(handle, result);
+            }
+        }
+    }
+
+    /// True once the load has finished, whether or not it succeeded.
+    pub fn ready(&mut self, handle: u32) -> bool {
+        self.collect();
+
+        self.finished.contains_key(&handle)
+    }
+
+    /// Take the data of a finished load, the handle is forgotten afterwards.
+    ///
+    /// Returns None if the load hasn't finished, the handle is unknown or the load failed.
+    pub fn take(&mut self, handle: u32) -> Option<T> {
+        if !self.ready(handle) {
+            return None;
+        }
+
+        self.finished.remove(&handle).and_then(|data| data)
+    }
+
+    /// Forget a load, discarding its data if it has finished. A load that hasn't started yet is
+    /// skipped, one that's running is left to finish and its data dropped.
+    ///
+    /// Returns false if the handle is unknown or was already taken.
+    pub fn cancel(&mut self, handle: u32) -> bool {
+        match self.in_flight.remove(&handle) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => self.finished.remove(&handle).is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+
+    fn wait_until_ready<T: Send + 'static>(loads: &mut BackgroundLoads<T>, handle: u32) {
+        let start = Instant::now();
+        while !loads.ready(handle) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "load never finished"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn loads_are_taken_once() {
+        let mut loads = BackgroundLoads::new();
+        let handle = loads.spawn(|| Some(vec![1, 2, 3]));
+
+        wait_until_ready(&mut loads, handle);
+        assert_eq!(loads.take(handle), Some(vec![1, 2, 3]));
+        assert!(!loads.ready(handle));
+        assert_eq!(loads.take(handle), None);
+    }
+
+    #[test]
+    fn failed_and_panicking_loads_finish_without_data() {
+        let mut loads: BackgroundLoads = BackgroundLoads::new();
+        let failed = loads.spawn(|| None);
+        let panicked = loads.spawn(|| panic!("load failed"));
+
+        wait_until_ready(&mut loads, failed);
+        wait_until_ready(&mut loads, panicked);
+        assert_eq!(loads.take(failed), None);
+        assert_eq!(loads.take(panicked), None);
+
+        // the worker survives the panic
+        let handle = loads.spawn(|| Some(vec![4]));
+        wait_until_ready(&mut loads, handle);
+        assert_eq!(loads.take(handle), Some(vec![4]));
+    }
+
+    #[test]
+    fn loads_beyond_the_pool_wait_for_a_worker() {
+        let mut loads = BackgroundLoads::new();
+        let (release, blocked) = channel::<()>();
+        let blocked = Arc::new(Mutex::new(blocked));
+
+        // occupy every worker until released
+        let blocking: Vec<u32> = (0..WORKER_COUNT)
+            .map(|_| {
+                let blocked = blocked.clone();
+                loads.spawn(move || {
+                    let _ = blocked.lock().unwrap().recv();
+                    Some(0)
+                })
+            })
+            .collect();
+        let queued = loads.spawn(|| Some(1));
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(loads.worker_count, WORKER_COUNT);
+        assert!(!loads.ready(queued));
+
+        for _ in 0..WORKER_COUNT {
+            release.send(()).unwrap();
+        }
+
+        wait_until_ready(&mut loads, queued);
+        assert_eq!(loads.take(queued), Some(1));
+        for handle in blocking {
+            wait_until_ready(&mut loads, handle);
+        }
+    }
+
+    #[test]
+    fn cancelled_loads_are_discarded() {
+        let mut loads = BackgroundLoads::new();
+        let finished = loads.spawn(|| Some(vec![1]));
+        wait_until_ready(&mut loads, finished);
+
+        assert!(loads.cancel(finished));
+        assert!(!loads.ready(finished));
+        assert!(!loads.cancel(finished));
+
+        let (release, blocked) = channel::<()>();
+        let running = loads.spawn(move || {
+            let _ = blocked.recv();
+            Some(vec![2])
+        });
+        assert!(loads.cancel(running));
+        release.send(()).unwrap();
+
+        // the cancelled result is dropped when it arrives
+        let after = loads.spawn(|| Some(vec![3]));
+        wait_until_ready(&mut loads, after);
+        thread::sleep(Duration::from_millis(20));
+        assert!(!loads.ready(running));
+        assert!(loads.finished.len() <= 1);
+    }
+}
@@ -0,0 +1,19 @@
+use std::io::Read;
+
+/// Perform a blocking HTTP GET request, returning the response body.
+///
+/// Returns None if the request fails or the server doesn't respond with a success status.
+/// https urls are supported through rustls, using the bundled web root certificates rather than
+/// the operating system's certificate store.
+pub fn http_get(url: &str) -> Option<Vec<u8>> {
+    let response = ureq::get(url).call();
+
+    if !response.ok() {
+        return None;
+    }
+
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body).ok()?;
+
+    Some(body)
+}
@@ -0,0 +1,12 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 tex_pos;
+in vec4 color;
+
+uniform sampler2D font;
+
+void main()
+{
+    FragColor = texture(font, tex_pos) * color;
+}
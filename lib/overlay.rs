@@ -0,0 +1,290 @@
+use crate::graphics::{Shader, Texture};
+use gl;
+use gl::types::*;
+use glutin::dpi::PhysicalSize;
+use std::mem;
+use std::ptr;
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+/// The engine's built-in font, independent of the game's tileset.
+///
+/// Each glyph is 3x5 pixels, stored as 5 rows of 3 bits with the most significant bit on the left.
+/// The first glyph is solid and is used to draw plain rectangles.
+const FONT_GLYPHS: &[(char, [u8; GLYPH_HEIGHT])] = &[
+    ('\u{2588}', [7, 7, 7, 7, 7]),
+    ('0', [7, 5, 5, 5, 7]),
+    ('1', [2, 6, 2, 2, 7]),
+    ('2', [7, 1, 7, 4, 7]),
+    ('3', [7, 1, 7, 1, 7]),
+    ('4', [5, 5, 7, 1, 1]),
+    ('5', [7, 4, 7, 1, 7]),
+    ('6', [7, 4, 7, 5, 7]),
+    ('7', [7, 1, 1, 1, 1]),
+    ('8', [7, 5, 7, 5, 7]),
+    ('9', [7, 5, 7, 1, 7]),
+    ('A', [2, 5, 7, 5, 5]),
+    ('B', [6, 5, 6, 5, 6]),
+    ('C', [3, 4, 4, 4, 3]),
+    ('D', [6, 5, 5, 5, 6]),
+    ('E', [7, 4, 6, 4, 7]),
+    ('F', [7, 4, 6, 4, 4]),
+    ('G', [3, 4, 5, 5, 3]),
+    ('H', [5, 5, 7, 5, 5]),
+    ('I', [7, 2, 2, 2, 7]),
+    ('J', [1, 1, 1, 5, 2]),
+    ('K', [5, 5, 6, 5, 5]),
+    ('L', [4, 4, 4, 4, 7]),
+    ('M', [5, 7, 7, 5, 5]),
+    ('N', [6, 5, 5, 5, 5]),
+    ('O', [2, 5, 5, 5, 2]),
+    ('P', [6, 5, 6, 4, 4]),
+    ('Q', [2, 5, 5, 6, 3]),
+    ('R', [6, 5, 6, 5, 5]),
+    ('S', [3, 4, 2, 1, 6]),
+    ('T', [7, 2, 2, 2, 2]),
+    ('U', [5, 5, 5, 5, 7]),
+    ('V', [5, 5, 5, 5, 2]),
+    ('W', [5, 5, 7, 7, 5]),
+    ('X', [5, 5, 2, 5, 5]),
+    ('Y', [5, 5, 2, 2, 2]),
+    ('Z', [7, 1, 2, 4, 7]),
+    (' ', [0, 0, 0, 0, 0]),
+    ('.', [0, 0, 0, 0, 2]),
+    (',', [0, 0, 0, 2, 4]),
+    (':', [0, 2, 0, 2, 0]),
+    ('-', [0, 0, 7, 0, 0]),
+    ('+', [0, 2, 7, 2, 0]),
+    ('=', [0, 7, 0, 7, 0]),
+    ('_', [0, 0, 0, 0, 7]),
+    ('/', [1, 1, 2, 4, 4]),
+    ('%', [5, 1, 2, 4, 5]),
+    ('(', [1, 2, 2, 2, 1]),
+    (')', [4, 2, 2, 2, 4]),
+    ('!', [2, 2, 2, 0, 2]),
+    ('?', [6, 1, 2, 0, 2]),
+];
+
+/// Draws engine owned text and rectangles over the top of the scene, in window pixels.
+///
+/// Shapes are batched up with the push functions and drawn all at once, the batch is emptied
+/// after each draw.
+pub struct Overlay {
+    vao: u32,
+    vbo: u32,
+    shader: Shader,
+    font_texture: Texture,
+    vertices: Vec<GLfloat>,
+}
+
+impl Overlay {
+    // x, y, u, v, r, g, b, a
+    const VERTEX_SIZE: usize = 8;
+
+    pub fn new() -> Result<Self, String> {
+        let shader = Shader::new(include_str!("overlay.vert"), include_str!("overlay.frag"))?;
+        let font_texture = build_font_texture()?;
+
+        let (mut vao, mut vbo) = (0, 0);
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let stride = (Self::VERTEX_SIZE * mem::size_of::<GLfloat>()) as GLsizei;
+
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                mem::transmute(2 * mem::size_of::<GLfloat>()),
+            );
+            gl::EnableVertexAttribArray(1);
+
+            gl::VertexAttribPointer(
+                2,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                mem::transmute(4 * mem::size_of::<GLfloat>()),
+            );
+            gl::EnableVertexAttribArray(2);
+
+            gl::BindVertexArray(0);
+        }
+
+        Ok(Self {
+            vao,
+            vbo,
+            shader,
+            font_texture,
+            vertices: Vec::new(),
+        })
+    }
+
+    pub fn push_rect(
+        &mut self,
+        position: (f32, f32),
+        size: (f32, f32),
+        color: (f32, f32, f32, f32),
+    ) {
+        self.push_glyph_quad(0, position, size, color);
+    }
+
+    /// Queue a line of text, '\n' starts a new line. Unsupported characters are drawn as '?'.
+    pub fn push_text(
+        &mut self,
+        position: (f32, f32),
+        scale: f32,
+        text: &str,
+        color: (f32, f32, f32, f32),
+    ) {
+        let glyph_size = (GLYPH_WIDTH as f32 * scale, GLYPH_HEIGHT as f32 * scale);
+        let (mut x, mut y) = position;
+
+        for character in text.chars() {
+            if character == '\n' {
+                x = position.0;
+                y += glyph_size.1 + scale;
+                continue;
+            }
+
+            self.push_glyph_quad(glyph_index(character), (x, y), glyph_size, color);
+
+            // leave a pixel gap between glyphs
+            x += glyph_size.0 + scale;
+        }
+    }
+
+    /// The size in window pixels that text will cover when drawn at the given scale.
+    pub fn text_size(text: &str, scale: f32) -> (f32, f32) {
+        let lines = text.split('\n');
+        let line_count = lines.clone().count() as f32;
+        let longest_line = lines.map(|line| line.chars().count()).max().unwrap_or(0) as f32;
+
+        (
+            (longest_line * (GLYPH_WIDTH as f32 + 1.) - 1.).max(0.) * scale,
+            (line_count * (GLYPH_HEIGHT as f32 + 1.) - 1.) * scale,
+        )
+    }
+
+    fn push_glyph_quad(
+        &mut self,
+        glyph: usize,
+        position: (f32, f32),
+        size: (f32, f32),
+        color: (f32, f32, f32, f32),
+    ) {
+        let glyph_count = FONT_GLYPHS.len() as f32;
+        let u0 = glyph as f32 / glyph_count;
+        let u1 = (glyph + 1) as f32 / glyph_count;
+
+        let (x0, y0) = position;
+        let (x1, y1) = (position.0 + size.0, position.1 + size.1);
+        let (r, g, b, a) = color;
+
+        #[rustfmt::skip]
+        let quad = [
+            x0, y0, u0, 0., r, g, b, a,
+            x1, y0, u1, 0., r, g, b, a,
+            x1, y1, u1, 1., r, g, b, a,
+            x0, y0, u0, 0., r, g, b, a,
+            x1, y1, u1, 1., r, g, b, a,
+            x0, y1, u0, 1., r, g, b, a,
+        ];
+
+        self.vertices.extend_from_slice(&quad);
+    }
+
+    /// Draw everything queued since the last draw over the whole window.
+    pub fn draw(&mut self, window_size: PhysicalSize<u32>) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl::Viewport(0, 0, window_size.width as i32, window_size.height as i32);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        self.font_texture.bind();
+
+        self.shader.bind();
+        self.shader.set_uniform_2f(
+            "window_size",
+            (window_size.width as f32, window_size.height as f32),
+        );
+        self.shader.set_uniform_1i("font", 0);
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
+                mem::transmute(&self.vertices[0]),
+                gl::STREAM_DRAW,
+            );
+
+            gl::DrawArrays(
+                gl::TRIANGLES,
+                0,
+                (self.vertices.len() / Self::VERTEX_SIZE) as GLsizei,
+            );
+
+            gl::BindVertexArray(0);
+            gl::Disable(gl::BLEND);
+        }
+
+        self.vertices.clear();
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
+
+fn glyph_index(character: char) -> usize {
+    let character = character.to_ascii_uppercase();
+
+    FONT_GLYPHS
+        .iter()
+        .position(|(glyph, _)| *glyph == character)
+        .or_else(|| FONT_GLYPHS.iter().position(|(glyph, _)| *glyph == '?'))
+        .unwrap_or(0)
+}
+
+/// Lay every glyph out in a single row, white where the glyph is filled and transparent elsewhere.
+fn build_font_texture() -> Result<Texture, String> {
+    let width = FONT_GLYPHS.len() * GLYPH_WIDTH;
+    let mut pixels = vec![(0, 0, 0, 0); width * GLYPH_HEIGHT];
+
+    for (glyph_index, (_, rows)) in FONT_GLYPHS.iter().enumerate() {
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                    pixels[y * width + glyph_index * GLYPH_WIDTH + x] = (255, 255, 255, 255);
+                }
+            }
+        }
+    }
+
+    Texture::from_vec4_u8(width as i32, GLYPH_HEIGHT as i32, &pixels)
+}
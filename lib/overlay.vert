@@ -0,0 +1,18 @@
+#version 330 core
+layout (location = 0) in vec2 in_position;
+layout (location = 1) in vec2 in_tex_pos;
+layout (location = 2) in vec4 in_color;
+
+uniform vec2 window_size;
+
+out vec2 tex_pos;
+out vec4 color;
+
+void main()
+{
+	// positions are in window pixels from the top left
+	vec2 position = in_position / window_size * 2.0 - 1.0;
+	gl_Position = vec4(position.x, -position.y, 0.0, 1.0);
+	tex_pos = in_tex_pos;
+	color = in_color;
+}
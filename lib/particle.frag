@@ -0,0 +1,27 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 tex_pos;
+in vec3 color;
+
+uniform sampler2D tileset;
+uniform vec4 screen_tint;
+
+void main()
+{
+    // negative texture positions are solid particles that don't use the tileset
+    vec4 tile_color = vec4(1.0);
+
+    if (tex_pos.x >= 0.0) {
+        tile_color = texture(tileset, tex_pos / vec2(textureSize(tileset, 0)));
+    }
+
+    if (tile_color.a == 0.0) {
+        discard;
+    }
+
+    FragColor = vec4(tile_color.rgb * color, tile_color.a);
+
+    // matches the tint applied to the scene underneath
+    FragColor.rgb = mix(FragColor.rgb, FragColor.rgb * screen_tint.rgb, screen_tint.a);
+}
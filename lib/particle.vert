@@ -0,0 +1,20 @@
+#version 330 core
+layout (location = 0) in vec2 in_position;
+layout (location = 1) in vec2 in_tex_pos;
+layout (location = 2) in vec3 in_color;
+
+uniform vec2 scene_size;
+uniform vec2 camera_offset;
+uniform float zoom;
+
+out vec2 tex_pos;
+out vec3 color;
+
+void main()
+{
+	// positions are in scene pixels from the top left of the scene
+	vec2 position = ((in_position - camera_offset) / scene_size - 0.5) * 2.0 * zoom;
+	gl_Position = vec4(position.x, -position.y, 0.0, 1.0);
+	tex_pos = in_tex_pos;
+	color = in_color;
+}
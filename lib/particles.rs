@@ -0,0 +1,169 @@
+use crate::graphics::{Shader, Texture};
+use gl;
+use gl::types::*;
+use std::mem;
+use std::ptr;
+
+/// Draws tiles at sub-tile positions over the top of the scene, for effects like sparks and rain
+/// that would be too costly to write into the tile grid.
+///
+/// Particles are batched up with push and drawn all at once in a single draw call, the batch is
+/// emptied after each draw so particles only last for one frame.
+pub struct Particles {
+    vao: u32,
+    vbo: u32,
+    shader: Shader,
+    vertices: Vec<GLfloat>,
+}
+
+impl Particles {
+    // x, y, u, v, r, g, b
+    const VERTEX_SIZE: usize = 7;
+
+    pub fn new() -> Result<Self, String> {
+        let shader = Shader::new(include_str!("particle.vert"), include_str!("particle.frag"))?;
+
+        let (mut vao, mut vbo) = (0, 0);
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let stride = (Self::VERTEX_SIZE * mem::size_of::<GLfloat>()) as GLsizei;
+
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                mem::transmute(2 * mem::size_of::<GLfloat>()),
+            );
+            gl::EnableVertexAttribArray(1);
+
+            gl::VertexAttribPointer(
+                2,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                mem::transmute(4 * mem::size_of::<GLfloat>()),
+            );
+            gl::EnableVertexAttribArray(2);
+
+            gl::BindVertexArray(0);
+        }
+
+        Ok(Self {
+            vao,
+            vbo,
+            shader,
+            vertices: Vec::new(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Queue a tile, position and size are in scene pixels and the tile in tileset pixels. A
+    /// tile of None is drawn as a solid block of color.
+    pub fn push(
+        &mut self,
+        position: (f32, f32),
+        size: (f32, f32),
+        tile: Option<(f32, f32)>,
+        color: (f32, f32, f32),
+    ) {
+        let (x0, y0) = position;
+        let (x1, y1) = (position.0 + size.0, position.1 + size.1);
+
+        // negative texture coordinates tell the shader to skip the tileset
+        let (u0, v0) = tile.unwrap_or((-1., -1.));
+        let (u1, v1) = match tile {
+            Some(_) => (u0 + size.0, v0 + size.1),
+            None => (-1., -1.),
+        };
+
+        let (r, g, b) = color;
+
+        #[rustfmt::skip]
+        let quad = [
+            x0, y0, u0, v0, r, g, b,
+            x1, y0, u1, v0, r, g, b,
+            x1, y1, u1, v1, r, g, b,
+            x0, y0, u0, v0, r, g, b,
+            x1, y1, u1, v1, r, g, b,
+            x0, y1, u0, v1, r, g, b,
+        ];
+
+        self.vertices.extend_from_slice(&quad);
+    }
+
+    /// Draw everything queued since the last draw into the current gl viewport, which should
+    /// cover the scene. The camera offset is in scene pixels, zoom magnifies about the centre.
+    pub fn draw(
+        &mut self,
+        tileset: &Texture,
+        scene_size: (f32, f32),
+        camera_offset: (f32, f32),
+        zoom: f32,
+        screen_tint: (f32, f32, f32, f32),
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        tileset.bind();
+
+        self.shader.bind();
+        self.shader.set_uniform_2f("scene_size", scene_size);
+        self.shader.set_uniform_2f("camera_offset", camera_offset);
+        self.shader.set_uniform_1f("zoom", zoom);
+        self.shader.set_uniform_4f("screen_tint", screen_tint);
+        self.shader.set_uniform_1i("tileset", 0);
+
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
+                mem::transmute(&self.vertices[0]),
+                gl::STREAM_DRAW,
+            );
+
+            gl::DrawArrays(
+                gl::TRIANGLES,
+                0,
+                (self.vertices.len() / Self::VERTEX_SIZE) as GLsizei,
+            );
+
+            gl::BindVertexArray(0);
+            gl::Disable(gl::BLEND);
+        }
+
+        self.vertices.clear();
+    }
+}
+
+impl Drop for Particles {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
@@ -6,6 +6,7 @@ in vec2 tex_pos;
 uniform uvec2 viewport_size;
 uniform uvec2 tileset_size;
 uniform ivec2 tile_size;
+uniform ivec2 camera;
 uniform uvec2 framebuffer_size;
 uniform uint scale;
 
@@ -13,6 +14,14 @@ uniform sampler2D tileset;
 uniform sampler2D scene_tiles;
 uniform sampler2D front_scene_tiles_modifiers;
 uniform sampler2D back_scene_tiles_modifiers;
+uniform sampler2D scene_tiles_depths;
+uniform sampler2D tileset_animations;
+uniform sampler2D palette;
+uniform int palette_enabled;
+uniform int transparent;
+uniform int linear_filtering;
+uniform vec4 screen_tint;
+uniform float zoom;
 
 ivec2 calculate_flip(float flip_modifier, ivec2 pixel_pos, ivec2 pixel_range) {
     ivec2 out_pixel_pos = pixel_pos;
@@ -28,52 +37,122 @@ ivec2 calculate_flip(float flip_modifier, ivec2 pixel_pos, ivec2 pixel_range) {
     return out_pixel_pos;
 }
 
+vec4 apply_palette(vec4 tile_color) {
+    if (palette_enabled == 0 || tile_color.a == 0.0) {
+        return tile_color;
+    }
+
+    float luminance = dot(tile_color.rgb, vec3(0.299, 0.587, 0.114));
+    vec4 palette_color = texelFetch(palette, ivec2(int(round(luminance * 255.0)), 0), 0);
+
+    // unset palette entries leave the tile color unchanged
+    if (palette_color.a > 0.0) {
+        return vec4(palette_color.rgb, tile_color.a);
+    }
+
+    return tile_color;
+}
+
+// Sample the tileset between pixels, for tilesets using linear filtering.
+vec4 sample_linear(ivec2 tile_tex_offset, float flip_modifier, vec2 tile_pixel_pos) {
+    vec2 layer_pixel_pos = tile_pixel_pos;
+
+    if ((flip_modifier > 0.1 && flip_modifier < 0.3) || flip_modifier > 0.5) {
+        layer_pixel_pos.x = float(tile_size.x) - tile_pixel_pos.x;
+    }
+
+    if ((flip_modifier > 0.3 && flip_modifier < 0.5) || flip_modifier > 0.5) {
+        layer_pixel_pos.y = float(tile_size.y) - tile_pixel_pos.y;
+    }
+
+    // stay half a pixel inside the tile, so neighbouring tiles in the tileset don't bleed in
+    layer_pixel_pos = clamp(layer_pixel_pos, vec2(0.5), vec2(tile_size) - vec2(0.5));
+
+    vec2 tileset_pos = vec2(tile_tex_offset * tile_size) + layer_pixel_pos;
+    return texture(tileset, tileset_pos / vec2(textureSize(tileset, 0)));
+}
+
+// Sample one layer of a tile, returning the tileset color as loaded and the shaded color.
+vec4 sample_layer(ivec2 tile_tex_offset, vec4 tile_modifiers, vec2 tile_pixel_pos, out vec4 raw_color) {
+    // animated tiles store their animation index, the current frame is looked up by it
+    if (tile_tex_offset.y == -3) {
+        tile_tex_offset = ivec2(texelFetch(tileset_animations, ivec2(tile_tex_offset.x, 0), 0).xy);
+    }
+
+    vec4 modifier_color = vec4(tile_modifiers.xyz, 1.0);
+
+    // the built in "none" and "fill" tiles sit outside the tileset and are always fetched
+    if (linear_filtering != 0 && tile_tex_offset.x >= 0 && tile_tex_offset.y >= 0) {
+        raw_color = sample_linear(tile_tex_offset, tile_modifiers.w, tile_pixel_pos);
+    } else {
+        ivec2 layer_pixel_pos = calculate_flip(
+            tile_modifiers.w,
+            ivec2(tile_pixel_pos),
+            tile_size
+        );
+
+        raw_color = texelFetch(
+            tileset,
+            tile_tex_offset * ivec2(tile_size) + layer_pixel_pos,
+            0
+        );
+    }
+
+    vec4 tile_color = apply_palette(raw_color);
+
+    // fix for tiles that have transparent pixels, but still have the pixel data.
+    return vec4(tile_color.rgb * modifier_color.rgb * tile_color.a, 1.0);
+}
+
 void main()
 {
-    ivec2 pixel_pos = ivec2((ivec2(framebuffer_size) / float(scale)) * tex_pos);
-    ivec2 tile_pixel_pos = ivec2(mod(pixel_pos, tile_size));
-    ivec2 tile_pos = pixel_pos / tile_size;
+    // zoom magnifies about the centre of the viewport
+    vec2 zoomed_tex_pos = vec2(0.5) + (tex_pos - vec2(0.5)) / zoom;
+    vec2 scene_pixel_pos = (vec2(framebuffer_size) / float(scale)) * zoomed_tex_pos;
+    ivec2 pixel_pos = ivec2(scene_pixel_pos);
+    vec2 tile_pixel_pos = mod(scene_pixel_pos, vec2(tile_size));
+    ivec2 tile_pos = pixel_pos / tile_size + camera;
 
     ivec4 tile_tex_offset = ivec4(texelFetch(scene_tiles, tile_pos, 0));
 
     vec4 front_tile_modifiers = texelFetch(front_scene_tiles_modifiers, tile_pos, 0);
-    vec4 front_modifier_color = vec4(front_tile_modifiers.xyz, 1.0);
+    vec4 back_tile_modifiers = texelFetch(back_scene_tiles_modifiers, tile_pos, 0);
 
-    ivec2 front_tile_pixel_pos = calculate_flip(
-        front_tile_modifiers.w, 
+    // depths are stored as normalised bytes, the deeper layer is drawn underneath. The front
+    // layer wins when both layers have the same depth.
+    vec4 tile_depths = texelFetch(scene_tiles_depths, tile_pos, 0);
+    bool back_on_top = tile_depths.y > tile_depths.x;
+
+    vec4 front_raw_color;
+    vec4 front_color = sample_layer(
+        tile_tex_offset.xy,
+        front_tile_modifiers,
         tile_pixel_pos,
-        tile_size
+        front_raw_color
     );
 
-    vec4 front_tile_color = texelFetch(
-        tileset, 
-        tile_tex_offset.xy * ivec2(tile_size) + front_tile_pixel_pos, 
-        0
+    vec4 back_raw_color;
+    vec4 back_color = sample_layer(
+        tile_tex_offset.zw,
+        back_tile_modifiers,
+        tile_pixel_pos,
+        back_raw_color
     );
 
-    if (length(front_tile_color) > 0) {
-        // fix for tiles that have transparent pixels, but still have the pixel data.
-        FragColor = vec4(front_tile_color.rgb * front_modifier_color.rgb * front_tile_color.a,
-                1.0);
-    } else {
-
-        vec4 back_tile_modifiers = texelFetch(back_scene_tiles_modifiers, tile_pos, 0);
-        vec4 back_modifier_color = vec4(back_tile_modifiers.xyz, 1.0);
-        ivec2 back_tile_pixel_pos = calculate_flip(
-            back_tile_modifiers.w, 
-            tile_pixel_pos,
-            tile_size
-        );
+    // in a transparent window the desktop shows through tiles with nothing on either layer
+    if (transparent != 0 && length(front_raw_color) == 0.0 && length(back_raw_color) == 0.0) {
+        FragColor = vec4(0.0);
+        return;
+    }
 
-        vec4 back_tile_color = texelFetch(
-            tileset, 
-            tile_tex_offset.zw * ivec2(tile_size) + back_tile_pixel_pos, 
-            0
-        );
+    vec4 top_raw_color = back_on_top ? back_raw_color : front_raw_color;
 
-        // fix for tiles that have transparent pixels, but still have the pixel data.
-        FragColor = vec4(back_tile_color.rgb * back_modifier_color.rgb * back_tile_color.a,
-                1.0);
+    if (length(top_raw_color) > 0.0) {
+        FragColor = back_on_top ? back_color : front_color;
+    } else {
+        FragColor = back_on_top ? front_color : back_color;
     }
-}
 
+    // the tint alpha controls how strongly the tint color is multiplied over the scene
+    FragColor.rgb = mix(FragColor.rgb, FragColor.rgb * screen_tint.rgb, screen_tint.a);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 tex_pos;
+
+uniform sampler2D pixels;
+uniform float zoom;
+uniform vec4 screen_tint;
+
+void main()
+{
+    // zoom magnifies about the centre of the viewport, the same as the scene underneath
+    vec2 zoomed_tex_pos = vec2(0.5) + (tex_pos - vec2(0.5)) / zoom;
+
+    FragColor = texture(pixels, zoomed_tex_pos);
+
+    // matches the tint applied to the scene underneath
+    FragColor.rgb = mix(FragColor.rgb, FragColor.rgb * screen_tint.rgb, screen_tint.a);
+}
@@ -1,22 +1,47 @@
 use crate::engine;
-use crate::graphics::Viewport;
+use crate::graphics::{RenderRegion, Viewport};
+use crate::pyrite_log;
 use glutin::dpi::PhysicalSize;
 use glutin::event::{
-    ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
 };
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::platform::desktop::EventLoopExtDesktop;
 #[cfg(target_os = "linux")]
 use glutin::platform::unix::EventLoopExtUnix;
-use std::collections::{HashMap, VecDeque};
+use glutin::window::WindowId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+/// The window made from the game's configuration, other windows are numbered from 1.
+pub const MAIN_WINDOW: u32 = 0;
 
 pub struct Platform {
     pub events: Option<EventLoop<()>>,
+    // the engine's number for each window, events from unknown windows belong to the main window.
+    window_ids: HashMap<WindowId, u32>,
+    active_window: u32,
     button_states: HashMap<String, ButtonState>,
-    logical_mouse_position: (i32, i32),
+    buttons_pressed: HashSet<String>,
+    buttons_released: HashSet<String>,
+    transitions_cleared: bool,
+    // the last position of the mouse over each window, and the window it's over now.
+    logical_mouse_positions: HashMap<u32, (i32, i32)>,
+    mouse_window: Option<u32>,
+    modifiers: engine::Modifiers,
     smooth_mouse_scroll_accumulator: (f32, f32),
-    engine_event_queue: VecDeque<engine::Event>,
+    scroll_pixels_per_line: f32,
+    scroll_threshold: f32,
+    engine_event_queue: VecDeque<(engine::Event, Instant)>,
+    replaying: bool,
+    // closing the main window ends the game, the others are just closed.
     pub close_requested: bool,
+    pub closed_windows: Vec<u32>,
+    pub resized_windows: HashSet<u32>,
+    // focus and minimizing are only tracked for the main window.
+    pub window_focused: bool,
+    pub window_minimized: bool,
+    pub scale_factor_changes: HashMap<u32, f64>,
 }
 
 #[cfg(target_os = "linux")]
@@ -40,14 +65,65 @@ impl Platform {
 
         Self {
             events,
+            window_ids: HashMap::new(),
+            active_window: MAIN_WINDOW,
             button_states,
-            logical_mouse_position: (0, 0),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            transitions_cleared: false,
+            logical_mouse_positions: HashMap::new(),
+            mouse_window: None,
+            modifiers: engine::Modifiers::default(),
             smooth_mouse_scroll_accumulator: (0., 0.),
+            scroll_pixels_per_line: 10.,
+            scroll_threshold: 1.,
             engine_event_queue,
+            replaying: false,
             close_requested: false,
+            closed_windows: Vec::new(),
+            resized_windows: HashSet::new(),
+            window_focused: true,
+            window_minimized: false,
+            scale_factor_changes: HashMap::new(),
+        }
+    }
+
+    /// Route events from a window to the engine's number for it.
+    pub fn add_window(&mut self, window_id: WindowId, window: u32) {
+        self.window_ids.insert(window_id, window);
+    }
+
+    pub fn remove_window(&mut self, window: u32) {
+        self.window_ids.retain(|_, id| *id != window);
+        self.logical_mouse_positions.remove(&window);
+        self.resized_windows.remove(&window);
+        self.scale_factor_changes.remove(&window);
+
+        if self.mouse_window == Some(window) {
+            self.mouse_window = None;
         }
     }
 
+    /// The mouse functions report the position over the active window.
+    pub fn set_active_window(&mut self, window: u32) {
+        self.active_window = window;
+    }
+
+    /// Tune how trackpad (pixel) scrolling is converted to lines, and how many lines need to
+    /// build up before a scroll event is raised.
+    pub fn set_scroll_options(&mut self, pixels_per_line: f32, threshold: f32) {
+        if pixels_per_line > 0. {
+            self.scroll_pixels_per_line = pixels_per_line;
+        } else {
+            pyrite_log!(
+                "scroll_pixels_per_line must be above zero, keeping {}",
+                self.scroll_pixels_per_line
+            );
+        }
+
+        self.scroll_threshold = threshold.max(0.);
+    }
+
     pub fn service(&mut self) {
         // We need to remove the events loop from self as we pass self into a closure passed to
         // run_return and this causes borrow checker issues. This isn't optimal, but it's better
@@ -58,148 +134,35 @@ impl Platform {
 
         events.run_return(|event, _, control_flow| {
             *control_flow = ControlFlow::Exit;
+
+            // a replay stands in for the player, so their input can't change the button states.
+            if self.replaying && is_input_event(&event) {
+                return;
+            }
+
             match event {
-                Event::WindowEvent { event, .. } => match event {
-                    WindowEvent::Resized(physical_framebuffer_size) => unsafe {
-                        gl::Viewport(
-                            0,
-                            0,
-                            physical_framebuffer_size.width as i32,
-                            physical_framebuffer_size.height as i32,
-                        );
-                    },
-                    WindowEvent::CloseRequested => {
-                        self.close_requested = true;
-                    }
-                    WindowEvent::CursorMoved { position, .. } => {
-                        // possible bug here with hi-dpi screens
-                        self.logical_mouse_position = position.into();
-                    }
-                    WindowEvent::ReceivedCharacter(c) => {
-                        self.engine_event_queue.push_back(engine::Event::Text {
-                            text: c.to_string(),
-                        });
-                    }
-                    WindowEvent::MouseWheel { delta, .. } => {
-                        match delta {
-                            MouseScrollDelta::LineDelta(x, y) => {
-                                self.smooth_mouse_scroll_accumulator.0 += x as f32;
-                                self.smooth_mouse_scroll_accumulator.1 += y as f32;
-                            }
-                            MouseScrollDelta::PixelDelta(delta) => {
-                                self.smooth_mouse_scroll_accumulator.0 += (delta.x / 10.) as f32;
-                                self.smooth_mouse_scroll_accumulator.1 += (delta.y / 10.) as f32;
-                            }
-                        };
-
-                        let mut raise_event = false;
-
-                        let delta_x = if self.smooth_mouse_scroll_accumulator.0.abs() >= 1.0 {
-                            let delta = self.smooth_mouse_scroll_accumulator.0;
-                            self.smooth_mouse_scroll_accumulator.0 = 0.;
-                            raise_event = true;
-                            delta
-                        } else {
-                            0.
-                        };
-
-                        let delta_y = if self.smooth_mouse_scroll_accumulator.1.abs() >= 1.0 {
-                            let delta = self.smooth_mouse_scroll_accumulator.1;
-                            self.smooth_mouse_scroll_accumulator.1 = 0.;
-                            raise_event = true;
-                            delta
-                        } else {
-                            0.
-                        };
-
-                        if raise_event {
-                            let event = engine::Event::Scroll {
-                                x: delta_x as i32,
-                                y: delta_y as i32,
-                            };
-
-                            self.engine_event_queue.push_back(event);
-                        }
-                    }
-                    WindowEvent::MouseInput { button, state, .. } => {
-                        let (transition, state) = match state {
-                            ElementState::Pressed => ("PRESSED".to_owned(), ButtonState::Down),
-                            ElementState::Released => ("RELEASED".to_owned(), ButtonState::Up),
-                        };
-
-                        let (button_code, button_name) = match button {
-                            MouseButton::Left => {
-                                ("MOUSE_1".to_owned(), Some("MOUSE_LEFT".to_owned()))
-                            }
-                            MouseButton::Middle => {
-                                ("MOUSE_2".to_owned(), Some("MOUSE_MIDDLE".to_owned()))
-                            }
-                            MouseButton::Right => {
-                                ("MOUSE_3".to_owned(), Some("MOUSE_RIGHT".to_owned()))
-                            }
-                            MouseButton::Other(code) => (format!("MOUSE_{}", code), None),
-                        };
-
-                        self.button_states.insert(button_code.clone(), state);
-
-                        let button_code_event = engine::Event::Button {
-                            button: button_code,
-                            transition: transition.clone(),
-                        };
-
-                        self.engine_event_queue.push_back(button_code_event);
-
-                        if let Some(button_name) = button_name {
-                            self.button_states.insert(button_name.clone(), state);
-
-                            let button_name_event = engine::Event::Button {
-                                button: button_name,
-                                transition,
-                            };
-
-                            self.engine_event_queue.push_back(button_name_event);
-                        }
-                    }
-                    WindowEvent::KeyboardInput { input, .. } => {
-                        let (transition, state) = match input.state {
-                            ElementState::Pressed => ("PRESSED".to_owned(), ButtonState::Down),
-                            ElementState::Released => ("RELEASED".to_owned(), ButtonState::Up),
-                        };
-
-                        let scancode_str = format!("K{}", input.scancode);
-
-                        let last_state = self.button_states.insert(scancode_str.clone(), state);
-
-                        let scancode_event = engine::Event::Button {
-                            button: scancode_str,
-                            transition: transition.clone(),
-                        };
-
-                        if last_state.is_some() && last_state.unwrap() != state {
-                            self.engine_event_queue.push_back(scancode_event);
-                        } else if last_state.is_none() {
-                            self.engine_event_queue.push_back(scancode_event);
-                        }
-
-                        if let Some(virtual_key) = input.virtual_keycode {
-                            let key_str = virtual_key_to_string_identifier(virtual_key);
-
-                            let last_state = self.button_states.insert(key_str.clone(), state);
-
-                            let named_event = engine::Event::Button {
-                                button: key_str,
-                                transition: transition,
-                            };
-
-                            if last_state.is_some() && last_state.unwrap() != state {
-                                self.engine_event_queue.push_back(named_event);
-                            } else if last_state.is_none() {
-                                self.engine_event_queue.push_back(named_event);
-                            }
-                        }
-                    }
-                    _ => (),
-                },
+                Event::WindowEvent { event, window_id } => {
+                    let window = self
+                        .window_ids
+                        .get(&window_id)
+                        .cloned()
+                        .unwrap_or(MAIN_WINDOW);
+
+                    self.window_event(window, event);
+                }
+                // tracked separately so button events carry the modifiers held as they arrived,
+                // rather than games polling the modifier keys after the fact.
+                Event::DeviceEvent {
+                    event: DeviceEvent::ModifiersChanged(state),
+                    ..
+                } => {
+                    self.modifiers = engine::Modifiers {
+                        shift: state.shift(),
+                        ctrl: state.ctrl(),
+                        alt: state.alt(),
+                        logo: state.logo(),
+                    };
+                }
                 _ => (),
             }
         });
@@ -207,41 +170,381 @@ impl Platform {
         self.events = Some(events);
     }
 
+    /// Handle an event from one of the windows. Input is shared by every window, so buttons and
+    /// text are handled the same whichever window has focus.
+    fn window_event(&mut self, window: u32, event: WindowEvent) {
+        match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor_changes.insert(window, scale_factor);
+            }
+            WindowEvent::Resized(size) => {
+                // the graphics context works out where to draw from the new size.
+                self.resized_windows.insert(window);
+
+                // winit has no minimize event, minimized windows are resized to nothing.
+                let minimized = size.width == 0 || size.height == 0;
+                if window == MAIN_WINDOW {
+                    self.set_window_state(self.window_focused, minimized);
+                }
+            }
+            WindowEvent::Focused(focused) if window == MAIN_WINDOW => {
+                self.set_window_state(focused, self.window_minimized);
+            }
+            WindowEvent::CloseRequested if window == MAIN_WINDOW => {
+                // the game can still call cancel_close while handling the event.
+                self.close_requested = true;
+                self.queue_event(engine::Event::CloseRequested);
+            }
+            WindowEvent::CloseRequested => {
+                if !self.closed_windows.contains(&window) {
+                    self.closed_windows.push(window);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                // possible bug here with hi-dpi screens
+                self.logical_mouse_positions.insert(window, position.into());
+                self.mouse_window = Some(window);
+            }
+            WindowEvent::CursorEntered { .. } => self.mouse_window = Some(window),
+            WindowEvent::CursorLeft { .. } => {
+                if self.mouse_window == Some(window) {
+                    self.mouse_window = None;
+                }
+            }
+            // winit 0.20 has no composition events, committed IME text is delivered
+            // here one character at a time so it follows the same TEXT event path.
+            // Control characters (backspace, enter, etc.) are already reported as
+            // BUTTON events through KeyboardInput, so they're not repeated as text.
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                self.queue_event(engine::Event::Text {
+                    text: c.to_string(),
+                });
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        self.smooth_mouse_scroll_accumulator.0 += x as f32;
+                        self.smooth_mouse_scroll_accumulator.1 += y as f32;
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        self.smooth_mouse_scroll_accumulator.0 +=
+                            delta.x as f32 / self.scroll_pixels_per_line;
+                        self.smooth_mouse_scroll_accumulator.1 +=
+                            delta.y as f32 / self.scroll_pixels_per_line;
+                    }
+                };
+
+                // a threshold of 0 still needs some movement, wheels can report a zero
+                // delta on one axis.
+                let mut raise_event = false;
+
+                let delta_x = if self.smooth_mouse_scroll_accumulator.0 != 0.
+                    && self.smooth_mouse_scroll_accumulator.0.abs() >= self.scroll_threshold
+                {
+                    let delta = self.smooth_mouse_scroll_accumulator.0;
+                    self.smooth_mouse_scroll_accumulator.0 = 0.;
+                    raise_event = true;
+                    delta
+                } else {
+                    0.
+                };
+
+                let delta_y = if self.smooth_mouse_scroll_accumulator.1 != 0.
+                    && self.smooth_mouse_scroll_accumulator.1.abs() >= self.scroll_threshold
+                {
+                    let delta = self.smooth_mouse_scroll_accumulator.1;
+                    self.smooth_mouse_scroll_accumulator.1 = 0.;
+                    raise_event = true;
+                    delta
+                } else {
+                    0.
+                };
+
+                if raise_event {
+                    let event = engine::Event::Scroll {
+                        x: delta_x as i32,
+                        y: delta_y as i32,
+                    };
+
+                    self.queue_event(event);
+                }
+            }
+            WindowEvent::MouseInput { button, state, .. } => {
+                let (transition, state) = match state {
+                    ElementState::Pressed => ("PRESSED".to_owned(), ButtonState::Down),
+                    ElementState::Released => ("RELEASED".to_owned(), ButtonState::Up),
+                };
+
+                let (button_code, button_name) = match button {
+                    MouseButton::Left => ("MOUSE_1".to_owned(), Some("MOUSE_LEFT".to_owned())),
+                    MouseButton::Middle => ("MOUSE_2".to_owned(), Some("MOUSE_MIDDLE".to_owned())),
+                    MouseButton::Right => ("MOUSE_3".to_owned(), Some("MOUSE_RIGHT".to_owned())),
+                    MouseButton::Other(code) => (format!("MOUSE_{}", code), None),
+                };
+
+                self.set_button_state(button_code.clone(), state);
+
+                let button_code_event = engine::Event::Button {
+                    button: button_code,
+                    transition: transition.clone(),
+                    modifiers: self.modifiers,
+                };
+
+                self.queue_event(button_code_event);
+
+                if let Some(button_name) = button_name {
+                    self.set_button_state(button_name.clone(), state);
+
+                    let button_name_event = engine::Event::Button {
+                        button: button_name,
+                        transition,
+                        modifiers: self.modifiers,
+                    };
+
+                    self.queue_event(button_name_event);
+                }
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                let (transition, state) = match input.state {
+                    ElementState::Pressed => ("PRESSED".to_owned(), ButtonState::Down),
+                    ElementState::Released => ("RELEASED".to_owned(), ButtonState::Up),
+                };
+
+                let scancode_str = format!("K{}", input.scancode);
+
+                let last_state = self.set_button_state(scancode_str.clone(), state);
+
+                let scancode_event = engine::Event::Button {
+                    button: scancode_str,
+                    transition: transition.clone(),
+                    modifiers: self.modifiers,
+                };
+
+                if last_state.is_some() && last_state.unwrap() != state {
+                    self.queue_event(scancode_event);
+                } else if last_state.is_none() {
+                    self.queue_event(scancode_event);
+                }
+
+                // the key in the same place on a US layout, whatever the player's layout
+                if let Some(physical_str) = scancode_to_physical_identifier(input.scancode) {
+                    let last_state = self.set_button_state(physical_str.clone(), state);
+
+                    let physical_event = engine::Event::Button {
+                        button: physical_str,
+                        transition: transition.clone(),
+                        modifiers: self.modifiers,
+                    };
+
+                    if last_state != Some(state) {
+                        self.queue_event(physical_event);
+                    }
+                }
+
+                // keys without a name are still reported through their scancode
+                if let Some(key_str) = input
+                    .virtual_keycode
+                    .and_then(virtual_key_to_string_identifier)
+                {
+                    let last_state = self.set_button_state(key_str.clone(), state);
+
+                    let named_event = engine::Event::Button {
+                        button: key_str,
+                        transition: transition,
+                        modifiers: self.modifiers,
+                    };
+
+                    if last_state.is_some() && last_state.unwrap() != state {
+                        self.queue_event(named_event);
+                    } else if last_state.is_none() {
+                        self.queue_event(named_event);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
     pub fn mouse_position(
         &mut self,
-        window_size: PhysicalSize<u32>,
+        render_region: RenderRegion,
         viewport: Viewport,
     ) -> (i32, i32) {
+        let logical_mouse_position = self.mouse_position_pixels();
+
+        // remove any letterbox offset so the position is relative to the drawn scene.
+        let normalised_mouse_position = (
+            (logical_mouse_position.0 - render_region.x) as f32 / render_region.width.max(1) as f32,
+            (logical_mouse_position.1 - render_region.y) as f32
+                / render_region.height.max(1) as f32,
+        );
+
+        // undo the zoom about the centre of the viewport, the same way the shader applies it.
+        let zoom = viewport.get_zoom();
         let normalised_mouse_position = (
-            self.logical_mouse_position.0 as f32 / window_size.width as f32,
-            self.logical_mouse_position.1 as f32 / window_size.height as f32,
+            0.5 + (normalised_mouse_position.0 - 0.5) / zoom,
+            0.5 + (normalised_mouse_position.1 - 0.5) / zoom,
         );
 
         let (viewport_width, viewport_height) = viewport.get_dimensions_f32();
 
         (
-            (normalised_mouse_position.0 * viewport_width) as i32,
-            (normalised_mouse_position.1 * viewport_height) as i32,
+            (normalised_mouse_position.0 * viewport_width).floor() as i32,
+            (normalised_mouse_position.1 * viewport_height).floor() as i32,
+        )
+    }
+
+    /// False once the cursor leaves the active window, until it moves over the window again.
+    pub fn mouse_in_window(&self) -> bool {
+        self.mouse_window == Some(self.active_window)
+    }
+
+    /// Mouse position in physical window pixels, relative to the top left of the active window.
+    pub fn mouse_position_pixels(&self) -> (i32, i32) {
+        self.logical_mouse_positions
+            .get(&self.active_window)
+            .cloned()
+            .unwrap_or((0, 0))
+    }
+
+    /// Mouse position as a fraction of the window size, 0.0 to 1.0 while inside the window.
+    pub fn mouse_position_normalized(&self, window_size: PhysicalSize<u32>) -> (f32, f32) {
+        let logical_mouse_position = self.mouse_position_pixels();
+
+        (
+            logical_mouse_position.0 as f32 / window_size.width.max(1) as f32,
+            logical_mouse_position.1 as f32 / window_size.height.max(1) as f32,
         )
     }
 
     pub fn button_down(&mut self, button: String) -> bool {
-        let mut required_button_groups = button
-            .split('+')
-            .map(|required| required.split('|').map(|button| button.to_uppercase()));
-
-        required_button_groups.all(|mut button_group| {
-            button_group.any(|button| {
-                self.button_states
-                    .get(&button)
-                    .map_or(false, |state| *state == ButtonState::Down)
+        button_groups(&button)
+            .iter()
+            .all(|button_group| group_any(button_group, |button| self.is_down(button)))
+    }
+
+    fn is_down(&self, button: &str) -> bool {
+        self.button_states
+            .get(button)
+            .map_or(false, |state| *state == ButtonState::Down)
+    }
+
+    pub fn pressed_buttons(&self) -> Vec<String> {
+        self.button_states
+            .iter()
+            .filter(|(_, state)| **state == ButtonState::Down)
+            .map(|(button, _)| button.clone())
+            .collect()
+    }
+
+    /// A combination is pressed on the frame its last button goes down while the others are held.
+    pub fn button_pressed(&self, button: String) -> bool {
+        self.combination_transitioned(&button, &self.buttons_pressed)
+    }
+
+    /// A combination is released on the frame one of its buttons goes up while the others are
+    /// still held.
+    pub fn button_released(&self, button: String) -> bool {
+        self.combination_transitioned(&button, &self.buttons_released)
+    }
+
+    /// True if a group of the combination made the transition this frame, and every other group is
+    /// held or made the transition too. For a single button that's just the transition.
+    fn combination_transitioned(&self, button: &str, transitions: &HashSet<String>) -> bool {
+        let button_groups = button_groups(button);
+        let transitioned = |button: &str| transitions.contains(button);
+
+        button_groups.iter().all(|button_group| {
+            group_any(button_group, |button| {
+                self.is_down(button) || transitioned(button)
             })
-        })
+        }) && button_groups
+            .iter()
+            .any(|button_group| group_any(button_group, transitioned))
+    }
+
+    /// Forget which buttons transitioned, should be called before servicing a new frame of events.
+    ///
+    /// Only the first call between polls clears anything, input sampled after the STEP event
+    /// belongs to the next frame and its transitions must survive until that frame is polled.
+    pub fn clear_button_transitions(&mut self) {
+        if self.transitions_cleared {
+            return;
+        }
+
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.transitions_cleared = true;
+    }
+
+    /// Record the new state of a button, returning the previous state.
+    ///
+    /// Transitions are also recorded so they can be queried until the next frame.
+    fn set_button_state(&mut self, button: String, state: ButtonState) -> Option<ButtonState> {
+        let last_state = self.button_states.insert(button.clone(), state);
+
+        if last_state != Some(state) {
+            match state {
+                ButtonState::Down => self.buttons_pressed.insert(button),
+                ButtonState::Up => self.buttons_released.insert(button),
+            };
+        }
+
+        last_state
+    }
+
+    /// Ignore the player's input while a replay supplies it instead.
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+
+    /// Apply a recorded event to the input state, the same way the live event would have.
+    pub fn replay_event(&mut self, event: &engine::Event) {
+        if let engine::Event::Button {
+            button, transition, ..
+        } = event
+        {
+            let state = if transition == "PRESSED" {
+                ButtonState::Down
+            } else {
+                ButtonState::Up
+            };
+
+            self.set_button_state(button.clone(), state);
+        }
+    }
+
+    pub fn replay_mouse(&mut self, position: (i32, i32), in_window: bool) {
+        self.logical_mouse_positions
+            .insert(self.active_window, position);
+
+        if in_window {
+            self.mouse_window = Some(self.active_window);
+        } else if self.mouse_in_window() {
+            self.mouse_window = None;
+        }
     }
 
-    pub fn poll_events(&mut self) -> Vec<engine::Event> {
+    /// Queued events along with the time they arrived.
+    pub fn poll_events(&mut self) -> Vec<(engine::Event, Instant)> {
+        self.transitions_cleared = false;
         self.engine_event_queue.drain(..).collect()
     }
+
+    /// Raise a FOCUS event when the window gains or loses focus, or is minimized or restored.
+    fn set_window_state(&mut self, focused: bool, minimized: bool) {
+        if (focused, minimized) == (self.window_focused, self.window_minimized) {
+            return;
+        }
+
+        self.window_focused = focused;
+        self.window_minimized = minimized;
+        self.queue_event(engine::Event::Focus { focused, minimized });
+    }
+
+    fn queue_event(&mut self, event: engine::Event) {
+        self.engine_event_queue.push_back((event, Instant::now()));
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -250,8 +553,188 @@ enum ButtonState {
     Up,
 }
 
-fn virtual_key_to_string_identifier(virtual_key: VirtualKeyCode) -> String {
-    match virtual_key {
+/// Events from the player, rather than the window or the operating system.
+fn is_input_event(event: &Event<()>) -> bool {
+    match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::KeyboardInput { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::CursorEntered { .. }
+            | WindowEvent::CursorLeft { .. } => true,
+            _ => false,
+        },
+        Event::DeviceEvent { .. } => true,
+        _ => false,
+    }
+}
+
+/// Split a button combination such as "CTRL+S|DOWN" into groups that must all match, any button
+/// of a group can match it.
+fn button_groups(button: &str) -> Vec<Vec<String>> {
+    button
+        .split('+')
+        .map(|required| {
+            required
+                .split('|')
+                .map(|button| button.to_uppercase())
+                .collect()
+        })
+        .collect()
+}
+
+/// True if any button of the group passes the test, modifier aliases pass if either side does.
+fn group_any(button_group: &[String], test: impl Fn(&str) -> bool) -> bool {
+    button_group
+        .iter()
+        .any(|button| match modifier_alias_buttons(button) {
+            Some(sided_buttons) => sided_buttons.iter().any(|button| test(button)),
+            None => test(button),
+        })
+}
+
+/// Side agnostic modifier names, which match either the left or right key.
+fn modifier_alias_buttons(button: &str) -> Option<[&'static str; 2]> {
+    match button {
+        "CTRL" | "CONTROL" => Some(["LEFT_CONTROL", "RIGHT_CONTROL"]),
+        "SHIFT" => Some(["LEFT_SHIFT", "RIGHT_SHIFT"]),
+        "ALT" => Some(["LEFT_ALT", "RIGHT_ALT"]),
+        "SUPER" => Some(["LEFT_SUPER", "RIGHT_SUPER"]),
+        _ => None,
+    }
+}
+
+/// Name a key by where it is on the keyboard rather than the symbol printed on it, using the
+/// symbol a US layout has there. Only the main block of keys is named, as the rest don't move
+/// between layouts.
+///
+/// Scancodes for the main block match on Windows and Linux, macOS numbers its keys differently.
+#[cfg(not(target_os = "macos"))]
+fn scancode_to_physical_identifier(scancode: u32) -> Option<String> {
+    let identifier = match scancode {
+        1 => "ESCAPE",
+        2 => "NUMBER1",
+        3 => "NUMBER2",
+        4 => "NUMBER3",
+        5 => "NUMBER4",
+        6 => "NUMBER5",
+        7 => "NUMBER6",
+        8 => "NUMBER7",
+        9 => "NUMBER8",
+        10 => "NUMBER9",
+        11 => "NUMBER0",
+        12 => "MINUS",
+        13 => "EQUALS",
+        14 => "BACK",
+        15 => "TAB",
+        16 => "Q",
+        17 => "W",
+        18 => "E",
+        19 => "R",
+        20 => "T",
+        21 => "Y",
+        22 => "U",
+        23 => "I",
+        24 => "O",
+        25 => "P",
+        26 => "LEFT_BRACKET",
+        27 => "RIGHT_BRACKET",
+        28 => "RETURN",
+        30 => "A",
+        31 => "S",
+        32 => "D",
+        33 => "F",
+        34 => "G",
+        35 => "H",
+        36 => "J",
+        37 => "K",
+        38 => "L",
+        39 => "SEMICOLON",
+        40 => "APOSTROPHE",
+        41 => "GRAVE",
+        43 => "BACKSLASH",
+        44 => "Z",
+        45 => "X",
+        46 => "C",
+        47 => "V",
+        48 => "B",
+        49 => "N",
+        50 => "M",
+        51 => "COMMA",
+        52 => "PERIOD",
+        53 => "SLASH",
+        57 => "SPACE",
+        _ => return None,
+    };
+
+    Some(format!("PHYSICAL_{}", identifier))
+}
+
+#[cfg(target_os = "macos")]
+fn scancode_to_physical_identifier(scancode: u32) -> Option<String> {
+    let identifier = match scancode {
+        0 => "A",
+        1 => "S",
+        2 => "D",
+        3 => "F",
+        4 => "H",
+        5 => "G",
+        6 => "Z",
+        7 => "X",
+        8 => "C",
+        9 => "V",
+        11 => "B",
+        12 => "Q",
+        13 => "W",
+        14 => "E",
+        15 => "R",
+        16 => "Y",
+        17 => "T",
+        18 => "NUMBER1",
+        19 => "NUMBER2",
+        20 => "NUMBER3",
+        21 => "NUMBER4",
+        22 => "NUMBER6",
+        23 => "NUMBER5",
+        24 => "EQUALS",
+        25 => "NUMBER9",
+        26 => "NUMBER7",
+        27 => "MINUS",
+        28 => "NUMBER8",
+        29 => "NUMBER0",
+        30 => "RIGHT_BRACKET",
+        31 => "O",
+        32 => "U",
+        33 => "LEFT_BRACKET",
+        34 => "I",
+        35 => "P",
+        36 => "RETURN",
+        37 => "L",
+        38 => "J",
+        39 => "APOSTROPHE",
+        40 => "K",
+        41 => "SEMICOLON",
+        42 => "BACKSLASH",
+        43 => "COMMA",
+        44 => "SLASH",
+        45 => "N",
+        46 => "M",
+        47 => "PERIOD",
+        48 => "TAB",
+        49 => "SPACE",
+        50 => "GRAVE",
+        51 => "BACK",
+        53 => "ESCAPE",
+        _ => return None,
+    };
+
+    Some(format!("PHYSICAL_{}", identifier))
+}
+
+fn virtual_key_to_string_identifier(virtual_key: VirtualKeyCode) -> Option<String> {
+    let identifier = match virtual_key {
         VirtualKeyCode::Key0 => "NUMBER0",
         VirtualKeyCode::Key1 => "NUMBER1",
         VirtualKeyCode::Key2 => "NUMBER2",
@@ -391,7 +874,26 @@ fn virtual_key_to_string_identifier(virtual_key: VirtualKeyCode) -> String {
         VirtualKeyCode::Underline => "UNDERLINE",
         VirtualKeyCode::VolumeUp => "VOLUME_UP",
         VirtualKeyCode::VolumeDown => "VOLUME_DOWN",
-        _ => "",
-    }
-    .to_owned()
+        VirtualKeyCode::Add => "ADD",
+        VirtualKeyCode::Apostrophe => "APOSTROPHE",
+        VirtualKeyCode::Apps => "APPS",
+        VirtualKeyCode::At => "AT",
+        VirtualKeyCode::Ax => "AX",
+        VirtualKeyCode::AbntC1 => "ABNT_C1",
+        VirtualKeyCode::AbntC2 => "ABNT_C2",
+        VirtualKeyCode::OEM102 => "OEM_102",
+        VirtualKeyCode::Sysrq => "SYSRQ",
+        VirtualKeyCode::Wake => "WAKE",
+        VirtualKeyCode::WebBack => "WEB_BACK",
+        VirtualKeyCode::WebFavorites => "WEB_FAVORITES",
+        VirtualKeyCode::WebForward => "WEB_FORWARD",
+        VirtualKeyCode::WebHome => "WEB_HOME",
+        VirtualKeyCode::WebRefresh => "WEB_REFRESH",
+        VirtualKeyCode::WebSearch => "WEB_SEARCH",
+        VirtualKeyCode::WebStop => "WEB_STOP",
+        VirtualKeyCode::Yen => "YEN",
+        _ => return None,
+    };
+
+    Some(identifier.to_owned())
 }
@@ -0,0 +1,462 @@
+use crate::engine::{Event, Modifiers};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+const HEADER: &str = "PYRITE_REPLAY\t1";
+
+/// Input recorded for a single frame of a replay.
+#[derive(Default)]
+pub struct FrameInput {
+    pub delta_time: Option<f64>,
+    pub mouse: Option<((i32, i32), bool)>,
+    pub events: Vec<(Event, f64)>,
+}
+
+pub enum Record {
+    DeltaTime(f64),
+    Mouse((i32, i32), bool),
+    Event(Event, f64),
+}
+
+/// Records the input of every frame to a file, or plays a recording back in place of the real
+/// input so a bug can be reproduced.
+///
+/// Recordings are text with one record per line. Each record is a list of tab separated fields,
+/// starting with the frame number and the record type. Network events aren't recorded, as socket
+/// data comes from outside the game, and neither are closed windows, which the player closes.
+pub enum Replay {
+    Recording {
+        file: BufWriter<File>,
+        last_mouse: Option<((i32, i32), bool)>,
+    },
+    Playing {
+        records: VecDeque<(u64, Record)>,
+    },
+}
+
+impl Replay {
+    pub fn record(path: &str) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+
+        Ok(Replay::Recording {
+            file,
+            last_mouse: None,
+        })
+    }
+
+    pub fn play(path: &str) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        if lines.next().transpose()?.as_ref().map(String::as_str) != Some(HEADER) {
+            return Err(invalid_data("not a pyrite replay".to_owned()));
+        }
+
+        let mut records = VecDeque::new();
+        for (line_index, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            // the header is the first line, records are numbered as they'd appear in an editor.
+            let record = parse_record(&line)
+                .ok_or_else(|| invalid_data(format!("line {} is malformed", line_index + 2)))?;
+            records.push_back(record);
+        }
+
+        Ok(Replay::Playing { records })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match self {
+            Replay::Playing { .. } => true,
+            Replay::Recording { .. } => false,
+        }
+    }
+
+    /// Every record up to and including the frame, or None once the recording has run out.
+    pub fn take_frame(&mut self, frame: u64) -> Option<FrameInput> {
+        let records = match self {
+            Replay::Playing { records } => records,
+            Replay::Recording { .. } => return None,
+        };
+
+        if records.is_empty() {
+            return None;
+        }
+
+        let mut input = FrameInput::default();
+        while records
+            .front()
+            .map_or(false, |(record_frame, _)| *record_frame <= frame)
+        {
+            match records.pop_front() {
+                Some((_, Record::DeltaTime(delta_time))) => input.delta_time = Some(delta_time),
+                Some((_, Record::Mouse(position, in_window))) => {
+                    input.mouse = Some((position, in_window))
+                }
+                Some((_, Record::Event(event, timestamp))) => input.events.push((event, timestamp)),
+                None => break,
+            }
+        }
+
+        Some(input)
+    }
+
+    /// The mouse is only written when it changes, rather than every frame.
+    pub fn record_input(
+        &mut self,
+        frame: u64,
+        mouse: ((i32, i32), bool),
+        events: &[(Event, f64)],
+    ) -> io::Result<()> {
+        let (file, last_mouse) = match self {
+            Replay::Recording { file, last_mouse } => (file, last_mouse),
+            Replay::Playing { .. } => return Ok(()),
+        };
+
+        if *last_mouse != Some(mouse) {
+            *last_mouse = Some(mouse);
+            let ((x, y), in_window) = mouse;
+            writeln!(file, "{}\tMOUSE\t{}\t{}\t{}", frame, x, y, flag(in_window))?;
+        }
+
+        for (event, timestamp) in events {
+            if let Some(fields) = event_fields(event) {
+                writeln!(
+                    file,
+                    "{}\t{}\t{}{}",
+                    frame,
+                    event.type_str(),
+                    timestamp,
+                    fields
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The time step is the last record of a frame, so the file is flushed afterwards to keep the
+    /// recording intact if the game crashes.
+    pub fn record_delta_time(&mut self, frame: u64, delta_time: f64) -> io::Result<()> {
+        if let Replay::Recording { file, .. } = self {
+            writeln!(file, "{}\tDELTA\t{}", frame, delta_time)?;
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn flag(value: bool) -> &'static str {
+    if value {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+/// The fields of an event after its timestamp, each with a leading tab, or None for events that
+/// aren't recorded.
+fn event_fields(event: &Event) -> Option<String> {
+    let fields = match event {
+        Event::Button {
+            button,
+            transition,
+            modifiers,
+        } => format!(
+            "\t{}\t{}\t{}\t{}\t{}\t{}",
+            button,
+            transition,
+            flag(modifiers.shift),
+            flag(modifiers.ctrl),
+            flag(modifiers.alt),
+            flag(modifiers.logo)
+        ),
+        Event::Scroll { x, y } => format!("\t{}\t{}", x, y),
+        // text is stored as code points, so tabs and newlines can't break the record.
+        Event::Text { text } => text.chars().map(|c| format!("\t{}", c as u32)).collect(),
+        Event::Focus { focused, minimized } => {
+            format!("\t{}\t{}", flag(*focused), flag(*minimized))
+        }
+        Event::CloseRequested => String::new(),
+        Event::Load
+        | Event::Step { .. }
+        | Event::Network { .. }
+        | Event::WindowClosed { .. }
+        | Event::Exit => return None,
+    };
+
+    Some(fields)
+}
+
+fn parse_record(line: &str) -> Option<(u64, Record)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let frame = fields.get(0)?.parse().ok()?;
+    let record_type = *fields.get(1)?;
+    let fields = &fields[2..];
+
+    let parse_flag = |index: usize| match fields.get(index) {
+        Some(&"1") => Some(true),
+        Some(&"0") => Some(false),
+        _ => None,
+    };
+
+    let record = match record_type {
+        "DELTA" => Record::DeltaTime(fields.get(0)?.parse().ok()?),
+        "MOUSE" => Record::Mouse(
+            (fields.get(0)?.parse().ok()?, fields.get(1)?.parse().ok()?),
+            parse_flag(2)?,
+        ),
+        _ => {
+            let timestamp = fields.get(0)?.parse().ok()?;
+
+            let event = match record_type {
+                "BUTTON" => Event::Button {
+                    button: fields.get(1)?.to_string(),
+                    transition: fields.get(2)?.to_string(),
+                    modifiers: Modifiers {
+                        shift: parse_flag(3)?,
+                        ctrl: parse_flag(4)?,
+                        alt: parse_flag(5)?,
+                        logo: parse_flag(6)?,
+                    },
+                },
+                "SCROLL" => Event::Scroll {
+                    x: fields.get(1)?.parse().ok()?,
+                    y: fields.get(2)?.parse().ok()?,
+                },
+                "TEXT" => Event::Text {
+                    text: fields[1..]
+                        .iter()
+                        .map(|code| code.parse().ok().and_then(std::char::from_u32))
+                        .collect::<Option<String>>()?,
+                },
+                "FOCUS" => Event::Focus {
+                    focused: parse_flag(1)?,
+                    minimized: parse_flag(2)?,
+                },
+                "CLOSE_REQUESTED" => Event::CloseRequested,
+                _ => return None,
+            };
+
+            Record::Event(event, timestamp)
+        }
+    };
+
+    Some((frame, record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A replay file in the temp directory that's removed when the test finishes.
+    struct TempReplay(PathBuf);
+
+    impl TempReplay {
+        fn new(name: &str) -> Self {
+            TempReplay(std::env::temp_dir().join(format!(
+                "pyrite_replay_{}_{}.txt",
+                std::process::id(),
+                name
+            )))
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempReplay {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn button(button: &str, transition: &str, modifiers: Modifiers) -> Event {
+        Event::Button {
+            button: button.to_owned(),
+            transition: transition.to_owned(),
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn every_record_type_round_trips() {
+        let file = TempReplay::new("round_trip");
+        let modifiers = Modifiers {
+            shift: true,
+            ctrl: false,
+            alt: true,
+            logo: false,
+        };
+        let events = vec![
+            (button("SPACE", "PRESSED", modifiers), 0.1 + 0.2),
+            (Event::Scroll { x: -2, y: 5 }, 1e-7),
+            (
+                Event::Text {
+                    text: "a\tb\nc\u{00E9}e\u{0301}\u{1F600}".to_owned(),
+                },
+                12345.678901234567,
+            ),
+            (
+                Event::Focus {
+                    focused: false,
+                    minimized: true,
+                },
+                2.5,
+            ),
+            (Event::CloseRequested, 3.0),
+        ];
+
+        {
+            let mut replay = Replay::record(file.path()).unwrap();
+            replay.record_input(0, ((3, -4), true), &events).unwrap();
+            replay.record_delta_time(0, 1. / 60.).unwrap();
+        }
+
+        let mut replay = Replay::play(file.path()).unwrap();
+        let frame = replay.take_frame(0).unwrap();
+        assert_eq!(frame.delta_time, Some(1. / 60.));
+        assert_eq!(frame.mouse, Some(((3, -4), true)));
+        assert_eq!(frame.events, events);
+        assert!(replay.take_frame(1).is_none());
+    }
+
+    #[test]
+    fn engine_only_events_are_not_recorded() {
+        let file = TempReplay::new("engine_only");
+        let events = vec![
+            (Event::Load, 0.),
+            (Event::Step { delta_time: 0.5 }, 0.),
+            (
+                Event::Network {
+                    handle: 1,
+                    data: vec![1, 2, 3],
+                },
+                0.,
+            ),
+            (Event::Exit, 0.),
+        ];
+
+        {
+            let mut replay = Replay::record(file.path()).unwrap();
+            replay.record_input(0, ((0, 0), false), &events).unwrap();
+            replay.record_delta_time(0, 0.5).unwrap();
+        }
+
+        let frame = Replay::play(file.path()).unwrap().take_frame(0).unwrap();
+        assert!(frame.events.is_empty());
+    }
+
+    #[test]
+    fn mouse_is_only_recorded_when_it_changes() {
+        let file = TempReplay::new("mouse");
+
+        {
+            let mut replay = Replay::record(file.path()).unwrap();
+            for frame in 0..3 {
+                replay.record_input(frame, ((1, 1), true), &[]).unwrap();
+                replay.record_delta_time(frame, 0.1).unwrap();
+            }
+            replay.record_input(3, ((2, 1), true), &[]).unwrap();
+        }
+
+        let contents = fs::read_to_string(file.path()).unwrap();
+        assert_eq!(contents.matches("\tMOUSE\t").count(), 2);
+
+        let mut replay = Replay::play(file.path()).unwrap();
+        assert_eq!(replay.take_frame(0).unwrap().mouse, Some(((1, 1), true)));
+        assert_eq!(replay.take_frame(1).unwrap().mouse, None);
+        assert_eq!(replay.take_frame(2).unwrap().mouse, None);
+        assert_eq!(replay.take_frame(3).unwrap().mouse, Some(((2, 1), true)));
+    }
+
+    #[test]
+    fn take_frame_groups_records_by_frame() {
+        let mut replay = Replay::Playing {
+            records: vec![
+                (0, Record::DeltaTime(0.1)),
+                (0, Record::Event(Event::CloseRequested, 0.05)),
+                (2, Record::Event(Event::Scroll { x: 1, y: 0 }, 0.2)),
+                (2, Record::DeltaTime(0.2)),
+                (4, Record::DeltaTime(0.3)),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let frame = replay.take_frame(0).unwrap();
+        assert_eq!(frame.delta_time, Some(0.1));
+        assert_eq!(frame.events, vec![(Event::CloseRequested, 0.05)]);
+
+        // frames without records are still part of the recording
+        let frame = replay.take_frame(1).unwrap();
+        assert_eq!(frame.delta_time, None);
+        assert!(frame.events.is_empty());
+
+        let frame = replay.take_frame(2).unwrap();
+        assert_eq!(frame.delta_time, Some(0.2));
+        assert_eq!(frame.events, vec![(Event::Scroll { x: 1, y: 0 }, 0.2)]);
+
+        // a skipped frame's records are taken with the next frame
+        let frame = replay.take_frame(5).unwrap();
+        assert_eq!(frame.delta_time, Some(0.3));
+
+        assert!(replay.take_frame(6).is_none());
+    }
+
+    #[test]
+    fn malformed_lines_report_their_line_number() {
+        let file = TempReplay::new("malformed");
+        fs::write(
+            file.path(),
+            format!("{}\n0\tDELTA\t0.1\n\n0\tSCROLL\t0.1\t1\n", HEADER),
+        )
+        .unwrap();
+
+        let error = Replay::play(file.path()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 4 is malformed");
+    }
+
+    #[test]
+    fn missing_header_is_rejected() {
+        let file = TempReplay::new("header");
+        fs::write(file.path(), "0\tDELTA\t0.1\n").unwrap();
+
+        let error = Replay::play(file.path()).err().unwrap();
+        assert_eq!(error.to_string(), "not a pyrite replay");
+    }
+
+    #[test]
+    fn malformed_records_are_rejected() {
+        let malformed = [
+            "",
+            "x\tDELTA\t0.1",
+            "0\tDELTA",
+            "0\tDELTA\tfast",
+            "0\tMOUSE\t1\t2\t2",
+            "0\tBUTTON\t0.1\tSPACE\tPRESSED\t0\t0\t0",
+            "0\tSCROLL\t0.1\t1",
+            "0\tTEXT\t0.1\t55296",
+            "0\tFOCUS\t0.1\ttrue\t0",
+            "0\tLOAD\t0.1",
+            "0\tUNKNOWN\t0.1",
+        ];
+
+        for line in &malformed {
+            assert!(parse_record(line).is_none(), "{:?} was parsed", line);
+        }
+    }
+}
@@ -4,9 +4,10 @@ use std::env;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-pub trait Provider {
+pub trait Provider: Send + Sync {
     fn read_to_string(&self, path: &str) -> Option<String>;
 
     fn read_to_bytes(&self, path: &str) -> Option<Vec<u8>>;
@@ -46,8 +47,13 @@ impl Provider for FilesystemProvider {
     }
 }
 
+/// Resources packaged onto the end of the executable. Only the index is kept in memory, each
+/// resource is read from the executable when it's asked for.
 pub struct PackagedProvider {
-    resource_index: HashMap<String, Vec<u8>>,
+    // background loads read from worker threads, so the shared file position needs a lock.
+    shared_binary: Mutex<File>,
+    // offset from the start of the executable and length of each resource.
+    resource_index: HashMap<String, (u64, u64)>,
 }
 
 impl Provider for PackagedProvider {
@@ -57,7 +63,24 @@ impl Provider for PackagedProvider {
     }
 
     fn read_to_bytes(&self, path: &str) -> Option<Vec<u8>> {
-        self.resource_index.get(path).cloned()
+        let (offset, length) = *self.resource_index.get(path)?;
+
+        let mut shared_binary = self.shared_binary.lock().ok()?;
+        shared_binary.seek(SeekFrom::Start(offset)).ok()?;
+
+        let mut resource_bytes = Vec::with_capacity(length as usize);
+        shared_binary
+            .by_ref()
+            .take(length)
+            .read_to_end(&mut resource_bytes)
+            .ok()?;
+
+        if resource_bytes.len() as u64 != length {
+            pyrite_log!("Packaged resource \"{}\" is truncated", path);
+            return None;
+        }
+
+        Some(resource_bytes)
     }
 
     fn exists(&self, path: &str) -> bool {
@@ -74,6 +97,11 @@ impl PackagedProvider {
         //     String::from("/home/jasper/projects/rust/pyrite/target/debug/builds/packaged-linux")
         //         .into();
 
+        Self::from_file(&package_path)
+    }
+
+    /// Index the resources packaged onto the end of a file.
+    fn from_file(package_path: &Path) -> Self {
         let mut resource_index = HashMap::new();
 
         let mut shared_binary =
@@ -131,19 +159,22 @@ impl PackagedProvider {
                 .expect("failed to read name length");
             let resource_length = u64::from_be_bytes(resource_length_bytes);
 
-            // read resource
-            let mut resource_bytes = Vec::new();
+            // skip over the resource, it's read when it's needed
+            let resource_offset = shared_binary
+                .seek(SeekFrom::Current(0))
+                .expect("failed to find resource offset");
             shared_binary
-                .by_ref()
-                .take(resource_length)
-                .read_to_end(&mut resource_bytes)
-                .expect("failed to read resource name");
+                .seek(SeekFrom::Current(resource_length as i64))
+                .expect("failed to seek past resource");
 
-            pyrite_log!("Indexed {} {}b", resource_name, resource_bytes.len());
-            resource_index.insert(resource_name, resource_bytes);
+            pyrite_log!("Indexed {} {}b", resource_name, resource_length);
+            resource_index.insert(resource_name, (resource_offset, resource_length));
         }
 
-        Self { resource_index }
+        Self {
+            shared_binary: Mutex::new(shared_binary),
+            resource_index,
+        }
     }
 
     pub fn create_packaged_data(root_path: PathBuf) -> Option<Vec<u8>> {
@@ -215,3 +246,106 @@ impl PackagedProvider {
         return Some(package_data);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory in the temp directory that's removed when the test finishes.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                env::temp_dir().join(format!("pyrite_resources_{}_{}", std::process::id(), name));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Index a package the way older versions did, reading every resource into memory up front.
+    fn eager_index(package: &[u8]) -> HashMap<String, Vec<u8>> {
+        let read_u32 = |at: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&package[at..at + 4]);
+            u32::from_be_bytes(bytes) as usize
+        };
+        let read_u64 = |at: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&package[at..at + 8]);
+            u64::from_be_bytes(bytes) as usize
+        };
+
+        let resources_offset = read_u64(package.len() - 8);
+        let resource_count = read_u32(package.len() - 12);
+
+        let mut resource_index = HashMap::new();
+        let mut at = package.len() - resources_offset;
+        for _ in 0..resource_count {
+            let name_length = read_u32(at);
+            at += 4;
+            let name = String::from_utf8(package[at..at + name_length].to_vec()).unwrap();
+            at += name_length;
+            let resource_length = read_u64(at);
+            at += 8;
+            resource_index.insert(name, package[at..at + resource_length].to_vec());
+            at += resource_length;
+        }
+
+        resource_index
+    }
+
+    #[test]
+    fn packaged_reads_match_eager_reads() {
+        let resources = TempDir::new("resources");
+        let resource_files: Vec<(&str, Vec<u8>)> = vec![
+            ("main.py", b"import pyrite\n".to_vec()),
+            ("tiles.png", vec![0x89, b'P', b'N', b'G', 0, 0xff, 0xfe]),
+            ("empty.txt", Vec::new()),
+            ("caf\u{00E9}.txt", "\u{00E9}\u{1F600}".as_bytes().to_vec()),
+        ];
+        for (name, data) in &resource_files {
+            fs::write(resources.0.join(name), data).unwrap();
+        }
+
+        let package = PackagedProvider::create_packaged_data(resources.0.clone()).unwrap();
+
+        // the package is appended to the executable, so it doesn't start at the beginning.
+        let executable = TempDir::new("executable");
+        let executable_path = executable.0.join("player");
+        let mut executable_data = b"not really an executable".to_vec();
+        executable_data.extend_from_slice(&package);
+        fs::write(&executable_path, &executable_data).unwrap();
+
+        let packaged = PackagedProvider::from_file(&executable_path);
+        let eager = eager_index(&package);
+
+        assert_eq!(eager.len(), resource_files.len());
+        for (name, data) in &resource_files {
+            assert_eq!(eager.get(*name), Some(data));
+            assert!(packaged.exists(name));
+            assert_eq!(packaged.read_to_bytes(name).as_ref(), eager.get(*name));
+            assert_eq!(
+                packaged.read_to_string(name),
+                String::from_utf8(eager[*name].clone()).ok()
+            );
+        }
+
+        // reads can happen in any order, each one seeks to its resource
+        assert_eq!(
+            packaged.read_to_bytes("main.py"),
+            Some(b"import pyrite\n".to_vec())
+        );
+
+        assert!(!packaged.exists("missing.txt"));
+        assert_eq!(packaged.read_to_bytes("missing.txt"), None);
+        assert_eq!(packaged.read_to_string("missing.txt"), None);
+        assert_eq!(packaged.read_to_string("tiles.png"), None);
+    }
+}
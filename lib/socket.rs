@@ -0,0 +1,461 @@
+use crate::loader::BackgroundLoads;
+use crate::pyrite_log;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+// Large enough for any UDP datagram.
+const RECEIVE_BUFFER_SIZE: usize = 65536;
+
+/// Non-blocking sockets so games can experiment with multiplayer without stalling the frame loop.
+///
+/// Connections are made on a background worker, and data that can't be sent straight away is
+/// kept and sent as the socket is polled each frame.
+pub struct Sockets {
+    next_handle: u32,
+    sockets: HashMap<u32, Socket>,
+    connections: BackgroundLoads<Connection>,
+}
+
+struct Socket {
+    state: State,
+    // data waiting to be sent, TCP sockets may have sent part of the front chunk already.
+    outgoing: VecDeque<Vec<u8>>,
+    // received data is raised as NETWORK events rather than read with receive.
+    raise_events: bool,
+}
+
+enum State {
+    // waiting on the background connection with this handle.
+    Connecting(u32),
+    Open(Connection),
+}
+
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    /// Resolve the address and connect, which can take seconds so it's run on a worker.
+    fn connect(address: &str, protocol: &str) -> io::Result<Self> {
+        let connection = match protocol {
+            "TCP" => {
+                let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "address didn't resolve")
+                })?;
+                let stream = TcpStream::connect_timeout(&address, Duration::from_secs(5))?;
+                stream.set_nodelay(true)?;
+                stream.set_nonblocking(true)?;
+                Connection::Tcp(stream)
+            }
+            _ => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket.connect(address)?;
+                socket.set_nonblocking(true)?;
+                Connection::Udp(socket)
+            }
+        };
+
+        Ok(connection)
+    }
+
+    /// Send as much of the outgoing data as the operating system will take right now.
+    fn flush(&mut self, outgoing: &mut VecDeque<Vec<u8>>) -> io::Result<()> {
+        while let Some(data) = outgoing.front_mut() {
+            let sent = match self {
+                Connection::Tcp(stream) => stream.write(data),
+                Connection::Udp(socket) => socket.send(data),
+            };
+
+            match sent {
+                Ok(0) if !data.is_empty() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "connection closed",
+                    ))
+                }
+                // a datagram is sent whole, whatever the length.
+                Ok(length) if length < data.len() && self.is_stream() => {
+                    data.drain(..length);
+                }
+                Ok(_) => {
+                    outgoing.pop_front();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_stream(&self) -> bool {
+        match self {
+            Connection::Tcp(_) => true,
+            Connection::Udp(_) => false,
+        }
+    }
+
+    /// Returns Ok(None) when there's nothing to read yet.
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = vec![0; RECEIVE_BUFFER_SIZE];
+
+        let received = match self {
+            Connection::Tcp(stream) => match stream.read(&mut buffer) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "connection closed",
+                    ))
+                }
+                received => received,
+            },
+            Connection::Udp(socket) => socket.recv(&mut buffer),
+        };
+
+        match received {
+            Ok(length) => {
+                buffer.truncate(length);
+                Ok(Some(buffer))
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Sockets {
+    pub fn new() -> Self {
+        Self {
+            next_handle: 0,
+            sockets: HashMap::new(),
+            connections: BackgroundLoads::new(),
+        }
+    }
+
+    /// Start connecting a socket to the address in the background, returning its handle or None
+    /// if the protocol isn't TCP or UDP.
+    pub fn open(&mut self, address: &str, protocol: &str, raise_events: bool) -> Option<u32> {
+        let protocol = protocol.to_uppercase();
+        if protocol != "TCP" && protocol != "UDP" {
+            pyrite_log!(
+                "Failed to open socket to {}: protocol must be TCP or UDP",
+                address
+            );
+            return None;
+        }
+
+        let address = address.to_string();
+        let connection =
+            self.connections
+                .spawn(move || match Connection::connect(&address, &protocol) {
+                    Ok(connection) => Some(connection),
+                    Err(e) => {
+                        pyrite_log!("Failed to open {} socket to {}: {}", protocol, address, e);
+                        None
+                    }
+                });
+
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        self.sockets.insert(
+            handle,
+            Socket {
+                state: State::Connecting(connection),
+                outgoing: VecDeque::new(),
+                raise_events,
+            },
+        );
+
+        Some(handle)
+    }
+
+    /// "CONNECTING" until the connection is made, then "OPEN" until the socket is closed or fails,
+    /// after which it's "CLOSED".
+    pub fn state(&mut self, handle: u32) -> &'static str {
+        self.finish_connecting(handle);
+
+        match self.sockets.get(&handle) {
+            Some(Socket {
+                state: State::Connecting(_),
+                ..
+            }) => "CONNECTING",
+            Some(_) => "OPEN",
+            None => "CLOSED",
+        }
+    }
+
+    /// Queue data to send, sending what the operating system will take straight away and the rest
+    /// as the socket is polled. Returns false if the socket is closed, a failed socket is closed.
+    pub fn send(&mut self, handle: u32, data: &[u8]) -> bool {
+        match self.sockets.get_mut(&handle) {
+            Some(socket) => socket.outgoing.push_back(data.to_vec()),
+            None => return false,
+        }
+
+        self.flush(handle)
+    }
+
+    /// Read whatever has arrived on a socket without waiting, a failed socket is closed.
+    ///
+    /// Sockets that raise events have their data read as they're polled, so this returns None.
+    pub fn receive(&mut self, handle: u32) -> Option<Vec<u8>> {
+        if self.sockets.get(&handle)?.raise_events {
+            return None;
+        }
+
+        self.read(handle)
+    }
+
+    fn read(&mut self, handle: u32) -> Option<Vec<u8>> {
+        self.finish_connecting(handle);
+
+        let result = match &mut self.sockets.get_mut(&handle)?.state {
+            State::Open(connection) => connection.receive(),
+            State::Connecting(_) => return None,
+        };
+
+        match result {
+            Ok(data) => data,
+            Err(e) => {
+                self.close_after_error(handle, e);
+                None
+            }
+        }
+    }
+
+    /// Finish connections, send waiting data and drain everything that has arrived on the
+    /// sockets that raise events. Called once a frame.
+    pub fn poll(&mut self) -> Vec<(u32, Vec<u8>)> {
+        let handles: Vec<u32> = self.sockets.keys().cloned().collect();
+        let mut received = Vec::new();
+
+        for handle in handles {
+            if !self.flush(handle) {
+                continue;
+            }
+
+            let raise_events = self
+                .sockets
+                .get(&handle)
+                .map_or(false, |socket| socket.raise_events);
+
+            if raise_events {
+                while let Some(data) = self.read(handle) {
+                    received.push((handle, data));
+                }
+            }
+        }
+
+        received
+    }
+
+    pub fn close(&mut self, handle: u32) {
+        if let Some(Socket {
+            state: State::Connecting(connection),
+            ..
+        }) = self.sockets.remove(&handle)
+        {
+            self.connections.cancel(connection);
+        }
+    }
+
+    /// Move a socket whose connection has been made in the background to open, closing it if the
+    /// connection failed.
+    fn finish_connecting(&mut self, handle: u32) {
+        let connection = match self.sockets.get(&handle) {
+            Some(Socket {
+                state: State::Connecting(connection),
+                ..
+            }) => *connection,
+            _ => return,
+        };
+
+        if !self.connections.ready(connection) {
+            return;
+        }
+
+        match self.connections.take(connection) {
+            Some(connection) => {
+                if let Some(socket) = self.sockets.get_mut(&handle) {
+                    socket.state = State::Open(connection);
+                }
+            }
+            // the reason was logged by the worker.
+            None => {
+                self.sockets.remove(&handle);
+            }
+        }
+    }
+
+    /// Send waiting data on an open socket, returns false if the socket is closed or failed.
+    fn flush(&mut self, handle: u32) -> bool {
+        self.finish_connecting(handle);
+
+        let result = match self.sockets.get_mut(&handle) {
+            Some(Socket {
+                state: State::Open(connection),
+                outgoing,
+                ..
+            }) => connection.flush(outgoing),
+            Some(_) => Ok(()),
+            None => return false,
+        };
+
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                self.close_after_error(handle, e);
+                false
+            }
+        }
+    }
+
+    fn close_after_error(&mut self, handle: u32, error: io::Error) {
+        pyrite_log!("Closing socket {}: {}", handle, error);
+        self.close(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Instant;
+
+    fn wait_until_open(sockets: &mut Sockets, handle: u32) {
+        let start = Instant::now();
+        while sockets.state(handle) == "CONNECTING" {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "socket never connected"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(sockets.state(handle), "OPEN");
+    }
+
+    fn listen() -> (TcpListener, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        (listener, address)
+    }
+
+    #[test]
+    fn unknown_protocols_are_refused() {
+        let mut sockets = Sockets::new();
+
+        assert_eq!(sockets.open("127.0.0.1:4000", "SCTP", true), None);
+    }
+
+    #[test]
+    fn data_sent_while_connecting_is_delivered() {
+        let (listener, address) = listen();
+        let mut sockets = Sockets::new();
+
+        let handle = sockets.open(&address, "tcp", false).unwrap();
+        assert!(sockets.send(handle, b"hello "));
+        assert!(sockets.send(handle, b"world"));
+
+        wait_until_open(&mut sockets, handle);
+        sockets.poll();
+
+        let (mut peer, _) = listener.accept().unwrap();
+        let mut received = [0; 11];
+        peer.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello world");
+    }
+
+    #[test]
+    fn sends_larger_than_the_os_buffer_are_kept_until_flushed() {
+        let (listener, address) = listen();
+        let mut sockets = Sockets::new();
+
+        let handle = sockets.open(&address, "TCP", false).unwrap();
+        wait_until_open(&mut sockets, handle);
+        let (mut peer, _) = listener.accept().unwrap();
+
+        // far more than the socket buffers hold while the peer isn't reading.
+        let data: Vec<u8> = (0..32 * 1024 * 1024).map(|i| i as u8).collect();
+        assert!(sockets.send(handle, &data));
+        assert!(!sockets.sockets[&handle].outgoing.is_empty());
+
+        let reader = thread::spawn(move || {
+            let mut received = vec![0; data.len()];
+            peer.read_exact(&mut received).unwrap();
+            received == data
+        });
+
+        let start = Instant::now();
+        while !sockets.sockets[&handle].outgoing.is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(10), "data never sent");
+            sockets.poll();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(reader.join().unwrap());
+        assert_eq!(sockets.state(handle), "OPEN");
+    }
+
+    #[test]
+    fn polling_sockets_keep_their_data_for_receive() {
+        let (listener, address) = listen();
+        let mut sockets = Sockets::new();
+
+        let polled = sockets.open(&address, "TCP", false).unwrap();
+        wait_until_open(&mut sockets, polled);
+        let (mut polled_peer, _) = listener.accept().unwrap();
+
+        let evented = sockets.open(&address, "TCP", true).unwrap();
+        wait_until_open(&mut sockets, evented);
+        let (mut evented_peer, _) = listener.accept().unwrap();
+
+        polled_peer.write_all(b"polled").unwrap();
+        evented_peer.write_all(b"evented").unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        assert_eq!(sockets.receive(evented), None);
+        assert_eq!(sockets.poll(), vec![(evented, b"evented".to_vec())]);
+        assert_eq!(sockets.receive(polled), Some(b"polled".to_vec()));
+        assert_eq!(sockets.receive(polled), None);
+    }
+
+    #[test]
+    fn failed_connections_close_the_socket() {
+        // nothing listens on the port once the listener is dropped.
+        let (listener, address) = listen();
+        drop(listener);
+        let mut sockets = Sockets::new();
+
+        let handle = sockets.open(&address, "TCP", true).unwrap();
+
+        let start = Instant::now();
+        while sockets.state(handle) == "CONNECTING" {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "connect never failed"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(sockets.state(handle), "CLOSED");
+        assert!(!sockets.send(handle, b"data"));
+    }
+
+    #[test]
+    fn closing_while_connecting_forgets_the_socket() {
+        let (_listener, address) = listen();
+        let mut sockets = Sockets::new();
+
+        let handle = sockets.open(&address, "TCP", true).unwrap();
+        sockets.close(handle);
+
+        assert_eq!(sockets.state(handle), "CLOSED");
+        assert_eq!(sockets.receive(handle), None);
+    }
+}
@@ -82,7 +82,9 @@ Commands:
     Create new project
     new <name>
 
-    Run the game in development mode
+    Run the game in development mode, set PYRITE_ENTRY to run a
+    file other than entry.py, set PYRITE_RECORD or PYRITE_REPLAY
+    to record input to a file or play it back
     run <name>
     
     Create game executables ready for distribution